@@ -1,15 +1,39 @@
-use std::{sync::Arc, time::Duration};
+use std::{path::Path, sync::Arc, time::Duration};
 
 use anyhow::{Context, Result};
-use auth_api::http::{start_server, Configuration};
-use auth_db::connect_database;
-use auth_domain_core::create_auth;
-use auth_play::{config::AuthPlayConfig, logging};
+use auth_api::http::{
+    start_server, AccessRule, Configuration, ForwardAuthConfiguration, GatewayConfiguration, GatewayRoute, IpNet, JwtConfiguration, KeyRing, ListenAddress,
+    ListenerConfiguration, RouterKind, SecurityHeadersConfiguration, SessionBindingConfiguration, SessionConfiguration, SessionEncryption, SessionStorage,
+    TlsConfiguration, Uri,
+};
+use auth_audit::{create_audit_sinks, start_dispatcher, Configuration as AuditConfiguration, FileSinkConfiguration, SyslogSinkConfiguration};
+use auth_db::{connect_database, Configuration as DatabaseConfiguration};
+use auth_domain_core::{
+    create_auth,
+    jobs::{start_cache_listener, start_purge_job, start_webhook_worker},
+    Configuration as DomainConfiguration,
+};
+use auth_play::{
+    config::{AuthPlayConfig, AuthPlayHttpConfig},
+    logging,
+};
 use tokio_graceful_shutdown::{SubsystemBuilder, Toplevel};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let config = AuthPlayConfig::load().context("Cannot load configuration")?;
+    let keys = load_keys(&config.http).context("Cannot load secret keys")?;
+    let session_encryption = match config.http.session.encryption.active_key {
+        // Ids of the keys come lowercased from the environment.
+        Some(ref active_key) => {
+            Some(SessionEncryption::new(&active_key.to_lowercase(), &config.http.session.encryption.keys).context("Cannot load session encryption keys")?)
+        }
+        None if !config.http.session.encryption.keys.is_empty() => anyhow::bail!("Session encryption keys are set without an active_key"),
+        None => None,
+    };
+    if config.http.session.storage == SessionStorage::Cookie && session_encryption.is_none() {
+        anyhow::bail!("Cookie sessions need session encryption, set session.encryption");
+    }
 
     logging::init_logging()?;
 
@@ -17,20 +41,239 @@ async fn main() -> Result<()> {
     let git_revision = env!("BUILD_GIT_HASH");
     tracing::info!("AuthPlay {}-{}", crate_version, git_revision);
 
-    let database = connect_database(&config.database.database_url).await.context("Couldn't connect to database")?;
-    let arch_service = Arc::new(create_auth(database).await.context("Couldn't create service")?);
+    let database_config = DatabaseConfiguration {
+        url: config.database.database_url,
+        max_connections: config.database.max_connections,
+        min_connections: config.database.min_connections,
+        acquire_timeout: Duration::from_secs(config.database.acquire_timeout_secs),
+        idle_timeout: Some(config.database.idle_timeout_secs).filter(|secs| *secs > 0).map(Duration::from_secs),
+        max_lifetime: Some(config.database.max_lifetime_secs).filter(|secs| *secs > 0).map(Duration::from_secs),
+        statement_timeout: config.database.statement_timeout_ms.map(Duration::from_millis),
+        transaction_retries: config.database.transaction_retries,
+        run_migrations: config.database.run_migrations,
+    };
+    let database = connect_database(&database_config).await.context("Couldn't connect to database")?;
+    let audit_config = AuditConfiguration {
+        buffer_size: config.audit.buffer_size,
+        file: config.audit.file.map(|file| FileSinkConfiguration {
+            path: file.path,
+            format: file.format,
+            max_bytes: file.max_bytes,
+            max_files: file.max_files,
+        }),
+        syslog: config.audit.syslog.map(|syslog| SyslogSinkConfiguration {
+            address: syslog.address,
+            format: syslog.format,
+            hostname: syslog.hostname.or_else(|| std::env::var("HOSTNAME").ok()).unwrap_or_default(),
+            app_name: syslog.app_name,
+        }),
+    };
+    let (audit_sender, audit_dispatcher) = create_audit_sinks(&audit_config).context("Couldn't create audit sinks")?;
+
+    let domain_config = DomainConfiguration {
+        deletion_grace_period: Duration::from_secs(config.account.deletion_grace_period_days * 24 * 60 * 60),
+        webhook_max_attempts: config.webhook.max_attempts,
+        webhook_retry_base: Duration::from_secs(config.webhook.retry_base_secs),
+        webhook_retry_max: Duration::from_secs(config.webhook.retry_max_secs),
+        webhook_timeout: Duration::from_secs(config.webhook.timeout_secs),
+        webhook_retention: Duration::from_secs(config.webhook.retention_days * 24 * 60 * 60),
+        cache_ttl: Duration::from_secs(config.cache.ttl_secs),
+        cache_capacity: config.cache.capacity,
+        cache_fallback_ttl: Duration::from_secs(config.cache.fallback_ttl_secs),
+    };
+    let arch_service = Arc::new(create_auth(domain_config, database, audit_sender).await.context("Couldn't create service")?);
+    let account_api = arch_service.account_api.clone();
+    let purge_interval = Duration::from_secs(config.account.purge_interval_secs);
+    let webhook_api = arch_service.webhook_api.clone();
+    let webhook_interval = Duration::from_secs(config.webhook.poll_interval_secs);
+    let cache_api = arch_service.cache_api.clone();
+    let cache_listen_retry = Duration::from_secs(config.cache.listen_retry_secs);
+
+    let mut listeners: Vec<ListenerConfiguration> = config
+        .http
+        .listeners
+        .into_iter()
+        .map(|(name, listener)| ListenerConfiguration {
+            name,
+            address: listener.address,
+            router: listener.router,
+            tls: listener.tls,
+            proxy_protocol: listener.proxy_protocol,
+        })
+        .collect();
+    if let (true, Some(port)) = (listeners.is_empty(), config.http.port) {
+        listeners.push(ListenerConfiguration {
+            name: "default".to_string(),
+            address: ListenAddress::Tcp(([0, 0, 0, 0], port).into()),
+            router: RouterKind::Public,
+            tls: true,
+            proxy_protocol: false,
+        });
+    }
+    let trusted_proxies = config
+        .http
+        .trusted_proxies
+        .iter()
+        .map(|proxy| parse_trusted_proxy(proxy).with_context(|| format!("Bad trusted proxy - {}", proxy)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let gateway_routes = config
+        .http
+        .gateway
+        .routes
+        .into_iter()
+        .map(|(name, route)| {
+            let upstream = parse_upstream(&route.upstream).with_context(|| format!("Bad upstream for gateway route {}", name))?;
+            Ok(GatewayRoute {
+                host: route.host,
+                path: route.path,
+                upstream,
+                strip_path: route.strip_path,
+                roles: parse_roles(&route.roles),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     let http_config = Configuration {
-        port: config.http.port,
-        secret_key: config.http.secret_key,
+        listeners,
+        keys,
+        session: SessionConfiguration {
+            storage: config.http.session.storage,
+            cookie_name: config.http.session.cookie_name,
+            cookie_domain: config.http.session.cookie_domain,
+            cookie_path: config.http.session.cookie_path,
+            same_site: config.http.session.same_site,
+            secure: config.http.session.secure,
+            inactivity_timeout: Duration::from_secs(config.http.session.inactivity_timeout_secs),
+            absolute_lifetime: Duration::from_secs(config.http.session.absolute_lifetime_secs),
+            remember_lifetime: Duration::from_secs(config.http.session.remember_lifetime_secs),
+            data_key: config.http.session.data_key,
+            binding: SessionBindingConfiguration {
+                enabled: config.http.session.binding.enabled,
+                ipv4_prefix: config.http.session.binding.ipv4_prefix,
+                ipv6_prefix: config.http.session.binding.ipv6_prefix,
+                user_agent: config.http.session.binding.user_agent,
+                device_hints: config.http.session.binding.device_hints,
+                policy: config.http.session.binding.policy,
+            },
+            encryption: session_encryption,
+            max_cookie_size: config.http.session.max_cookie_size,
+            revocation_refresh: Duration::from_secs(config.http.session.revocation_refresh_secs),
+            extension_interval: Duration::from_secs(config.http.session.extension_interval_secs),
+            write_capacity: config.http.session.write_capacity,
+        },
+        trusted_proxies,
+        allowed_origins: config.http.allowed_origins,
+        metrics_token: config.http.metrics_token.and_then(non_empty),
+        security_headers: SecurityHeadersConfiguration {
+            hsts: non_empty(config.http.security_headers.hsts),
+            content_type_options: config.http.security_headers.content_type_options,
+            referrer_policy: non_empty(config.http.security_headers.referrer_policy),
+            permissions_policy: non_empty(config.http.security_headers.permissions_policy),
+            frame_ancestors: non_empty(config.http.security_headers.frame_ancestors),
+            content_security_policy: config.http.security_headers.content_security_policy,
+        },
+        forward_auth: ForwardAuthConfiguration {
+            login_url: config.http.forward_auth.login_url,
+            rules: config
+                .http
+                .forward_auth
+                .rules
+                .into_values()
+                .map(|rule| AccessRule {
+                    host: rule.host,
+                    path: rule.path,
+                    public: rule.public,
+                    roles: parse_roles(&rule.roles),
+                })
+                .collect(),
+        },
+        gateway: GatewayConfiguration {
+            routes: gateway_routes,
+            jwt: config.http.gateway.jwt_secret.map(|secret| JwtConfiguration {
+                secret,
+                ttl: Duration::from_secs(config.http.gateway.jwt_ttl_secs),
+            }),
+            timeout: Duration::from_secs(config.http.gateway.timeout_secs),
+        },
+        tls: config.http.tls.map(|tls| TlsConfiguration {
+            cert_path: tls.cert_path,
+            key_path: tls.key_path,
+        }),
+        keep_alive: config.http.keep_alive,
+        keep_alive_interval: config.http.keep_alive_interval_secs.map(Duration::from_secs),
+        max_concurrent_streams: config.http.max_concurrent_streams,
+        header_read_timeout: Duration::from_secs(config.http.header_read_timeout_secs),
+        max_connections: config.http.max_connections,
+        drain_timeout: Duration::from_secs(config.http.drain_timeout_secs),
     };
+    // Leave the other subsystems time to stop after connections have drained.
+    let shutdown_timeout = http_config.drain_timeout + Duration::from_secs(5);
 
-    let server = Toplevel::new(|s| async move {
+    let server = Toplevel::new(move |s| async move {
         s.start(SubsystemBuilder::new("http_api", |h| start_server(http_config, arch_service, h)));
+        s.start(SubsystemBuilder::new("audit_dispatcher", |h| start_dispatcher(audit_dispatcher, h)));
+        s.start(SubsystemBuilder::new("account_purge", move |h| start_purge_job(account_api, purge_interval, h)));
+        s.start(SubsystemBuilder::new("webhook_worker", move |h| {
+            start_webhook_worker(webhook_api, webhook_interval, h)
+        }));
+        s.start(SubsystemBuilder::new("cache_listener", move |h| {
+            start_cache_listener(cache_api, cache_listen_retry, h)
+        }));
     })
     .catch_signals()
-    .handle_shutdown_requests(Duration::from_secs(5));
+    .handle_shutdown_requests(shutdown_timeout);
 
     server.await?;
     Ok(())
 }
+
+/// The signing key, and the previous keys still accepted, from the
+/// configuration and key files.
+fn load_keys(config: &AuthPlayHttpConfig) -> Result<KeyRing> {
+    let active = match (&config.secret_key, &config.secret_key_file) {
+        (Some(_), Some(_)) => anyhow::bail!("Set only one of secret_key and secret_key_file"),
+        (Some(key), None) => key.as_bytes().to_vec(),
+        (None, Some(path)) => read_key(path)?,
+        (None, None) => anyhow::bail!("Set secret_key or secret_key_file"),
+    };
+    let mut previous: Vec<Vec<u8>> = config.previous_secret_keys.iter().map(|key| key.as_bytes().to_vec()).collect();
+    for path in config.previous_secret_key_files.iter() {
+        previous.push(read_key(path)?);
+    }
+
+    Ok(KeyRing::new(&active, &previous)?)
+}
+
+fn read_key(path: &Path) -> Result<Vec<u8>> {
+    let key = std::fs::read(path).with_context(|| format!("Cannot read key file {}", path.display()))?;
+
+    Ok(key.trim_ascii().to_vec())
+}
+
+/// Accept a bare address as a single host range.
+fn parse_trusted_proxy(proxy: &str) -> Result<IpNet> {
+    let proxy = proxy.trim();
+    match proxy.parse::<std::net::IpAddr>() {
+        Ok(ip) => Ok(IpNet::from(ip)),
+        Err(_) => Ok(proxy.parse()?),
+    }
+}
+
+fn non_empty(value: String) -> Option<String> {
+    Some(value).filter(|value| !value.trim().is_empty())
+}
+
+fn parse_roles(roles: &str) -> Vec<String> {
+    roles.split(',').map(str::trim).filter(|role| !role.is_empty()).map(str::to_string).collect()
+}
+
+/// Upstreams are reached over plain HTTP on the internal network.
+fn parse_upstream(upstream: &str) -> Result<Uri> {
+    let uri: Uri = upstream.parse()?;
+    if uri.scheme_str() != Some("http") || uri.authority().is_none() {
+        anyhow::bail!("Expected http://host:port - {}", upstream);
+    }
+
+    Ok(uri)
+}
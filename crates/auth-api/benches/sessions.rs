@@ -0,0 +1,209 @@
+//! Throughput of signed in requests through the session layer, with and
+//! without the domain cache. Each request loads the session and its user the
+//! way any authenticated request does. Needs a migrated database with a user
+//! in it:
+//!
+//! ```sh
+//! AUTH_BENCH_DATABASE_URL=postgres://postgres@127.0.0.1/authplay AUTH_BENCH_EMAIL=admin@example.com AUTH_BENCH_PASSWORD=password \
+//!     cargo bench -p auth-api --bench sessions
+//! ```
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use auth_api::http::{
+    start_server, Configuration as HttpConfiguration, ForwardAuthConfiguration, GatewayConfiguration, KeyRing, ListenAddress, ListenerConfiguration,
+    RouterKind, SecurityHeadersConfiguration, SessionConfiguration,
+};
+use auth_audit::{create_audit_sinks, Configuration as AuditConfiguration};
+use auth_db::{connect_database, Configuration as DatabaseConfiguration};
+use auth_domain_core::{create_auth, Configuration};
+use axum::{
+    body::Body,
+    http::{header, Request, Response, StatusCode},
+};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle, Toplevel};
+
+const TASKS: usize = 32;
+const DURATION: Duration = Duration::from_secs(10);
+
+#[tokio::main]
+async fn main() {
+    let database_url = std::env::var("AUTH_BENCH_DATABASE_URL").unwrap_or_else(|_| "postgres://postgres@127.0.0.1/authplay".to_string());
+    let email = std::env::var("AUTH_BENCH_EMAIL").expect("Set AUTH_BENCH_EMAIL to a user's email");
+    let password = std::env::var("AUTH_BENCH_PASSWORD").expect("Set AUTH_BENCH_PASSWORD to the user's password");
+    let repository_adapters = connect_database(&DatabaseConfiguration::new(database_url))
+        .await
+        .expect("Couldn't connect to the database");
+    let client: Client<HttpConnector, Body> = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+
+    for capacity in [10_000, 0] {
+        let (audit_sender, _audit_dispatcher) = create_audit_sinks(&AuditConfiguration {
+            buffer_size: 1024,
+            file: None,
+            syslog: None,
+        })
+        .expect("Couldn't create audit sinks");
+        let config = Configuration {
+            deletion_grace_period: Duration::from_secs(60),
+            webhook_max_attempts: 1,
+            webhook_retry_base: Duration::from_secs(1),
+            webhook_retry_max: Duration::from_secs(1),
+            webhook_timeout: Duration::from_secs(1),
+            webhook_retention: Duration::from_secs(60),
+            cache_ttl: Duration::from_secs(30),
+            cache_capacity: capacity,
+            cache_fallback_ttl: Duration::from_secs(2),
+        };
+        let auth_domain_api = create_auth(config, repository_adapters.clone(), audit_sender)
+            .await
+            .expect("Couldn't create the domain");
+
+        let address = free_address();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let http_config = http_configuration(address);
+        let server = tokio::spawn(
+            Toplevel::new(move |s| async move {
+                s.start(SubsystemBuilder::new("http_api", |h| start_server(http_config, Arc::new(auth_domain_api), h)));
+                s.start(SubsystemBuilder::new("bench", |h: SubsystemHandle| async move {
+                    let _ = stopped.await;
+                    h.request_shutdown();
+                    Ok::<(), std::convert::Infallible>(())
+                }));
+            })
+            .handle_shutdown_requests(Duration::from_secs(5)),
+        );
+
+        let base = format!("http://{}", address);
+        let cookie = sign_in(&client, &base, &email, &password).await;
+        let requests = Arc::new(AtomicU64::new(0));
+        let started = Instant::now();
+        let tasks: Vec<_> = (0..TASKS)
+            .map(|_| {
+                let client = client.clone();
+                let requests = requests.clone();
+                let url = format!("{}/auth/session", base);
+                let cookie = cookie.clone();
+                tokio::spawn(async move {
+                    while started.elapsed() < DURATION {
+                        let request = Request::get(&url).header(header::COOKIE, &cookie).body(Body::empty()).unwrap();
+                        let response = client.request(request).await.expect("Request failed");
+                        assert_eq!(response.status(), StatusCode::OK);
+                        axum::body::to_bytes(Body::new(response.into_body()), usize::MAX)
+                            .await
+                            .expect("Couldn't read the response");
+                        requests.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.expect("Request task failed");
+        }
+
+        let requests = requests.load(Ordering::Relaxed);
+        println!(
+            "capacity {:>6}: {:>8} requests, {:>10.0} requests/s",
+            capacity,
+            requests,
+            requests as f64 / started.elapsed().as_secs_f64()
+        );
+        let _ = stop.send(());
+        let _ = server.await;
+    }
+}
+
+fn free_address() -> SocketAddr {
+    let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).expect("Couldn't find a free port");
+
+    listener.local_addr().unwrap()
+}
+
+fn http_configuration(address: SocketAddr) -> HttpConfiguration {
+    HttpConfiguration {
+        listeners: vec![ListenerConfiguration {
+            name: "bench".to_string(),
+            address: ListenAddress::Tcp(address),
+            router: RouterKind::Public,
+            tls: false,
+            proxy_protocol: false,
+        }],
+        keys: KeyRing::new(auth_utils::hmac::random_token(32).as_bytes(), &[]).unwrap(),
+        session: SessionConfiguration::default(),
+        trusted_proxies: vec![],
+        allowed_origins: vec![],
+        metrics_token: None,
+        forward_auth: ForwardAuthConfiguration {
+            login_url: "/app".to_string(),
+            rules: vec![],
+        },
+        gateway: GatewayConfiguration {
+            routes: vec![],
+            jwt: None,
+            timeout: Duration::from_secs(30),
+        },
+        security_headers: SecurityHeadersConfiguration::default(),
+        tls: None,
+        keep_alive: true,
+        keep_alive_interval: None,
+        max_concurrent_streams: 256,
+        header_read_timeout: Duration::from_secs(10),
+        max_connections: 1024,
+        drain_timeout: Duration::from_secs(1),
+    }
+}
+
+/// Sign in once, waiting for the server to come up, and return the session
+/// cookie.
+async fn sign_in(client: &Client<HttpConnector, Body>, base: &str, email: &str, password: &str) -> String {
+    let session_url = format!("{}/auth/session", base);
+    let mut response = None;
+    for _ in 0..50 {
+        match client.get(session_url.parse().unwrap()).await {
+            Ok(ok) => {
+                response = Some(ok);
+                break;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    let response = response.expect("The server did not come up");
+    let anonymous = session_cookie(&response);
+    let body = axum::body::to_bytes(Body::new(response.into_body()), usize::MAX).await.unwrap();
+    let csrf_token = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["csrf_token"]
+        .as_str()
+        .expect("No CSRF token")
+        .to_string();
+
+    let request = Request::post(format!("{}/auth/login", base))
+        .header(header::COOKIE, anonymous)
+        .header(header::ORIGIN, base)
+        .header("x-csrf-token", csrf_token)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::json!({ "email": email, "password": password }).to_string()))
+        .unwrap();
+    let response = client.request(request).await.expect("Couldn't sign in");
+    assert!(
+        response.status().is_redirection() || response.status().is_success(),
+        "Couldn't sign in: {}",
+        response.status()
+    );
+
+    session_cookie(&response)
+}
+
+fn session_cookie<B>(response: &Response<B>) -> String {
+    let cookie = response.headers().get(header::SET_COOKIE).expect("No session cookie").to_str().unwrap();
+
+    cookie.split(';').next().unwrap().to_string()
+}
@@ -1,7 +1,13 @@
 #[derive(Debug, thiserror::Error)]
 pub enum ApiError {
-    #[error("Bad port - {}", _0)]
-    BadPort(u16),
+    #[error("Couldn't bind {} - {}", _0, _1)]
+    Bind(String, std::io::Error),
+
+    #[error("No listeners configured")]
+    NoListeners,
+
+    #[error("More than one listener on {}", _0)]
+    DuplicateListener(String),
 
     #[error("Encode/decode error")]
     EncodeDecodeError,
@@ -11,4 +17,22 @@ pub enum ApiError {
 
     #[error("Not found - {}", _0)]
     UserNotFound(String),
+
+    #[error("Not signed in")]
+    Unauthorized,
+
+    #[error("Forbidden")]
+    Forbidden,
+
+    #[error("CSRF check failed - {}", _0)]
+    Csrf(&'static str),
+
+    #[error("Bad request - {}", _0)]
+    BadRequest(String),
+
+    #[error("TLS error - {}", _0)]
+    Tls(String),
+
+    #[error("Bad secret key - {}", _0)]
+    SecretKey(String),
 }
@@ -1,57 +1,187 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{sync::Arc, time::Duration};
 
 use auth_domain_api::AuthDomainApi;
 use axum::response::{IntoResponse, Response};
-use hyper::StatusCode;
-use tokio::net::TcpListener;
+use hyper::{header, StatusCode};
 use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle};
 
+use listener::BoundListener;
+use session::{cookie::Revocations, writes::SessionWrites};
+
 use crate::ApiError;
 
 pub(crate) mod auth;
+pub(crate) mod context;
+pub(crate) mod csrf;
+pub(crate) mod forward_auth;
+pub(crate) mod gateway;
 pub(crate) mod handlers;
 pub(crate) mod health;
+pub(crate) mod keys;
+pub(crate) mod listener;
+pub(crate) mod proxy;
+pub(crate) mod security_headers;
+pub(crate) mod server;
 pub(crate) mod session;
+#[cfg(test)]
+pub(crate) mod testing;
+pub(crate) mod tls;
 pub(crate) mod v1;
 
+pub use forward_auth::{AccessRule, ForwardAuthConfiguration};
+pub use gateway::{GatewayConfiguration, GatewayRoute, JwtConfiguration};
+pub use hyper::Uri;
+pub use ipnet::IpNet;
+pub use keys::KeyRing;
+pub use listener::{ListenAddress, ListenerConfiguration, RouterKind};
+pub use security_headers::SecurityHeadersConfiguration;
+pub use session::{DriftPolicy, SameSite, SessionBindingConfiguration, SessionConfiguration, SessionEncryption, SessionStorage};
+pub use tls::TlsConfiguration;
+
 #[derive(Debug, Clone)]
 pub struct Configuration {
-    pub port: u16,
-    pub secret_key: String,
+    pub listeners: Vec<ListenerConfiguration>,
+    /// Keys that sign the session cookie.
+    pub keys: KeyRing,
+    /// Session cookie and lifetimes.
+    pub session: SessionConfiguration,
+    /// Proxies whose forwarding headers and PROXY protocol headers are believed.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Origins besides the server's own allowed to make state-changing
+    /// requests, e.g. `https://app.example.com`.
+    pub allowed_origins: Vec<String>,
+    /// Bearer token that reads `/health/metrics`, which is off without one.
+    pub metrics_token: Option<String>,
+    /// Access rules for the `/auth/verify` forward-auth endpoint.
+    pub forward_auth: ForwardAuthConfiguration,
+    /// Upstreams served by gateway listeners.
+    pub gateway: GatewayConfiguration,
+    /// Headers added to the server's own responses.
+    pub security_headers: SecurityHeadersConfiguration,
+    /// Certificate for the listeners that terminate TLS.
+    pub tls: Option<TlsConfiguration>,
+    /// Keep HTTP/1.1 connections open between requests.
+    pub keep_alive: bool,
+    /// Interval between HTTP/2 keep-alive pings, if any.
+    pub keep_alive_interval: Option<Duration>,
+    /// Streams a single HTTP/2 connection may have open at once.
+    pub max_concurrent_streams: u32,
+    /// Time a client has to send the request headers on HTTP/1.1, and to
+    /// complete the TLS handshake.
+    pub header_read_timeout: Duration,
+    /// Connections served at once, per listener. Further clients wait to be accepted.
+    pub max_connections: usize,
+    /// Time in-flight requests get to finish once shutdown starts.
+    pub drain_timeout: Duration,
 }
 
 pub async fn start_server(config: Configuration, auth_domain_api: Arc<AuthDomainApi>, subsys: SubsystemHandle) -> Result<(), ApiError> {
     tracing::trace!("Starting http service");
 
-    let port = config.port;
-    let addr: SocketAddr = format!("0.0.0.0:{}", port).parse().map_err(|_| ApiError::BadPort(port))?;
-    let listener = TcpListener::bind(addr).await.unwrap();
+    if config.listeners.is_empty() {
+        return Err(ApiError::NoListeners);
+    }
+    for (i, listener) in config.listeners.iter().enumerate() {
+        if config.listeners[..i].iter().any(|other| other.address == listener.address) {
+            return Err(ApiError::DuplicateListener(listener.address.to_string()));
+        }
+    }
+
+    let tls_acceptor = match config.tls {
+        Some(ref tls_config) if config.listeners.iter().any(|listener| listener.tls) => {
+            let resolver = Arc::new(tls::ReloadingCertResolver::new(tls_config.clone())?);
+            let watched = resolver.clone();
+            subsys.start(SubsystemBuilder::new("tls_reload", move |h| tls::watch_certificate(watched, h)));
 
-    let routes = handlers::get_routes(&config, auth_domain_api.clone());
+            Some(tls::create_acceptor(resolver))
+        }
+        _ => None,
+    };
 
-    tracing::info!("Listening on port {}", port);
-    loop {
-        let (socket, remote_addr) = tokio::select! {
-            _ = subsys.on_shutdown_requested() => {
-                break;
-            }
+    // Shared by the listeners, so a session is deferred the same whichever serves it.
+    let session_writes = Arc::new(SessionWrites::new(config.session.extension_interval, config.session.write_capacity));
+    let flushed = session_writes.clone();
+    let auth_api = auth_domain_api.auth_api.clone();
+    subsys.start(SubsystemBuilder::new("session_writes", move |h| {
+        session::writes::flush_extensions(flushed, auth_api, h)
+    }));
+
+    // Cookie sessions check revocations other instances made against a list
+    // refreshed in the background, loaded before the first request.
+    let revocations = Revocations::default();
+    if config.session.storage == SessionStorage::Cookie {
+        revocations.refresh(&auth_domain_api.auth_api).await;
+        let refreshed = revocations.clone();
+        let auth_api = auth_domain_api.auth_api.clone();
+        let interval = config.session.revocation_refresh;
+        subsys.start(SubsystemBuilder::new("session_revocations", move |h| {
+            session::cookie::refresh_revocations(refreshed, auth_api, interval, h)
+        }));
+    }
 
-            result = listener.accept() => {
-                result.unwrap()
+    for listener_config in config.listeners.iter() {
+        let bound = BoundListener::bind(&listener_config.address)
+            .await
+            .map_err(|err| ApiError::Bind(listener_config.address.to_string(), err))?;
+        let routes = handlers::get_routes(
+            &config,
+            auth_domain_api.clone(),
+            listener_config.router,
+            session_writes.clone(),
+            revocations.clone(),
+        );
+        let acceptor = if listener_config.tls { tls_acceptor.clone() } else { None };
+
+        tracing::info!(
+            "Listener {} serving {:?} routes on {}{}",
+            listener_config.name,
+            listener_config.router,
+            listener_config.address,
+            if acceptor.is_some() { " with TLS" } else { "" }
+        );
+        let options = server::ServerOptions::new(&config, listener_config, acceptor);
+        let address = listener_config.address.clone();
+        let name = format!("listener-{}", listener_config.name);
+        subsys.start(SubsystemBuilder::new(name, move |h| async move {
+            let shutdown = h.create_cancellation_token();
+            match bound {
+                BoundListener::Tcp(listener) => server::serve(listener, routes, options, shutdown).await,
+                BoundListener::Unix(listener) => server::serve(listener, routes, options, shutdown).await,
+            }
+            if let ListenAddress::Unix(path) = address {
+                let _ = std::fs::remove_file(path);
             }
-        };
 
-        tracing::debug!("connection {} accepted", remote_addr);
-        let tower_service = routes.clone();
-        let name = format!("handler-{remote_addr}");
-        subsys.start(SubsystemBuilder::new(name, move |h| handlers::handle(socket, remote_addr, tower_service, h)));
+            Ok::<(), ApiError>(())
+        }));
     }
 
+    subsys.on_shutdown_requested().await;
+    // Extensions deferred while connections drained.
+    subsys.wait_for_children().await;
+    session_writes.flush(&auth_domain_api.auth_api).await;
+
     Ok(())
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("Something went wrong: {}", self)).into_response()
+        let status = match self {
+            // Sessions are the only way in; the challenge names them for clients that expect one.
+            ApiError::Unauthorized => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, "Session realm=\"auth-play\"")],
+                    format!("Something went wrong: {}", self),
+                )
+                    .into_response()
+            }
+            ApiError::Forbidden | ApiError::Csrf(_) => StatusCode::FORBIDDEN,
+            ApiError::BadRequest(_) | ApiError::DomainError(auth_domain_api::Error::InvalidPassword) => StatusCode::BAD_REQUEST,
+            ApiError::UserNotFound(_) | ApiError::DomainError(auth_domain_api::Error::NotFound) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, format!("Something went wrong: {}", self)).into_response()
     }
 }
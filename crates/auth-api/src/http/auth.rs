@@ -13,44 +13,48 @@ pub(crate) fn get_routes(session_adapter: SessionAdapter) -> Router<()> {
         .route("/session", get(self::get::session))
         .route("/register", post(self::post::register))
         .route("/login", post(self::post::login))
-        .route("/logout", get(self::get::logout))
+        .route("/logout", post(self::post::logout))
+        .route("/logout/everywhere", post(self::post::logout_everywhere))
         .with_state(session_adapter)
         .layer(TimeoutLayer::new(Duration::from_secs(2)))
 }
 
 mod get {
-    use axum::{
-        response::{IntoResponse, Redirect},
-        Json,
-    };
+    use axum::Json;
     use serde::Serialize;
+    use tower_sessions::Session;
 
-    use crate::{http::session::adapter::AuthSession, ApiError};
+    use crate::{
+        http::{csrf, session::adapter::AuthSession},
+        ApiError,
+    };
 
     #[derive(Debug, Serialize)]
     pub(crate) struct SessionResponse {
         pub name: Option<String>,
         pub email: Option<String>,
+        /// To send in `X-CSRF-Token` with state-changing requests.
+        pub csrf_token: String,
     }
 
-    #[tracing::instrument(level = "trace", skip(auth_session))]
-    pub async fn session(auth_session: AuthSession) -> Result<Json<SessionResponse>, ApiError> {
+    #[tracing::instrument(level = "trace", skip(auth_session, session))]
+    pub async fn session(auth_session: AuthSession, session: Session) -> Result<Json<SessionResponse>, ApiError> {
+        let csrf_token = csrf::session_token(&session).await?;
         let response = match auth_session.user {
             Some(user) => SessionResponse {
                 name: Some(user.name),
                 email: Some(user.email),
+                csrf_token,
+            },
+            None => SessionResponse {
+                name: None,
+                email: None,
+                csrf_token,
             },
-            None => SessionResponse { name: None, email: None },
         };
 
         Ok(Json(response))
     }
-
-    #[tracing::instrument(level = "trace", skip(auth_session))]
-    pub async fn logout(mut auth_session: AuthSession) -> impl IntoResponse {
-        let _result = auth_session.logout().await;
-        Redirect::to("/app").into_response()
-    }
 }
 
 mod post {
@@ -63,8 +67,20 @@ mod post {
     use axum_login::AuthnBackend;
     use serde::{Deserialize, Serialize};
 
+    use chrono::Utc;
+    use tower_sessions::{
+        cookie::time::{Duration, OffsetDateTime},
+        session, Expiry, Session,
+    };
+
     use crate::{
-        http::session::adapter::{AuthSession, Credentials},
+        http::{
+            context::ClientContext,
+            session::{
+                adapter::{AuthSession, Credentials, User, FINGERPRINT_KEY, REMEMBER_KEY, ROLES_KEY, STARTED_AT_KEY},
+                binding,
+            },
+        },
         ApiError,
     };
 
@@ -81,6 +97,9 @@ mod post {
     pub(crate) struct LoginRequest {
         email: String,
         password: String,
+        /// Keep the user signed in for the longer "remember me" lifetime.
+        #[serde(default)]
+        remember: bool,
     }
 
     #[derive(Debug, Serialize, Deserialize)]
@@ -90,24 +109,36 @@ mod post {
     }
 
     #[tracing::instrument(level = "trace", skip(session_adapter))]
-    pub async fn register(State(session_adapter): State<SessionAdapter>, Json(payload): Json<RegisterRequest>) -> Result<impl IntoResponse, ApiError> {
+    pub async fn register(
+        State(session_adapter): State<SessionAdapter>,
+        ClientContext(context): ClientContext,
+        Json(payload): Json<RegisterRequest>,
+    ) -> Result<impl IntoResponse, ApiError> {
         let new_user = NewUser {
             name: payload.name.clone(),
             email: payload.email.clone(),
             password: payload.password.clone(),
         };
 
-        let _user = session_adapter.auth_api.register(&new_user).await?;
+        let _user = session_adapter.auth_api.register(&new_user, &context).await?;
 
         Ok(Redirect::to("/app").into_response())
     }
 
-    #[tracing::instrument(level = "trace", skip(auth_session, session_adapter, payload))]
-    pub async fn login(mut auth_session: AuthSession, State(session_adapter): State<SessionAdapter>, Json(payload): Json<LoginRequest>) -> impl IntoResponse {
+    #[tracing::instrument(level = "trace", skip(auth_session, session, session_adapter, payload))]
+    pub async fn login(
+        mut auth_session: AuthSession,
+        session: Session,
+        State(session_adapter): State<SessionAdapter>,
+        ClientContext(context): ClientContext,
+        Json(payload): Json<LoginRequest>,
+    ) -> impl IntoResponse {
+        let remember = payload.remember;
         let credentials = Credentials {
             email: payload.email,
             password: payload.password,
             _next: None,
+            context,
         };
 
         let result = session_adapter.authenticate(credentials).await;
@@ -120,7 +151,11 @@ mod post {
         }
         match result.unwrap() {
             Some(user) => {
-                if auth_session.login(&user).await.is_ok() {
+                // A new id on sign in, so one planted beforehand is useless.
+                if session_adapter.cycle_id(&session).await.is_ok()
+                    && auth_session.login(&user).await.is_ok()
+                    && start_session(&session, &session_adapter, &user, remember).await.is_ok()
+                {
                     Redirect::to("/app").into_response()
                 } else {
                     Json(LoginResponse {
@@ -137,4 +172,346 @@ mod post {
             .into_response(),
         }
     }
+
+    /// Start the absolute lifetime at sign in, and bind the session to the
+    /// client. "Remember me" sessions get a persistent cookie that outlives
+    /// the browser.
+    async fn start_session(session: &Session, session_adapter: &SessionAdapter, user: &User, remember: bool) -> Result<(), session::Error> {
+        session.insert(STARTED_AT_KEY, Utc::now().timestamp_millis()).await?;
+        session.insert(ROLES_KEY, &user.roles).await?;
+        if let (true, Some(client)) = (session_adapter.config.binding.enabled, binding::current()) {
+            session.insert(FINGERPRINT_KEY, client.fingerprint).await?;
+        }
+        if remember {
+            session.insert(REMEMBER_KEY, true).await?;
+            let lifetime = Duration::try_from(session_adapter.config.remember_lifetime).unwrap_or(Duration::MAX);
+            session.set_expiry(Some(Expiry::AtDateTime(OffsetDateTime::now_utc().saturating_add(lifetime))));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, session_adapter))]
+    pub async fn logout(
+        mut auth_session: AuthSession,
+        State(session_adapter): State<SessionAdapter>,
+        ClientContext(context): ClientContext,
+    ) -> impl IntoResponse {
+        if let Some(ref user) = auth_session.user {
+            let _result = session_adapter.auth_api.logout(user.id, &context).await;
+        }
+        let _result = auth_session.logout().await;
+        Redirect::to("/app").into_response()
+    }
+
+    /// Sign out of every session, on every device.
+    #[tracing::instrument(level = "trace", skip(auth_session, session_adapter))]
+    pub async fn logout_everywhere(
+        mut auth_session: AuthSession,
+        State(session_adapter): State<SessionAdapter>,
+        ClientContext(context): ClientContext,
+    ) -> impl IntoResponse {
+        if let Some(ref user) = auth_session.user {
+            if let Err(err) = session_adapter.revoke_user_sessions(user.id, &context).await {
+                tracing::warn!("Couldn't revoke the sessions of user {} - {}", user.id, err);
+            }
+        }
+        let _result = auth_session.logout().await;
+        Redirect::to("/app").into_response()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use auth_domain_models::{
+        audit::{AuditEventKind, RequestContext},
+        auth::{Session, SessionId},
+    };
+    use auth_utils::arcbox;
+    use axum::{
+        body::Body,
+        http::{header, Request, Response},
+        middleware, Router,
+    };
+    use axum_login::{tower_sessions::SessionManagerLayer, AuthManagerLayerBuilder};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use tower::ServiceExt;
+    use tower_sessions::{cookie::time::OffsetDateTime, session::Id, session::Record, Expiry, SessionStore};
+
+    use crate::http::{
+        session::{
+            self,
+            adapter::FINGERPRINT_KEY,
+            binding::{self, Client, Fingerprint},
+            cookie::{self, CookieSessions, Revocations},
+            writes::SessionWrites,
+            SessionAdapter,
+        },
+        testing::{TestAuditApi, TestAuthApi},
+        DriftPolicy, KeyRing, SessionBindingConfiguration, SessionConfiguration, SessionEncryption, SessionStorage,
+    };
+
+    fn routes(config: SessionConfiguration) -> (Router, Arc<Mutex<HashMap<SessionId, Session>>>) {
+        let auth_api = TestAuthApi::default();
+        let sessions = auth_api.sessions.clone();
+        let audit_api = TestAuditApi::default();
+        let keys = KeyRing::new(&[1u8; 64], &[]).unwrap();
+        let writes = Arc::new(SessionWrites::new(config.extension_interval, config.write_capacity));
+        let inactivity_timeout = tower_sessions::cookie::time::Duration::try_from(config.inactivity_timeout).unwrap();
+        let session_adapter = SessionAdapter::new(arcbox!(auth_api), arcbox!(audit_api), config, writes, Revocations::default());
+        let session_layer = SessionManagerLayer::new(session_adapter.clone())
+            .with_secure(false)
+            .with_expiry(Expiry::OnInactivity(inactivity_timeout))
+            .with_always_save(true)
+            .with_signed(keys.active().clone());
+        let auth_layer = AuthManagerLayerBuilder::new(session_adapter.clone(), session_layer)
+            .with_data_key("auth-play")
+            .build();
+        let cookie_layer = middleware::from_fn_with_state(CookieSessions::new(session_adapter.clone(), keys, false), cookie::exchange);
+
+        let remember_layer = middleware::from_fn_with_state(session_adapter.clone(), session::remember);
+
+        (
+            super::get_routes(session_adapter).layer(remember_layer).layer(auth_layer).layer(cookie_layer),
+            sessions,
+        )
+    }
+
+    fn session_cookie(response: &Response<Body>) -> Option<String> {
+        let cookie = response.headers().get(header::SET_COOKIE)?.to_str().ok()?;
+
+        cookie.split(';').next().map(str::to_string)
+    }
+
+    async fn signed_in(routes: &Router, cookie: &str) -> bool {
+        let request = Request::get("/session").header(header::COOKIE, cookie).body(Body::empty()).unwrap();
+        let response = routes.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()["email"].is_string()
+    }
+
+    #[tokio::test]
+    async fn login_rotates_session_id() {
+        let (routes, sessions) = routes(SessionConfiguration::default());
+
+        let response = routes.clone().oneshot(Request::get("/session").body(Body::empty()).unwrap()).await.unwrap();
+        let anonymous = session_cookie(&response).unwrap();
+        assert!(!signed_in(&routes, &anonymous).await);
+
+        let request = Request::post("/login")
+            .header(header::COOKIE, &anonymous)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"email":"test@example.com","password":"password"}"#))
+            .unwrap();
+        let response = routes.clone().oneshot(request).await.unwrap();
+        let authenticated = session_cookie(&response).unwrap();
+
+        assert_ne!(anonymous, authenticated);
+        assert_eq!(sessions.lock().unwrap().len(), 1);
+        assert!(signed_in(&routes, &authenticated).await);
+        assert!(!signed_in(&routes, &anonymous).await);
+    }
+
+    async fn post(routes: &Router, path: &str, cookie: &str, body: &'static str) -> Response<Body> {
+        let request = Request::post(path)
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+
+        routes.clone().oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn cookie_sessions() {
+        let mut keys = std::collections::BTreeMap::new();
+        keys.insert("k1".to_string(), STANDARD.encode([7u8; 32]));
+        let config = SessionConfiguration {
+            storage: SessionStorage::Cookie,
+            encryption: Some(SessionEncryption::new("k1", &keys).unwrap()),
+            ..Default::default()
+        };
+        let (routes, sessions) = routes(config.clone());
+
+        let response = routes.clone().oneshot(Request::get("/session").body(Body::empty()).unwrap()).await.unwrap();
+        let anonymous = session_cookie(&response).unwrap();
+        let response = post(&routes, "/login", &anonymous, r#"{"email":"test@example.com","password":"password"}"#).await;
+        let authenticated = session_cookie(&response).unwrap();
+
+        assert!(sessions.lock().unwrap().is_empty());
+        assert!(signed_in(&routes, &authenticated).await);
+        assert!(!signed_in(&routes, "id=forged").await);
+
+        // Copies of the cookie stop working on logout.
+        post(&routes, "/logout", &authenticated, "").await;
+        assert!(!signed_in(&routes, &authenticated).await);
+
+        let (small, _sessions) = self::routes(SessionConfiguration {
+            max_cookie_size: 100,
+            ..config
+        });
+        let response = post(&small, "/login", "", r#"{"email":"test@example.com","password":"password"}"#).await;
+        assert!(response.status().is_server_error());
+    }
+
+    #[tokio::test]
+    async fn reseals_sessions_with_the_active_key() {
+        let mut keys = BTreeMap::new();
+        keys.insert("old".to_string(), STANDARD.encode([7u8; 32]));
+        let adapter = |sessions: &Arc<Mutex<HashMap<SessionId, Session>>>, active: &str, keys: &BTreeMap<String, String>| {
+            let auth_api = TestAuthApi {
+                sessions: sessions.clone(),
+                ..Default::default()
+            };
+            let config = SessionConfiguration {
+                encryption: Some(SessionEncryption::new(active, keys).unwrap()),
+                ..Default::default()
+            };
+            let writes = Arc::new(SessionWrites::new(Duration::from_secs(30), 100));
+            let audit_api = TestAuditApi::default();
+
+            SessionAdapter::new(arcbox!(auth_api), arcbox!(audit_api), config, writes, Revocations::default())
+        };
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let mut record = Record {
+            id: Id::default(),
+            data: HashMap::from([("key".to_string(), "value".into())]),
+            expiry_date: OffsetDateTime::now_utc() + Duration::from_secs(60 * 60),
+        };
+        adapter(&sessions, "old", &keys).create(&mut record).await.unwrap();
+
+        // Saved unchanged after the key was retired, the session is still written.
+        keys.insert("new".to_string(), STANDARD.encode([8u8; 32]));
+        let new = adapter(&sessions, "new", &keys);
+        let loaded = new.load(&record.id).await.unwrap().unwrap();
+        new.save(&loaded).await.unwrap();
+
+        let data = sessions.lock().unwrap().values().next().unwrap().data.clone();
+        assert!(data.starts_with(&[0xe1, 3]) && data[2..].starts_with(b"new"));
+    }
+
+    #[tokio::test]
+    async fn remembers_sessions_across_requests() {
+        let (routes, _sessions) = routes(SessionConfiguration::default());
+        let response = post(&routes, "/login", "", r#"{"email":"test@example.com","password":"password","remember":true}"#).await;
+        let cookie = session_cookie(&response).unwrap();
+
+        // The next request keeps the remembered expiry, not the inactivity timeout.
+        let request = Request::get("/session").header(header::COOKIE, &cookie).body(Body::empty()).unwrap();
+        let response = routes.clone().oneshot(request).await.unwrap();
+        let set_cookie = response.headers().get(header::SET_COOKIE).unwrap().to_str().unwrap();
+        let max_age: i64 = set_cookie
+            .split(';')
+            .find_map(|attribute| attribute.trim().strip_prefix("Max-Age="))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(max_age > 29 * 24 * 60 * 60, "{}", set_cookie);
+    }
+
+    #[tokio::test]
+    async fn applies_drift_policies() {
+        let binding = |policy| SessionBindingConfiguration {
+            enabled: true,
+            policy,
+            ..Default::default()
+        };
+        let client = |ip: &str, user_agent: &'static str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert(header::USER_AGENT, user_agent.parse().unwrap());
+            Client {
+                fingerprint: Fingerprint::new(&binding(DriftPolicy::Audit), ip.parse().ok(), &headers),
+                context: RequestContext::default(),
+            }
+        };
+        let signed_in = client("192.0.2.10", "Firefox");
+        let elsewhere = client("198.51.100.1", "Firefox");
+        let other_browser = client("192.0.2.10", "curl");
+
+        for (policy, kept, signed_out, audited) in [
+            (DriftPolicy::Ignore, true, false, false),
+            (DriftPolicy::Audit, true, false, true),
+            (DriftPolicy::Reauthenticate, true, true, true),
+            (DriftPolicy::Revoke, false, true, true),
+        ] {
+            for current in [&elsewhere, &other_browser] {
+                let auth_api = TestAuthApi::default();
+                let sessions = auth_api.sessions.clone();
+                let audit_api = TestAuditApi::default();
+                let events = audit_api.events.clone();
+                let config = SessionConfiguration {
+                    binding: binding(policy),
+                    ..Default::default()
+                };
+                let data_key = config.data_key.clone();
+                let writes = Arc::new(SessionWrites::new(config.extension_interval, config.write_capacity));
+                let adapter = SessionAdapter::new(arcbox!(auth_api), arcbox!(audit_api), config, writes, Revocations::default());
+
+                let mut record = Record {
+                    id: Id::default(),
+                    data: HashMap::from([
+                        (data_key.clone(), serde_json::json!({ "user_id": 1 })),
+                        (FINGERPRINT_KEY.to_string(), serde_json::to_value(&signed_in.fingerprint).unwrap()),
+                    ]),
+                    expiry_date: OffsetDateTime::now_utc() + Duration::from_secs(60 * 60),
+                };
+                adapter.create(&mut record).await.unwrap();
+
+                let loaded = binding::scope(current.clone(), adapter.load(&record.id)).await.unwrap();
+                let case = format!("{:?} with {:?}", policy, current.fingerprint);
+                assert_eq!(loaded.is_some(), kept, "{}", case);
+                assert_eq!(sessions.lock().unwrap().is_empty(), !kept, "{}", case);
+                if let Some(loaded) = loaded {
+                    assert_eq!(!loaded.data.contains_key(&data_key), signed_out, "{}", case);
+                }
+                let drifted = || events.lock().unwrap().iter().filter(|event| event.kind == AuditEventKind::SessionDrift).count();
+                assert_eq!(drifted(), usize::from(audited), "{}", case);
+
+                // Audit rebinds the session, so the next request from the same client passes quietly.
+                if policy == DriftPolicy::Audit {
+                    binding::scope(current.clone(), adapter.load(&record.id)).await.unwrap().unwrap();
+                    assert_eq!(drifted(), 1, "{}", case);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_the_client_to_session_events() {
+        let auth_api = TestAuthApi::default();
+        let contexts = auth_api.contexts.clone();
+        let config = SessionConfiguration::default();
+        let writes = Arc::new(SessionWrites::new(config.extension_interval, config.write_capacity));
+        let audit_api = TestAuditApi::default();
+        let adapter = SessionAdapter::new(arcbox!(auth_api), arcbox!(audit_api), config, writes, Revocations::default());
+        let client = Client {
+            fingerprint: Fingerprint::default(),
+            context: RequestContext {
+                ip: Some("192.0.2.10".to_string()),
+                user_agent: Some("Firefox".to_string()),
+            },
+        };
+
+        let mut record = Record {
+            id: Id::default(),
+            data: HashMap::from([("key".to_string(), "value".into())]),
+            expiry_date: OffsetDateTime::now_utc() + Duration::from_secs(60 * 60),
+        };
+        binding::scope(client.clone(), adapter.create(&mut record)).await.unwrap();
+        binding::scope(client, adapter.delete(&record.id)).await.unwrap();
+
+        let contexts = contexts.lock().unwrap();
+        assert_eq!(contexts.len(), 2);
+        for context in contexts.iter() {
+            assert_eq!(context.ip.as_deref(), Some("192.0.2.10"));
+            assert_eq!(context.user_agent.as_deref(), Some("Firefox"));
+        }
+    }
 }
@@ -0,0 +1,71 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use auth_domain_models::audit::RequestContext;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use hyper::header;
+
+use super::proxy::TrustedProxies;
+
+/// What the server knows about the connection a request arrived on.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionInfo {
+    /// The peer, or the client reported by a PROXY protocol header. `None`
+    /// on local sockets.
+    pub peer: Option<SocketAddr>,
+    pub tls: bool,
+    pub trusted_proxies: Arc<TrustedProxies>,
+}
+
+/// The real client address and scheme, resolved through trusted proxies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ClientAddr {
+    pub ip: Option<IpAddr>,
+    pub scheme: &'static str,
+}
+
+impl ClientAddr {
+    pub(crate) fn from_parts(parts: &Parts) -> Self {
+        let Some(connection) = parts.extensions.get::<ConnectionInfo>() else {
+            return ClientAddr { ip: None, scheme: "http" };
+        };
+        let peer = connection.peer.map(|peer| peer.ip());
+        let connection_scheme = if connection.tls { "https" } else { "http" };
+
+        ClientAddr {
+            ip: connection.trusted_proxies.client_ip(peer, &parts.headers),
+            scheme: connection.trusted_proxies.scheme(peer, &parts.headers, connection_scheme),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for ClientAddr
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ClientAddr::from_parts(parts))
+    }
+}
+
+/// Extracts the client address and user agent of the current request.
+#[derive(Debug, Clone)]
+pub(crate) struct ClientContext(pub RequestContext);
+
+impl<S> FromRequestParts<S> for ClientContext
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip = ClientAddr::from_parts(parts).ip.map(|ip| ip.to_string());
+        let user_agent = parts.headers.get(header::USER_AGENT).and_then(|value| value.to_str().ok()).map(str::to_string);
+
+        Ok(ClientContext(RequestContext { ip, user_agent }))
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use auth_utils::hmac;
+use axum::{
+    extract::{OriginalUri, Request, State},
+    http::{header, HeaderMap, HeaderName, Method, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_sessions::Session;
+
+use super::context::ClientAddr;
+use crate::ApiError;
+
+/// Header state-changing requests carry the token in.
+pub(crate) static X_CSRF_TOKEN: HeaderName = HeaderName::from_static("x-csrf-token");
+
+/// Session key of the synchronizer token.
+const SESSION_KEY: &str = "csrf_token";
+
+/// Routes that change nothing whatever their method.
+const EXEMPT_PATHS: [&str; 1] = ["/auth/verify"];
+
+/// Origins allowed to make state-changing requests, besides the server's own.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CsrfOrigins(Arc<Vec<String>>);
+
+impl CsrfOrigins {
+    pub(crate) fn new(origins: Vec<String>) -> Self {
+        Self(Arc::new(
+            origins.into_iter().map(|origin| origin.trim_end_matches('/').to_ascii_lowercase()).collect(),
+        ))
+    }
+}
+
+/// The session's CSRF token, created on first use.
+pub(crate) async fn session_token(session: &Session) -> Result<String, ApiError> {
+    if let Some(token) = session.get::<String>(SESSION_KEY).await.map_err(|_| ApiError::EncodeDecodeError)? {
+        return Ok(token);
+    }
+
+    let token = hmac::random_token(32);
+    session.insert(SESSION_KEY, &token).await.map_err(|_| ApiError::EncodeDecodeError)?;
+
+    Ok(token)
+}
+
+/// Reject state-changing requests that come from another origin or do not
+/// carry the session's token in `X-CSRF-Token`.
+pub(crate) async fn verify(
+    State(origins): State<CsrfOrigins>,
+    session: Session,
+    client_addr: ClientAddr,
+    OriginalUri(original_uri): OriginalUri,
+    request: Request,
+    next: Next,
+) -> Response {
+    let safe = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE);
+    if safe || EXEMPT_PATHS.contains(&original_uri.path()) {
+        return next.run(request).await;
+    }
+
+    if let Err(reason) = check_origin(request.headers(), request.uri(), client_addr.scheme, &origins) {
+        tracing::debug!("Rejected {} {}: {}", request.method(), original_uri.path(), reason);
+        return ApiError::Csrf(reason).into_response();
+    }
+
+    let expected = match session.get::<String>(SESSION_KEY).await {
+        Ok(Some(token)) => token,
+        _ => return ApiError::Csrf("no token issued").into_response(),
+    };
+    let provided = request.headers().get(&X_CSRF_TOKEN).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    if !hmac::tokens_match(&expected, provided) {
+        tracing::debug!("Rejected {} {}: bad token", request.method(), original_uri.path());
+        return ApiError::Csrf("bad token").into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Strict Origin check, falling back to Referer. Requests with neither are
+/// rejected.
+fn check_origin(headers: &HeaderMap, uri: &Uri, scheme: &str, origins: &CsrfOrigins) -> Result<(), &'static str> {
+    let origin = match headers.get(header::ORIGIN) {
+        Some(origin) => origin.to_str().map_err(|_| "bad origin")?.to_ascii_lowercase(),
+        None => {
+            let referer = headers.get(header::REFERER).ok_or("no origin or referer")?;
+            let referer: Uri = referer.to_str().ok().and_then(|referer| referer.parse().ok()).ok_or("bad referer")?;
+            match (referer.scheme_str(), referer.authority()) {
+                (Some(scheme), Some(authority)) => format!("{}://{}", scheme, authority).to_ascii_lowercase(),
+                _ => return Err("bad referer"),
+            }
+        }
+    };
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| uri.authority().map(|authority| authority.as_str()))
+        .ok_or("no host")?;
+    if origin == format!("{}://{}", scheme, host).to_ascii_lowercase() || origins.0.contains(&origin) {
+        Ok(())
+    } else {
+        Err("cross-origin request")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{HeaderMap, HeaderValue, Uri};
+
+    use super::{check_origin, CsrfOrigins};
+
+    #[test]
+    fn checks_origin() {
+        let origins = CsrfOrigins::new(vec!["https://app.example.com/".to_string()]);
+        let uri = Uri::from_static("/auth/login");
+        let check = |name: &'static str, value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("host", HeaderValue::from_static("auth.example.com"));
+            if !name.is_empty() {
+                headers.insert(name, HeaderValue::from_static(value));
+            }
+            check_origin(&headers, &uri, "https", &origins)
+        };
+
+        assert!(check("origin", "https://auth.example.com").is_ok());
+        assert!(check("origin", "https://APP.example.com").is_ok());
+        assert!(check("origin", "http://auth.example.com").is_err());
+        assert!(check("origin", "https://evil.example").is_err());
+        assert!(check("origin", "null").is_err());
+        assert!(check("referer", "https://auth.example.com/app?x=1").is_ok());
+        assert!(check("referer", "https://evil.example/auth.example.com").is_err());
+        assert!(check("", "").is_err());
+    }
+}
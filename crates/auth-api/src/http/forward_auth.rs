@@ -0,0 +1,430 @@
+use std::{str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::any,
+    Router,
+};
+use axum_login::AuthnBackend;
+use hyper::Uri;
+use serde::Deserialize;
+use tower_sessions::{session::Id, SessionStore};
+
+use super::{
+    keys::KeyRing,
+    session::{
+        adapter::{AuthSession, User},
+        SessionAdapter, SessionStorage,
+    },
+};
+
+static X_AUTH_USER_ID: HeaderName = HeaderName::from_static("x-auth-user-id");
+static X_AUTH_EMAIL: HeaderName = HeaderName::from_static("x-auth-email");
+static X_AUTH_ROLES: HeaderName = HeaderName::from_static("x-auth-roles");
+
+/// Set by nginx (`proxy_set_header X-Original-URL $scheme://$http_host$request_uri`).
+static X_ORIGINAL_URL: HeaderName = HeaderName::from_static("x-original-url");
+/// Set by Traefik and Caddy.
+static X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+static X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+static X_FORWARDED_URI: HeaderName = HeaderName::from_static("x-forwarded-uri");
+
+#[derive(Debug, Clone)]
+pub struct ForwardAuthConfiguration {
+    /// Where unauthenticated users are sent, with the original URL in `next`.
+    pub login_url: String,
+    pub rules: Vec<AccessRule>,
+}
+
+/// Who may reach a host and path. The most specific matching rule applies;
+/// requests no rule matches need any signed in user.
+#[derive(Debug, Clone, Default)]
+pub struct AccessRule {
+    /// `app.example.com`, or `*.example.com` for any subdomain. Any host when unset.
+    pub host: Option<String>,
+    /// Path prefix, matched on whole segments. Any path when unset.
+    pub path: Option<String>,
+    /// Let anonymous requests through.
+    pub public: bool,
+    /// Roles of which the user needs at least one. Any signed in user when empty.
+    pub roles: Vec<String>,
+}
+
+impl AccessRule {
+    /// How specific the rule is for the request, `None` if it does not match.
+    fn specificity(&self, host: &str, path: &str) -> Option<(u8, usize)> {
+        specificity(self.host.as_deref(), self.path.as_deref(), host, path)
+    }
+
+    fn allows(&self, user: &User) -> bool {
+        self.roles.is_empty() || self.roles.iter().any(|role| user.roles.contains(role))
+    }
+}
+
+/// How specific a host pattern and path prefix are for a request, `None` if
+/// they do not match. More specific hosts beat longer paths.
+pub(crate) fn specificity(host_pattern: Option<&str>, path_prefix: Option<&str>, host: &str, path: &str) -> Option<(u8, usize)> {
+    let host_score = match host_pattern {
+        None => 0,
+        Some(pattern) => match pattern.strip_prefix("*.") {
+            Some(domain) if is_subdomain(host, domain) => 1,
+            Some(_) => return None,
+            None if pattern.eq_ignore_ascii_case(host) => 2,
+            None => return None,
+        },
+    };
+    let path_score = match path_prefix {
+        None => 0,
+        Some(prefix) if path_has_prefix(path, prefix) => prefix.len(),
+        Some(_) => return None,
+    };
+
+    Some((host_score, path_score))
+}
+
+fn is_subdomain(host: &str, domain: &str) -> bool {
+    match host.len().checked_sub(domain.len() + 1) {
+        Some(start) if start > 0 => host.is_char_boundary(start) && host[start..].eq_ignore_ascii_case(&format!(".{}", domain)),
+        _ => false,
+    }
+}
+
+pub(crate) fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// A request path the way the upstream will resolve it, so rules cannot be
+/// sidestepped with `/public/../admin`, `/%61dmin` or `//admin`: unreserved
+/// characters are percent-decoded, repeated slashes collapsed and dot
+/// segments resolved. `None` for paths that are not absolute, are badly
+/// encoded, or contain an encoded `/` or a `\`, which servers disagree on.
+pub(crate) fn normalize_path(path: &str) -> Option<String> {
+    if !path.starts_with('/') || path.contains('\\') {
+        return None;
+    }
+
+    let mut segments: Vec<String> = Vec::new();
+    let raw_segments: Vec<&str> = path[1..].split('/').collect();
+    let mut trailing_slash = false;
+    for (index, raw) in raw_segments.iter().enumerate() {
+        let last = index + 1 == raw_segments.len();
+        match decode_unreserved(raw)?.as_str() {
+            "" => trailing_slash = last,
+            "." => trailing_slash = last,
+            ".." => {
+                segments.pop();
+                trailing_slash = last;
+            }
+            segment => {
+                segments.push(segment.to_string());
+                trailing_slash = false;
+            }
+        }
+    }
+
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+
+    Some(normalized)
+}
+
+/// Decode percent-encoded unreserved characters and uppercase the rest.
+fn decode_unreserved(segment: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(segment.len());
+    let mut chars = segment.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            decoded.push(c);
+            continue;
+        }
+        let hex: String = chars.by_ref().take(2).collect();
+        let byte = match hex.len() {
+            2 => u8::from_str_radix(&hex, 16).ok()?,
+            _ => return None,
+        };
+        match byte {
+            b'/' | b'\\' => return None,
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => decoded.push(byte as char),
+            _ => decoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    Some(decoded)
+}
+
+fn find_rule<'a>(rules: &'a [AccessRule], host: &str, path: &str) -> Option<&'a AccessRule> {
+    rules
+        .iter()
+        .filter_map(|rule| rule.specificity(host, path).map(|score| (score, rule)))
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, rule)| rule)
+}
+
+/// `host` from a `host:port` authority.
+pub(crate) fn strip_port(host: &str) -> &str {
+    if let Some(end) = host.strip_prefix('[').and_then(|rest| rest.find(']')) {
+        return &host[..end + 2];
+    }
+    match host.rsplit_once(':') {
+        Some((hostname, _)) => hostname,
+        None => host,
+    }
+}
+
+/// The request the proxy is asking about.
+#[derive(Debug, PartialEq, Eq)]
+struct OriginalRequest {
+    scheme: String,
+    host: String,
+    /// Path and query.
+    uri: String,
+}
+
+impl OriginalRequest {
+    /// `None` when the headers are missing, or the path does not normalize.
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut original = Self::from_raw_headers(headers)?;
+        let (path, query) = match original.uri.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (original.uri.as_str(), None),
+        };
+        let path = normalize_path(path)?;
+        original.uri = match query {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+
+        Some(original)
+    }
+
+    fn from_raw_headers(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+
+        if let Some(url) = get(&X_ORIGINAL_URL) {
+            let url: Uri = url.parse().ok()?;
+            return Some(OriginalRequest {
+                scheme: url.scheme_str().unwrap_or("http").to_string(),
+                host: url.authority()?.to_string(),
+                uri: url.path_and_query().map(|path| path.to_string()).unwrap_or_else(|| "/".to_string()),
+            });
+        }
+
+        let host = get(&X_FORWARDED_HOST).or_else(|| get(&header::HOST))?;
+        Some(OriginalRequest {
+            scheme: get(&X_FORWARDED_PROTO).unwrap_or("http").to_string(),
+            host: host.to_string(),
+            uri: get(&X_FORWARDED_URI).unwrap_or("/").to_string(),
+        })
+    }
+
+    /// Host without the port, for matching rules.
+    fn hostname(&self) -> &str {
+        strip_port(&self.host)
+    }
+
+    fn path(&self) -> &str {
+        self.uri.split('?').next().unwrap_or("/")
+    }
+
+    fn url(&self) -> String {
+        format!("{}://{}{}", self.scheme, self.host, self.uri)
+    }
+}
+
+#[derive(Clone)]
+struct ForwardAuth {
+    session_adapter: SessionAdapter,
+    keys: KeyRing,
+    config: Arc<ForwardAuthConfiguration>,
+}
+
+pub(crate) fn get_routes(session_adapter: SessionAdapter, keys: KeyRing, config: ForwardAuthConfiguration) -> Router<()> {
+    let state = ForwardAuth {
+        session_adapter,
+        keys,
+        config: Arc::new(config),
+    };
+
+    Router::new().route("/verify", any(verify)).with_state(state)
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyQuery {
+    /// Answer unauthenticated requests with a redirect rather than 401, for
+    /// proxies that pass the response on to the client.
+    #[serde(default)]
+    redirect: bool,
+}
+
+/// Forward-auth check for nginx `auth_request`, Traefik `forwardAuth` and
+/// Caddy `forward_auth`. Accepts the session cookie, or its value as a
+/// bearer token.
+#[tracing::instrument(level = "trace", skip_all)]
+async fn verify(State(state): State<ForwardAuth>, Query(query): Query<VerifyQuery>, auth_session: AuthSession, headers: HeaderMap) -> Response {
+    let Some(original) = OriginalRequest::from_headers(&headers) else {
+        return (StatusCode::BAD_REQUEST, "Missing or invalid original request").into_response();
+    };
+
+    let user = match bearer_token(&headers) {
+        Some(token) => user_from_token(&state, token).await,
+        None => auth_session.user,
+    };
+    let rule = find_rule(&state.config.rules, original.hostname(), original.path());
+
+    match (user, rule) {
+        (Some(user), Some(rule)) if !rule.allows(&user) => {
+            tracing::debug!("User {} denied access to {}", user.id, original.url());
+            StatusCode::FORBIDDEN.into_response()
+        }
+        (Some(user), _) => (StatusCode::OK, user_headers(&user)).into_response(),
+        (None, Some(rule)) if rule.public => StatusCode::OK.into_response(),
+        (None, _) => {
+            let login_url = login_redirect(&state.config.login_url, &original.url());
+            let status = if query.redirect { StatusCode::FOUND } else { StatusCode::UNAUTHORIZED };
+
+            (status, [(header::LOCATION, login_url)]).into_response()
+        }
+    }
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+/// Resolve a session token the way the session layer resolves the cookie:
+/// a signed session id, or a sealed cookie session.
+async fn user_from_token(state: &ForwardAuth, token: &str) -> Option<User> {
+    let record = match state.session_adapter.config.storage {
+        SessionStorage::Database => {
+            let id = state.keys.verify(&state.session_adapter.config.cookie_name, token)?;
+            let id = Id::from_str(&id).ok()?;
+
+            state.session_adapter.load(&id).await.ok()??
+        }
+        SessionStorage::Cookie => state.session_adapter.load_cookie(token).await?,
+    };
+    let user_id = state.session_adapter.record_user_id(&record)?;
+    let auth_hash = state.session_adapter.record_auth_hash(&record)?;
+    let user = state.session_adapter.get_user(&user_id).await.ok()??;
+
+    // Same check as axum-login: changing the password ends the session.
+    (user.password_sha.as_bytes() == auth_hash.as_slice()).then_some(user)
+}
+
+fn user_headers(user: &User) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(X_AUTH_USER_ID.clone(), HeaderValue::from(user.id));
+    if let Ok(email) = HeaderValue::from_str(&user.email) {
+        headers.insert(X_AUTH_EMAIL.clone(), email);
+    }
+    if let Ok(roles) = HeaderValue::from_str(&user.roles.join(",")) {
+        headers.insert(X_AUTH_ROLES.clone(), roles);
+    }
+
+    headers
+}
+
+fn login_redirect(login_url: &str, next: &str) -> String {
+    let query = form_urlencoded::Serializer::new(String::new()).append_pair("next", next).finish();
+    let separator = if login_url.contains('?') { '&' } else { '?' };
+
+    format!("{}{}{}", login_url, separator, query)
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::{find_rule, normalize_path as normalize, AccessRule, OriginalRequest};
+
+    fn rule(host: Option<&str>, path: Option<&str>, roles: &[&str]) -> AccessRule {
+        AccessRule {
+            host: host.map(str::to_string),
+            path: path.map(str::to_string),
+            public: false,
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn picks_most_specific_rule() {
+        let rules = vec![
+            rule(None, None, &["user"]),
+            rule(Some("*.example.com"), None, &["staff"]),
+            rule(Some("grafana.example.com"), None, &["ops"]),
+            rule(Some("grafana.example.com"), Some("/admin"), &["admin"]),
+        ];
+        let roles = |host, path| find_rule(&rules, host, path).map(|rule| rule.roles[0].as_str());
+
+        assert_eq!(roles("other.org", "/"), Some("user"));
+        assert_eq!(roles("wiki.example.com", "/"), Some("staff"));
+        assert_eq!(roles("example.com", "/"), Some("user"));
+        assert_eq!(roles("GRAFANA.example.com", "/d/1"), Some("ops"));
+        assert_eq!(roles("grafana.example.com", "/admin/users"), Some("admin"));
+        assert_eq!(roles("grafana.example.com", "/administrator"), Some("ops"));
+        assert!(find_rule(&rules[1..], "other.org", "/").is_none());
+    }
+
+    #[test]
+    fn reads_original_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("auth.internal:3000"));
+        headers.insert("x-forwarded-host", HeaderValue::from_static("app.example.com:8443"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https"));
+        headers.insert("x-forwarded-uri", HeaderValue::from_static("/admin?tab=1"));
+        let original = OriginalRequest::from_headers(&headers).unwrap();
+        assert_eq!(original.url(), "https://app.example.com:8443/admin?tab=1");
+        assert_eq!(original.hostname(), "app.example.com");
+        assert_eq!(original.path(), "/admin");
+
+        headers.insert("x-original-url", HeaderValue::from_static("http://[::1]:8080/"));
+        let original = OriginalRequest::from_headers(&headers).unwrap();
+        assert_eq!(original.url(), "http://[::1]:8080/");
+        assert_eq!(original.hostname(), "[::1]");
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize("/public/../admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize("/public/%2e%2e/admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize("/%61dmin").as_deref(), Some("/admin"));
+        assert_eq!(normalize("//admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize("/./admin/./users/").as_deref(), Some("/admin/users/"));
+        assert_eq!(normalize("/../../admin").as_deref(), Some("/admin"));
+        assert_eq!(normalize("/admin/..").as_deref(), Some("/"));
+        assert_eq!(normalize("/files/a%20b").as_deref(), Some("/files/a%20b"));
+        assert_eq!(normalize("/files/a%c3%a9").as_deref(), Some("/files/a%C3%A9"));
+        assert_eq!(normalize("/public%2F..%2Fadmin"), None);
+        assert_eq!(normalize("/public/..%5cadmin"), None);
+        assert_eq!(normalize("/public\\..\\admin"), None);
+        assert_eq!(normalize("/admin%zz"), None);
+        assert_eq!(normalize("admin"), None);
+    }
+
+    #[test]
+    fn matches_rules_on_the_normalized_path() {
+        let rules = vec![rule(None, Some("/public"), &[]), rule(None, Some("/admin"), &["admin"])];
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("app.example.com"));
+
+        for uri in ["/public/../admin", "/%61dmin?tab=1", "//admin"] {
+            headers.insert("x-forwarded-uri", HeaderValue::from_str(uri).unwrap());
+            let original = OriginalRequest::from_headers(&headers).unwrap();
+            let rule = find_rule(&rules, original.hostname(), original.path()).unwrap();
+            assert_eq!(rule.roles, ["admin"], "{}", uri);
+        }
+
+        headers.insert("x-forwarded-uri", HeaderValue::from_static("/public%2F..%2Fadmin"));
+        assert!(OriginalRequest::from_headers(&headers).is_none());
+    }
+}
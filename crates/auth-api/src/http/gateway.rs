@@ -0,0 +1,400 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use auth_utils::hmac;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::{TokioExecutor, TokioIo},
+};
+use serde_json::json;
+
+use super::{
+    context::ClientAddr,
+    forward_auth::{normalize_path, specificity, strip_port},
+    session::adapter::{AuthSession, User},
+};
+
+static X_AUTH_USER_ID: HeaderName = HeaderName::from_static("x-auth-user-id");
+static X_AUTH_EMAIL: HeaderName = HeaderName::from_static("x-auth-email");
+static X_AUTH_ROLES: HeaderName = HeaderName::from_static("x-auth-roles");
+static X_AUTH_TOKEN: HeaderName = HeaderName::from_static("x-auth-token");
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+static X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+static X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+
+/// Prefix of the headers carrying the verified identity. Clients cannot set them.
+const IDENTITY_HEADER_PREFIX: &str = "x-auth-";
+
+/// Headers that only apply to a single connection.
+static HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+#[derive(Debug, Clone)]
+pub struct GatewayConfiguration {
+    pub routes: Vec<GatewayRoute>,
+    /// Also hand upstreams a signed JWT in `X-Auth-Token`.
+    pub jwt: Option<JwtConfiguration>,
+    /// Time an upstream has to start responding.
+    pub timeout: Duration,
+}
+
+/// Requests for a host and path prefix go to an upstream. The most specific
+/// matching route applies.
+#[derive(Debug, Clone)]
+pub struct GatewayRoute {
+    /// `app.example.com` or `*.example.com`. Any host when unset.
+    pub host: Option<String>,
+    /// Path prefix, matched on whole segments.
+    pub path: String,
+    /// `http://host:port`, optionally with a base path.
+    pub upstream: Uri,
+    /// Remove `path` before forwarding.
+    pub strip_path: bool,
+    /// Roles of which the user needs at least one. Any signed in user when empty.
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JwtConfiguration {
+    /// HS256 key shared with the upstreams.
+    pub secret: String,
+    pub ttl: Duration,
+}
+
+#[derive(Clone)]
+pub(crate) struct Gateway {
+    config: Arc<GatewayConfiguration>,
+    client: Client<HttpConnector, Body>,
+    /// Name of the session cookie, kept from upstreams.
+    session_cookie: Arc<str>,
+}
+
+impl Gateway {
+    pub(crate) fn new(config: GatewayConfiguration, session_cookie: &str) -> Self {
+        let client = Client::builder(TokioExecutor::new()).build(HttpConnector::new());
+
+        Self {
+            config: Arc::new(config),
+            client,
+            session_cookie: session_cookie.into(),
+        }
+    }
+
+    fn find_route(&self, host: &str, path: &str) -> Option<&GatewayRoute> {
+        self.config
+            .routes
+            .iter()
+            .filter_map(|route| specificity(route.host.as_deref(), Some(&route.path), host, path).map(|score| (score, route)))
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, route)| route)
+    }
+}
+
+/// Proxy a signed in user's request to the upstream of its route. Sits
+/// behind `login_required!`, so there is always a user.
+#[tracing::instrument(level = "trace", skip_all)]
+pub(crate) async fn proxy(State(gateway): State<Gateway>, auth_session: AuthSession, client_addr: ClientAddr, mut request: Request) -> Response {
+    let Some(user) = auth_session.user else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let host = request.uri().host().map(str::to_string).or_else(|| {
+        let host = request.headers().get(header::HOST)?.to_str().ok()?;
+        Some(strip_port(host).to_string())
+    });
+    // Route, and forward, the path the upstream will resolve.
+    let Some(path) = normalize_path(request.uri().path()) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let Some(route) = gateway.find_route(host.as_deref().unwrap_or_default(), &path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !route.roles.is_empty() && !route.roles.iter().any(|role| user.roles.contains(role)) {
+        tracing::debug!("User {} denied access to {}", user.id, request.uri());
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let Some(uri) = upstream_uri(route, &path, request.uri().query()) else {
+        return StatusCode::BAD_GATEWAY.into_response();
+    };
+    let upgrade = request.headers().get(header::UPGRADE).cloned();
+    let client_upgrade = upgrade.as_ref().map(|_| hyper::upgrade::on(&mut request));
+
+    let (mut parts, body) = request.into_parts();
+    // The client sets Host from the upstream URI; the original goes in X-Forwarded-Host.
+    let original_host = parts
+        .headers
+        .remove(header::HOST)
+        .or_else(|| parts.uri.authority().and_then(|authority| HeaderValue::from_str(authority.as_str()).ok()));
+    prepare_headers(
+        &mut parts.headers,
+        &gateway.session_cookie,
+        &user,
+        &client_addr,
+        original_host,
+        gateway.config.jwt.as_ref(),
+    );
+    if let Some(upgrade) = upgrade {
+        parts.headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+        parts.headers.insert(header::UPGRADE, upgrade);
+    }
+    parts.uri = uri;
+    parts.version = hyper::Version::HTTP_11;
+    let upstream_request = Request::from_parts(parts, body);
+
+    let mut response = match tokio::time::timeout(gateway.config.timeout, gateway.client.request(upstream_request)).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(err)) => {
+            tracing::warn!("Upstream {} failed: {}", route.upstream, err);
+            return StatusCode::BAD_GATEWAY.into_response();
+        }
+        Err(_) => {
+            tracing::warn!("Upstream {} timed out", route.upstream);
+            return StatusCode::GATEWAY_TIMEOUT.into_response();
+        }
+    };
+
+    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+        if let Some(client_upgrade) = client_upgrade {
+            let upstream_upgrade = hyper::upgrade::on(&mut response);
+            tokio::spawn(async move {
+                match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                    Ok((client, upstream)) => {
+                        let _ = tokio::io::copy_bidirectional(&mut TokioIo::new(client), &mut TokioIo::new(upstream)).await;
+                    }
+                    Err(err) => tracing::debug!("Upgrade failed: {}", err),
+                }
+            });
+        }
+        return response.map(Body::new);
+    }
+
+    remove_hop_by_hop(response.headers_mut());
+    response.map(Body::new)
+}
+
+fn upstream_uri(route: &GatewayRoute, path: &str, query: Option<&str>) -> Option<Uri> {
+    let path = match route.strip_path {
+        true => path.strip_prefix(route.path.trim_end_matches('/')).unwrap_or(path),
+        false => path,
+    };
+    let base = route.upstream.path().trim_end_matches('/');
+    let path = match (base.is_empty(), path.is_empty()) {
+        (_, true) => format!("{}/", base),
+        (true, false) => path.to_string(),
+        (false, false) => format!("{}{}", base, path),
+    };
+    let path_and_query = match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+
+    Uri::builder()
+        .scheme(route.upstream.scheme()?.clone())
+        .authority(route.upstream.authority()?.clone())
+        .path_and_query(path_and_query)
+        .build()
+        .ok()
+}
+
+/// Replace whatever identity the client claimed with the verified one.
+fn prepare_headers(
+    headers: &mut HeaderMap,
+    session_cookie: &str,
+    user: &User,
+    client_addr: &ClientAddr,
+    host: Option<HeaderValue>,
+    jwt: Option<&JwtConfiguration>,
+) {
+    remove_hop_by_hop(headers);
+    let claimed: Vec<HeaderName> = headers
+        .keys()
+        .filter(|name| name.as_str().starts_with(IDENTITY_HEADER_PREFIX))
+        .cloned()
+        .collect();
+    for name in claimed {
+        headers.remove(name);
+    }
+    strip_session_cookie(headers, session_cookie);
+
+    headers.insert(X_AUTH_USER_ID.clone(), HeaderValue::from(user.id));
+    if let Ok(email) = HeaderValue::from_str(&user.email) {
+        headers.insert(X_AUTH_EMAIL.clone(), email);
+    }
+    if let Ok(roles) = HeaderValue::from_str(&user.roles.join(",")) {
+        headers.insert(X_AUTH_ROLES.clone(), roles);
+    }
+    if let Some(token) = jwt.and_then(|jwt| HeaderValue::from_str(&identity_token(user, jwt)).ok()) {
+        headers.insert(X_AUTH_TOKEN.clone(), token);
+    }
+
+    if let Some(ip) = client_addr.ip.and_then(|ip| HeaderValue::from_str(&ip.to_string()).ok()) {
+        headers.insert(X_FORWARDED_FOR.clone(), ip);
+    }
+    headers.insert(X_FORWARDED_PROTO.clone(), HeaderValue::from_static(client_addr.scheme));
+    if let Some(host) = host {
+        headers.insert(X_FORWARDED_HOST.clone(), host);
+    }
+}
+
+fn remove_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in listed.iter().chain(HOP_BY_HOP.iter()) {
+        headers.remove(name);
+    }
+}
+
+/// Upstreams must not be able to replay the user's session.
+fn strip_session_cookie(headers: &mut HeaderMap, session_cookie: &str) {
+    let cookies: Vec<String> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|cookie| !cookie.is_empty() && cookie.split('=').next() != Some(session_cookie))
+        .map(str::to_string)
+        .collect();
+
+    headers.remove(header::COOKIE);
+    if cookies.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&cookies.join("; ")) {
+        headers.insert(header::COOKIE, value);
+    }
+}
+
+/// HS256 JWT naming the user, valid for the configured time.
+fn identity_token(user: &User, jwt: &JwtConfiguration) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let header = json!({ "alg": "HS256", "typ": "JWT" });
+    let claims = json!({
+        "iss": "auth-play",
+        "sub": user.id.to_string(),
+        "email": user.email,
+        "roles": user.roles,
+        "iat": now,
+        "exp": now + jwt.ttl.as_secs(),
+    });
+
+    let message = format!("{}.{}", URL_SAFE_NO_PAD.encode(header.to_string()), URL_SAFE_NO_PAD.encode(claims.to_string()));
+    let signature = URL_SAFE_NO_PAD.encode(hmac::sign_bytes(jwt.secret.as_bytes(), message.as_bytes()));
+
+    format!("{}.{}", message, signature)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use axum::http::{header, HeaderMap, HeaderValue};
+
+    use super::{prepare_headers, upstream_uri, Gateway, GatewayConfiguration, GatewayRoute};
+    use crate::http::forward_auth::normalize_path;
+    use crate::http::{context::ClientAddr, session::adapter::User};
+
+    fn route(path: &str, upstream: &str, strip_path: bool) -> GatewayRoute {
+        GatewayRoute {
+            host: None,
+            path: path.to_string(),
+            upstream: upstream.parse().unwrap(),
+            strip_path,
+            roles: vec![],
+        }
+    }
+
+    #[test]
+    fn rewrites_uri() {
+        let upstream = |route| upstream_uri(&route, "/grafana/d/1", Some("refresh=5s")).unwrap().to_string();
+
+        assert_eq!(
+            upstream(route("/grafana", "http://grafana:3000", false)),
+            "http://grafana:3000/grafana/d/1?refresh=5s"
+        );
+        assert_eq!(upstream(route("/grafana", "http://grafana:3000", true)), "http://grafana:3000/d/1?refresh=5s");
+        assert_eq!(
+            upstream(route("/grafana/", "http://grafana:3000/base/", true)),
+            "http://grafana:3000/base/d/1?refresh=5s"
+        );
+        assert_eq!(
+            upstream_uri(&route("/grafana/d/1", "http://grafana:3000", true), "/grafana/d/1", None)
+                .unwrap()
+                .to_string(),
+            "http://grafana:3000/"
+        );
+    }
+
+    #[tokio::test]
+    async fn routes_the_normalized_path() {
+        let mut admin = route("/admin", "http://admin:8080", false);
+        admin.roles = vec!["admin".to_string()];
+        let gateway = Gateway::new(
+            GatewayConfiguration {
+                routes: vec![route("/public", "http://public:8080", false), admin],
+                jwt: None,
+                timeout: Duration::from_secs(1),
+            },
+            "id",
+        );
+
+        for path in ["/public/../admin/users", "/%61dmin/users", "//admin/users"] {
+            let path = normalize_path(path).unwrap();
+            let route = gateway.find_route("app.example.com", &path).unwrap();
+            assert_eq!(route.path, "/admin");
+            assert_eq!(upstream_uri(route, &path, None).unwrap().to_string(), "http://admin:8080/admin/users");
+        }
+    }
+
+    #[test]
+    fn replaces_identity_headers() {
+        let user = User {
+            id: 7,
+            name: "Ann".to_string(),
+            email: "ann@example.com".to_string(),
+            password_sha: String::new(),
+            roles: vec!["admin".to_string()],
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert("x-auth-user-id", HeaderValue::from_static("1"));
+        headers.insert("x-auth-impersonate", HeaderValue::from_static("1"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("x-private"));
+        headers.insert("x-private", HeaderValue::from_static("1"));
+        headers.insert(header::COOKIE, HeaderValue::from_static("theme=dark; id=secret"));
+        let client_addr = ClientAddr {
+            ip: Some("10.0.0.1".parse().unwrap()),
+            scheme: "https",
+        };
+
+        prepare_headers(&mut headers, "id", &user, &client_addr, None, None);
+
+        assert_eq!(headers["x-auth-user-id"], "7");
+        assert_eq!(headers["x-auth-roles"], "admin");
+        assert!(!headers.contains_key("x-auth-impersonate"));
+        assert!(!headers.contains_key("x-private"));
+        assert_eq!(headers[header::COOKIE], "theme=dark");
+        assert_eq!(headers["x-forwarded-for"], "10.0.0.1");
+    }
+}
@@ -1,82 +1,158 @@
-use crate::ApiError;
-use std::{net::SocketAddr, sync::Arc};
+use std::{borrow::Cow, sync::Arc};
 
 use auth_api_frontend::Dist;
 use auth_domain_api::AuthDomainApi;
 use axum::{
-    extract::Request,
+    middleware,
     response::{IntoResponse, Redirect, Response},
-    routing::get,
+    routing::{any, get},
     Router,
 };
 use axum_login::{login_required, tower_sessions::SessionManagerLayer, AuthManagerLayerBuilder};
-use hyper::{body::Incoming, header, StatusCode, Uri};
-use hyper_util::rt::TokioIo;
-use tokio::net::TcpStream;
-use tokio_graceful_shutdown::SubsystemHandle;
-use tower::Service;
+use hyper::{header, StatusCode, Uri};
 use tower_http::timeout::TimeoutLayer;
-use tower_sessions::cookie::{time::Duration, Key};
+use tower_sessions::cookie::time::Duration;
 use tower_sessions::Expiry;
 
-use super::{auth, health, session::SessionAdapter, v1, Configuration};
+use super::{
+    auth,
+    csrf::{self, CsrfOrigins},
+    forward_auth,
+    gateway::{self, Gateway},
+    health, keys,
+    security_headers::{self, CspNonce, SecurityHeaders},
+    session::{
+        self,
+        cookie::{CookieSessions, Revocations},
+        writes::SessionWrites,
+        SessionAdapter,
+    },
+    v1, Configuration, RouterKind,
+};
 
 static INDEX_HTML: &str = "index.html";
 
-pub fn get_routes(config: &Configuration, auth_domain_api: Arc<AuthDomainApi>) -> Router<()> {
-    let session_adapter = SessionAdapter::new(auth_domain_api.auth_api.clone());
-
-    let v1_routes = v1::get_routes();
+pub(crate) fn get_routes(
+    config: &Configuration,
+    auth_domain_api: Arc<AuthDomainApi>,
+    kind: RouterKind,
+    session_writes: Arc<SessionWrites>,
+    revocations: Revocations,
+) -> Router<()> {
+    let session_config = &config.session;
+    let session_adapter = SessionAdapter::new(
+        auth_domain_api.auth_api.clone(),
+        auth_domain_api.audit_api.clone(),
+        session_config.clone(),
+        session_writes,
+        revocations,
+    );
+
+    // The admin API moves off the public router when it has its own listener.
+    let separate_admin = config.listeners.iter().any(|listener| listener.router == RouterKind::Admin);
+    let v1_routes = match kind {
+        RouterKind::Public => v1::get_routes(auth_domain_api.clone(), !separate_admin),
+        RouterKind::Admin => v1::get_admin_routes(auth_domain_api.clone()),
+        RouterKind::Gateway => Router::new(),
+    };
     let api_routes = Router::new().nest("/v1", v1_routes);
-    let auth_routes = auth::get_routes(session_adapter.clone());
     let health_route: Router = Router::new().route("/", get(health::health)).with_state(auth_domain_api.health_api.clone());
-
-    // Generate a cryptographic key to sign the session cookie.
-    let key = Key::from(config.secret_key.as_bytes());
-
+    let metrics = health::Metrics {
+        health_api: auth_domain_api.health_api.clone(),
+        token: config.metrics_token.as_deref().map(Into::into),
+    };
+
+    let auth_routes = auth::get_routes(session_adapter.clone()).merge(forward_auth::get_routes(
+        session_adapter.clone(),
+        config.keys.clone(),
+        config.forward_auth.clone(),
+    ));
+
+    let inactivity_timeout = Duration::try_from(session_config.inactivity_timeout).unwrap_or(Duration::MAX);
+    let secure = session_config.secure.unwrap_or(config.tls.is_some());
     let session_layer = SessionManagerLayer::new(session_adapter.clone())
-        .with_secure(false)
-        .with_expiry(Expiry::OnInactivity(Duration::days(1)))
-        .with_signed(key);
-
+        .with_name(session_config.cookie_name.clone())
+        .with_path(session_config.cookie_path.clone())
+        .with_same_site(session_config.same_site.into())
+        .with_secure(secure)
+        .with_expiry(Expiry::OnInactivity(inactivity_timeout))
+        // Activity extends the session; saves that only do that are deferred by the store.
+        .with_always_save(true)
+        .with_signed(config.keys.active().clone());
+    let session_layer = match session_config.cookie_domain {
+        Some(ref domain) => session_layer.with_domain(domain.clone()),
+        None => session_layer,
+    };
+
+    // axum-login wants the key for the lifetime of the program; built once per listener.
+    let data_key: &'static str = Box::leak(session_config.data_key.clone().into_boxed_str());
     let auth_layer = AuthManagerLayerBuilder::new(session_adapter.clone(), session_layer)
-        .with_data_key("auth-play")
+        .with_data_key(data_key)
         .build();
+    // Cookies signed with a previous key are re-signed on the way in, then sent back.
+    let resign_layer = middleware::from_fn_with_state((config.keys.clone(), Arc::<str>::from(session_config.cookie_name.as_str())), keys::resign);
+    // Cookie sessions are unsealed on the way in, for the session layer to find by id, and sealed on the way out.
+    let cookie_layer = middleware::from_fn_with_state(
+        CookieSessions::new(session_adapter.clone(), config.keys.clone(), secure),
+        session::cookie::exchange,
+    );
+    let binding_layer = middleware::from_fn_with_state(Arc::new(session_config.binding.clone()), session::binding::bind_client);
+    let reissue_layer = middleware::from_fn(keys::reissue);
+    let remember_layer = middleware::from_fn_with_state(session_adapter.clone(), session::remember);
+    let role_change_layer = middleware::from_fn_with_state(session_adapter.clone(), session::rotate_on_role_change);
+    let csrf_layer = middleware::from_fn_with_state(CsrfOrigins::new(config.allowed_origins.clone()), csrf::verify);
+    let security_headers_layer = middleware::from_fn_with_state(SecurityHeaders::new(&config.security_headers), security_headers::apply);
+
+    if kind == RouterKind::Gateway {
+        // Upstreams may be slow or stream, so they are bound by the gateway timeout instead.
+        // Proxied responses keep the upstream's own headers.
+        let gateway = Gateway::new(config.gateway.clone(), &session_config.cookie_name);
+        let local_routes = axum::Router::new()
+            .route("/app", get(static_handler))
+            .route("/app/{*path}", get(static_handler))
+            .nest("/auth", auth_routes.layer(csrf_layer))
+            .layer(security_headers_layer);
+        return axum::Router::new()
+            .route("/", any(gateway::proxy))
+            .route("/{*path}", any(gateway::proxy))
+            .with_state(gateway)
+            .route_layer(login_required!(SessionAdapter, login_url = "/app"))
+            .merge(local_routes)
+            .layer(role_change_layer)
+            .layer(reissue_layer)
+            .layer(remember_layer)
+            .layer(auth_layer)
+            .layer(cookie_layer)
+            .layer(resign_layer)
+            .layer(binding_layer);
+    }
 
-    axum::Router::new()
+    let routes = axum::Router::new()
         .nest("/api", api_routes)
         .nest("/health", health_route)
         .route_layer(login_required!(SessionAdapter, login_url = "/app"))
+        .route("/health/metrics", get(health::metrics).with_state(metrics))
         .nest("/auth", auth_routes)
+        .layer(csrf_layer)
+        .layer(role_change_layer)
+        .layer(reissue_layer)
+        .layer(remember_layer)
         .layer(auth_layer)
-        .layer(TimeoutLayer::new(std::time::Duration::from_secs(2)))
-        .fallback(static_handler)
-}
-
-pub async fn handle(socket: TcpStream, remote_addr: SocketAddr, tower_service: Router<()>, subsys: SubsystemHandle) -> Result<(), ApiError> {
-    let socket = TokioIo::new(socket);
-    let hyper_service = hyper::service::service_fn(move |request: Request<Incoming>| tower_service.clone().call(request));
-    let conn = hyper::server::conn::http1::Builder::new().serve_connection(socket, hyper_service);
-    let mut conn = std::pin::pin!(conn);
-
-    tokio::select! {
-        result = conn.as_mut() => {
-            if let Err(err) = result {
-                tracing::warn!("Failed to serve connection{}: {:#}", remote_addr, err);
-            }
-        }
+        .layer(cookie_layer)
+        .layer(resign_layer)
+        .layer(binding_layer)
+        .layer(TimeoutLayer::new(std::time::Duration::from_secs(2)));
 
-        _ = subsys.on_shutdown_requested() => {
-            tracing::debug!("signal received, starting graceful shutdown");
-        }
-    }
+    let routes = match kind {
+        RouterKind::Admin => routes.fallback(not_found),
+        _ => routes.fallback(static_handler),
+    };
 
-    tracing::debug!("Connection {} closed", remote_addr);
-    Ok(())
+    routes.layer(security_headers_layer)
 }
 
-#[tracing::instrument(level = "trace")]
-async fn static_handler(uri: Uri) -> impl IntoResponse {
+#[tracing::instrument(level = "trace", skip(nonce))]
+async fn static_handler(uri: Uri, nonce: Option<CspNonce>) -> impl IntoResponse {
     let path = uri.path().trim_start_matches('/');
 
     if path.is_empty() || path == INDEX_HTML {
@@ -85,32 +161,43 @@ async fn static_handler(uri: Uri) -> impl IntoResponse {
     let path = path.trim_start_matches("app/");
 
     match Dist::get(path) {
-        Some(content) => {
-            let mime = mime_guess::from_path(path).first_or_octet_stream();
-            ([(header::CONTENT_TYPE, mime.as_ref())], content.data).into_response()
-        }
+        Some(content) => serve_asset(path, content.data, nonce),
         None => {
             if path.contains('.') {
                 tracing::debug!("{} not found", path);
                 return not_found().await;
             }
 
-            index_html().await
+            index_html(nonce).await
         }
     }
 }
 
-#[tracing::instrument(level = "trace")]
-async fn index_html() -> Response<axum::body::Body> {
+#[tracing::instrument(level = "trace", skip(nonce))]
+async fn index_html(nonce: Option<CspNonce>) -> Response<axum::body::Body> {
     match Dist::get(INDEX_HTML) {
-        Some(content) => {
-            let mime = mime_guess::from_path(INDEX_HTML).first_or_octet_stream();
-            ([(header::CONTENT_TYPE, mime.as_ref())], content.data).into_response()
-        }
+        Some(content) => serve_asset(INDEX_HTML, content.data, nonce),
         None => not_found().await,
     }
 }
 
+/// Pages get the request's CSP nonce on their inline scripts and styles,
+/// which makes them uncacheable.
+fn serve_asset(path: &str, data: Cow<'static, [u8]>, nonce: Option<CspNonce>) -> Response<axum::body::Body> {
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+
+    let html = mime.type_() == mime_guess::mime::TEXT && mime.subtype() == mime_guess::mime::HTML;
+
+    match (html, nonce, std::str::from_utf8(&data)) {
+        (true, Some(CspNonce(nonce)), Ok(html)) => (
+            [(header::CONTENT_TYPE, mime.as_ref()), (header::CACHE_CONTROL, "no-store")],
+            security_headers::add_nonce(html, &nonce),
+        )
+            .into_response(),
+        _ => ([(header::CONTENT_TYPE, mime.as_ref())], data).into_response(),
+    }
+}
+
 #[tracing::instrument(level = "trace")]
 async fn not_found() -> Response<axum::body::Body> {
     (StatusCode::NOT_FOUND, "404").into_response()
@@ -1,9 +1,23 @@
+use std::sync::Arc;
+
 use auth_domain_api::HealthApi;
-use auth_utils::arcbox::ArcBox;
-use axum::{extract::State, response::IntoResponse};
-use hyper::StatusCode;
+use auth_utils::{arcbox::ArcBox, hmac};
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use hyper::{header, StatusCode};
+
+use super::{forward_auth::bearer_token, session::adapter::AuthSession};
 
-use super::session::adapter::AuthSession;
+/// What `/health/metrics` reports on, and the bearer token it is read with.
+/// Without a token the endpoint is off.
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    pub health_api: ArcBox<dyn HealthApi>,
+    pub token: Option<Arc<str>>,
+}
 
 #[tracing::instrument(level = "trace", skip(health_api))]
 pub(crate) async fn health(auth_session: AuthSession, State(health_api): State<ArcBox<dyn HealthApi>>) -> impl IntoResponse {
@@ -12,3 +26,86 @@ pub(crate) async fn health(auth_session: AuthSession, State(health_api): State<A
         false => StatusCode::BAD_REQUEST,
     }
 }
+
+/// Cache hits and misses in the Prometheus text format. Scrapers do not
+/// sign in, so this takes the metrics token instead of a session.
+#[tracing::instrument(level = "trace", skip_all)]
+pub(crate) async fn metrics(State(metrics): State<Metrics>, headers: HeaderMap) -> Response {
+    let Some(ref token) = metrics.token else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !bearer_token(&headers).is_some_and(|provided| hmac::tokens_match(token, provided)) {
+        return (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")]).into_response();
+    }
+
+    let caches = metrics.health_api.cache_metrics();
+    let mut body = String::new();
+    let families = [
+        (
+            "auth_play_cache_hits_total",
+            "counter",
+            caches.iter().map(|cache| cache.hits).collect::<Vec<_>>(),
+        ),
+        ("auth_play_cache_misses_total", "counter", caches.iter().map(|cache| cache.misses).collect()),
+        ("auth_play_cache_entries", "gauge", caches.iter().map(|cache| cache.entries).collect()),
+    ];
+    for (name, kind, values) in families {
+        body.push_str(&format!("# TYPE {} {}\n", name, kind));
+        for (cache, value) in caches.iter().zip(values) {
+            body.push_str(&format!("{}{{cache=\"{}\"}} {}\n", name, cache.name, value));
+        }
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use async_trait::async_trait;
+    use auth_domain_api::{CacheMetrics, HealthApi};
+    use auth_utils::arcbox;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use hyper::{header, StatusCode};
+    use tower::ServiceExt;
+
+    use super::{metrics, Metrics};
+
+    struct TestHealthApi;
+
+    #[async_trait]
+    impl HealthApi for TestHealthApi {
+        async fn is_healthy(&self) -> bool {
+            true
+        }
+
+        fn cache_metrics(&self) -> Vec<CacheMetrics> {
+            vec![CacheMetrics {
+                name: "users",
+                hits: 3,
+                misses: 1,
+                entries: 1,
+            }]
+        }
+    }
+
+    async fn status(token: Option<&str>, authorization: Option<&str>) -> StatusCode {
+        let routes = Router::new().route("/metrics", get(metrics)).with_state(Metrics {
+            health_api: arcbox!(TestHealthApi),
+            token: token.map(Into::into),
+        });
+        let mut request = Request::get("/metrics");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+
+        routes.oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn needs_the_metrics_token() {
+        assert_eq!(status(None, Some("Bearer secret")).await, StatusCode::NOT_FOUND);
+        assert_eq!(status(Some("secret"), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("secret"), Some("Bearer guess")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(Some("secret"), Some("Bearer secret")).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tower_sessions::{
+    cookie::{Cookie, CookieJar, Key},
+    Session,
+};
+
+use crate::ApiError;
+
+/// Shortest key `Key::from` accepts.
+const MIN_KEY_LENGTH: usize = 64;
+
+/// Keys that sign the session cookie. New cookies are signed with the active
+/// key. Cookies signed with a previous key are still accepted, and re-signed
+/// with the active key, so the key can be rotated without signing everyone
+/// out.
+#[derive(Clone)]
+pub struct KeyRing {
+    active: Key,
+    previous: Arc<Vec<Key>>,
+}
+
+impl KeyRing {
+    pub fn new(active: &[u8], previous: &[Vec<u8>]) -> Result<Self, ApiError> {
+        Ok(Self {
+            active: create_key(active, "secret key")?,
+            previous: Arc::new(
+                previous
+                    .iter()
+                    .enumerate()
+                    .map(|(i, key)| create_key(key, &format!("previous secret key {}", i + 1)))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    pub(crate) fn active(&self) -> &Key {
+        &self.active
+    }
+
+    /// The value of a cookie signed with any of the keys.
+    pub(crate) fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        std::iter::once(&self.active)
+            .chain(self.previous.iter())
+            .find_map(|key| unsign(key, name, signed))
+    }
+
+    /// Re-sign a cookie signed with a previous key. `None` when it is signed
+    /// with the active key, or with none of them.
+    fn resign(&self, name: &str, signed: &str) -> Option<String> {
+        if unsign(&self.active, name, signed).is_some() {
+            return None;
+        }
+        let value = self.previous.iter().find_map(|key| unsign(key, name, signed))?;
+
+        Some(self.sign(name, &value))
+    }
+
+    /// A cookie value signed with the active key.
+    pub(crate) fn sign(&self, name: &str, value: &str) -> String {
+        let mut jar = CookieJar::new();
+        jar.signed_mut(&self.active).add(Cookie::new(name.to_string(), value.to_string()));
+        jar.get(name).map(|cookie| cookie.value().to_string()).unwrap_or_default()
+    }
+}
+
+impl std::fmt::Debug for KeyRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyRing").field("previous", &self.previous.len()).finish_non_exhaustive()
+    }
+}
+
+fn create_key(key: &[u8], name: &str) -> Result<Key, ApiError> {
+    if key.len() < MIN_KEY_LENGTH {
+        return Err(ApiError::SecretKey(format!(
+            "{} is {} bytes, it must be at least {}",
+            name,
+            key.len(),
+            MIN_KEY_LENGTH
+        )));
+    }
+
+    Ok(Key::from(key))
+}
+
+fn unsign(key: &Key, name: &str, signed: &str) -> Option<String> {
+    let mut jar = CookieJar::new();
+    jar.add_original(Cookie::new(name.to_string(), signed.to_string()));
+
+    jar.signed(key).get(name).map(|cookie| cookie.value().to_string())
+}
+
+/// Marks requests whose session cookie was signed with a previous key.
+#[derive(Debug, Clone)]
+struct Resigned;
+
+/// Swap a session cookie signed with a previous key for one signed with the
+/// active key, before the session layer checks it.
+pub(crate) async fn resign(State((keys, cookie_name)): State<(KeyRing, Arc<str>)>, mut request: Request, next: Next) -> Response {
+    if resign_cookies(&keys, &cookie_name, request.headers_mut()) {
+        request.extensions_mut().insert(Resigned);
+    }
+
+    next.run(request).await
+}
+
+/// Have the session layer send the re-signed cookie back to the browser.
+/// The expiry is kept, including that of "remember me" sessions.
+pub(crate) async fn reissue(session: Session, request: Request, next: Next) -> Response {
+    if request.extensions().get::<Resigned>().is_some() {
+        session.set_expiry(session.expiry());
+    }
+
+    next.run(request).await
+}
+
+/// Rewrite the `Cookie` headers, returning whether the session cookie was re-signed.
+fn resign_cookies(keys: &KeyRing, cookie_name: &str, headers: &mut HeaderMap) -> bool {
+    let mut resigned = false;
+    let values: Vec<HeaderValue> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .map(|value| {
+            let Ok(cookies) = value.to_str() else {
+                return value.clone();
+            };
+            let cookies: Vec<String> = cookies
+                .split(';')
+                .map(str::trim)
+                .map(|cookie| match cookie.split_once('=') {
+                    Some((name, signed)) if name == cookie_name => match keys.resign(name, signed) {
+                        Some(signed) => {
+                            resigned = true;
+                            format!("{}={}", name, signed)
+                        }
+                        None => cookie.to_string(),
+                    },
+                    _ => cookie.to_string(),
+                })
+                .collect();
+
+            HeaderValue::from_str(&cookies.join("; ")).unwrap_or_else(|_| value.clone())
+        })
+        .collect();
+
+    if resigned {
+        headers.remove(header::COOKIE);
+        for value in values {
+            headers.append(header::COOKIE, value);
+        }
+    }
+
+    resigned
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{header, HeaderMap, HeaderValue};
+    use tower_sessions::cookie::{Cookie, CookieJar};
+
+    use super::{resign_cookies, KeyRing};
+
+    fn sign(key: &[u8], value: &str) -> String {
+        let mut jar = CookieJar::new();
+        jar.signed_mut(&tower_sessions::cookie::Key::from(key))
+            .add(Cookie::new("id", value.to_string()));
+        jar.get("id").unwrap().value().to_string()
+    }
+
+    #[test]
+    fn rotates_keys() {
+        let (old, new) = ([1u8; 64], [2u8; 64]);
+        assert!(KeyRing::new(&[1u8; 32], &[]).is_err());
+        assert!(KeyRing::new(&new, &[vec![1u8; 63]]).is_err());
+
+        let keys = KeyRing::new(&new, &[old.to_vec()]).unwrap();
+        assert_eq!(keys.verify("id", &sign(&old, "abc")).as_deref(), Some("abc"));
+        assert_eq!(keys.verify("id", &sign(&new, "abc")).as_deref(), Some("abc"));
+        assert_eq!(keys.verify("id", &sign(&[3u8; 64], "abc")), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_str(&format!("theme=dark; id={}", sign(&old, "abc"))).unwrap());
+        assert!(resign_cookies(&keys, "id", &mut headers));
+        assert_eq!(headers[header::COOKIE], format!("theme=dark; id={}", sign(&new, "abc")));
+        assert!(!resign_cookies(&keys, "id", &mut headers));
+    }
+}
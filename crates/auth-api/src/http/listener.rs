@@ -0,0 +1,297 @@
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    os::{
+        fd::{FromRawFd, IntoRawFd, RawFd},
+        unix::fs::FileTypeExt,
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+use tokio::net::{TcpListener, UnixListener, UnixStream};
+
+use super::server::Listener;
+
+/// First descriptor passed by systemd socket activation.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Where a listener accepts connections.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ListenAddress {
+    /// `host:port` or `tcp://host:port`.
+    Tcp(SocketAddr),
+    /// `unix:///path/to/socket`.
+    Unix(PathBuf),
+    /// `systemd://N`, the N-th socket passed in via `LISTEN_FDS`.
+    Systemd(usize),
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix://") {
+            return match path {
+                "" => Err(format!("Missing socket path - {}", s)),
+                path => Ok(ListenAddress::Unix(PathBuf::from(path))),
+            };
+        }
+        if let Some(index) = s.strip_prefix("systemd://") {
+            return index.parse().map(ListenAddress::Systemd).map_err(|_| format!("Bad socket index - {}", s));
+        }
+
+        let addr = s.strip_prefix("tcp://").unwrap_or(s);
+        addr.parse().map(ListenAddress::Tcp).map_err(|_| format!("Bad listen address - {}", s))
+    }
+}
+
+impl TryFrom<String> for ListenAddress {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(addr) => write!(f, "tcp://{}", addr),
+            ListenAddress::Unix(path) => write!(f, "unix://{}", path.display()),
+            ListenAddress::Systemd(index) => write!(f, "systemd://{}", index),
+        }
+    }
+}
+
+/// Which routes a listener serves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouterKind {
+    /// The application, auth and user API. Includes the admin API unless an
+    /// admin listener is configured.
+    #[default]
+    Public,
+    /// The admin API and health check.
+    Admin,
+    /// Signed in users' requests proxied to the gateway upstreams, next to
+    /// the app and auth routes needed to sign in.
+    Gateway,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListenerConfiguration {
+    pub name: String,
+    pub address: ListenAddress,
+    pub router: RouterKind,
+    /// Terminate TLS on this listener, if a certificate is configured.
+    pub tls: bool,
+    /// Expect a PROXY protocol (v1 or v2) header from a trusted proxy at the
+    /// start of every connection.
+    pub proxy_protocol: bool,
+}
+
+pub(crate) enum BoundListener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl BoundListener {
+    pub(crate) async fn bind(address: &ListenAddress) -> io::Result<Self> {
+        match address {
+            ListenAddress::Tcp(addr) => Ok(BoundListener::Tcp(TcpListener::bind(addr).await?)),
+            ListenAddress::Unix(path) => {
+                remove_stale_socket(path)?;
+                Ok(BoundListener::Unix(UnixListener::bind(path)?))
+            }
+            ListenAddress::Systemd(index) => from_systemd(*index),
+        }
+    }
+}
+
+/// Remove a socket file left over from an unclean exit, which would fail the
+/// bind. Anything else at the path, or a socket something still listens on,
+/// is left alone and refused.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+
+    match std::os::unix::net::UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use by another process", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => std::fs::remove_file(path),
+        Err(err) => Err(err),
+    }
+}
+
+/// Take over a socket passed in by systemd, checking it was meant for us.
+fn from_systemd(index: usize) -> io::Result<BoundListener> {
+    let fd = passed_fd(index, std::env::var("LISTEN_PID").ok(), std::env::var("LISTEN_FDS").ok())?;
+
+    // SAFETY: systemd hands us ownership of descriptors 3..3+LISTEN_FDS, and
+    // each index is bound at most once.
+    unsafe { adopt(fd) }
+}
+
+/// The descriptor of socket `index`, if `LISTEN_PID` and `LISTEN_FDS` say
+/// it was passed to this process.
+fn passed_fd(index: usize, listen_pid: Option<String>, listen_fds: Option<String>) -> io::Result<RawFd> {
+    let not_passed = || io::Error::new(io::ErrorKind::NotFound, format!("No socket {} passed in LISTEN_FDS", index));
+
+    let pid: u32 = listen_pid.and_then(|pid| pid.parse().ok()).ok_or_else(not_passed)?;
+    let count: usize = listen_fds.and_then(|count| count.parse().ok()).ok_or_else(not_passed)?;
+    if pid != std::process::id() || index >= count {
+        return Err(not_passed());
+    }
+
+    Ok(SD_LISTEN_FDS_START + index as RawFd)
+}
+
+/// Own a listening Unix or TCP socket.
+///
+/// # Safety
+///
+/// `fd` must be an open listening socket that nothing else owns or closes.
+unsafe fn adopt(fd: RawFd) -> io::Result<BoundListener> {
+    // SAFETY: the caller hands over ownership of `fd`.
+    let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+    if unix.local_addr().is_ok() {
+        unix.set_nonblocking(true)?;
+        return Ok(BoundListener::Unix(UnixListener::from_std(unix)?));
+    }
+
+    // SAFETY: as above; the descriptor was released by `into_raw_fd`.
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(unix.into_raw_fd()) };
+    tcp.set_nonblocking(true)?;
+    Ok(BoundListener::Tcp(TcpListener::from_std(tcp)?))
+}
+
+impl Listener for UnixListener {
+    type Io = UnixStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, Option<SocketAddr>)> {
+        let (stream, _) = UnixListener::accept(self).await?;
+
+        Ok((stream, None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        os::fd::IntoRawFd,
+        path::{Path, PathBuf},
+    };
+
+    use tokio::net::{TcpStream, UnixStream};
+
+    use super::{adopt, passed_fd, BoundListener, ListenAddress};
+
+    /// A socket path of its own for each test.
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("auth-play-{}-{}.sock", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+
+        path
+    }
+
+    async fn accepts_unix(path: &Path) {
+        let Ok(BoundListener::Unix(listener)) = BoundListener::bind(&ListenAddress::Unix(path.to_path_buf())).await else {
+            panic!("Not bound to {}", path.display());
+        };
+        let (_client, accepted) = tokio::join!(UnixStream::connect(path), listener.accept());
+        accepted.unwrap();
+    }
+
+    #[test]
+    fn parse_address() {
+        assert_eq!("0.0.0.0:8080".parse(), Ok(ListenAddress::Tcp("0.0.0.0:8080".parse().unwrap())));
+        assert_eq!("tcp://[::1]:9090".parse(), Ok(ListenAddress::Tcp("[::1]:9090".parse().unwrap())));
+        assert_eq!(
+            "unix:///run/auth-play.sock".parse(),
+            Ok(ListenAddress::Unix(PathBuf::from("/run/auth-play.sock")))
+        );
+        assert_eq!("systemd://1".parse(), Ok(ListenAddress::Systemd(1)));
+        assert!("unix://".parse::<ListenAddress>().is_err());
+        assert!("localhost".parse::<ListenAddress>().is_err());
+    }
+
+    #[tokio::test]
+    async fn binds_unix_sockets() {
+        let path = socket_path("bind");
+        accepts_unix(&path).await;
+
+        // The first listener is gone, leaving its socket file behind.
+        assert!(path.exists());
+        accepts_unix(&path).await;
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_in_use() {
+        let path = socket_path("in-use");
+        let _live = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let Err(err) = BoundListener::bind(&ListenAddress::Unix(path.clone())).await else {
+            panic!("Bound over a live socket");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::write(&path, "not a socket").unwrap();
+        let Err(err) = BoundListener::bind(&ListenAddress::Unix(path.clone())).await else {
+            panic!("Bound over a file");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checks_listen_fds() {
+        let pid = Some(std::process::id().to_string());
+
+        assert_eq!(passed_fd(0, pid.clone(), Some("2".to_string())).unwrap(), 3);
+        assert_eq!(passed_fd(1, pid.clone(), Some("2".to_string())).unwrap(), 4);
+        assert!(passed_fd(2, pid.clone(), Some("2".to_string())).is_err());
+        assert!(passed_fd(0, pid, None).is_err());
+        // Passed to another process.
+        assert!(passed_fd(0, Some("1".to_string()), Some("2".to_string())).is_err());
+    }
+
+    #[tokio::test]
+    async fn adopts_passed_sockets() {
+        let path = socket_path("adopt");
+        let unix = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        // SAFETY: the descriptor was released by `into_raw_fd`.
+        let Ok(BoundListener::Unix(listener)) = (unsafe { adopt(unix.into_raw_fd()) }) else {
+            panic!("Not adopted as a Unix socket");
+        };
+        let (_client, accepted) = tokio::join!(UnixStream::connect(&path), listener.accept());
+        accepted.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = tcp.local_addr().unwrap();
+        // SAFETY: as above.
+        let Ok(BoundListener::Tcp(listener)) = (unsafe { adopt(tcp.into_raw_fd()) }) else {
+            panic!("Not adopted as a TCP socket");
+        };
+        let (_client, accepted) = tokio::join!(TcpStream::connect(address), listener.accept());
+        accepted.unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+use hyper::{header::HeaderName, HeaderMap};
+use ipnet::IpNet;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+const FORWARDED: HeaderName = HeaderName::from_static("forwarded");
+
+const PROXY_V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const PROXY_V1_MAX_LENGTH: usize = 107;
+
+/// Networks whose forwarding headers are believed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub(crate) fn new(networks: Vec<IpNet>) -> Self {
+        Self(networks)
+    }
+
+    pub(crate) fn is_trusted(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|network| network.contains(&ip))
+    }
+
+    /// A peer without an address is on a local socket and always trusted.
+    fn is_trusted_peer(&self, peer: Option<IpAddr>) -> bool {
+        peer.is_none_or(|ip| self.is_trusted(ip))
+    }
+
+    /// Resolve the client behind `peer`. Forwarding headers are only read
+    /// when `peer` is trusted, and the chain is walked from the nearest hop
+    /// outwards, stopping at the first address that is not a trusted proxy.
+    pub(crate) fn client_ip(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        let peer = peer.map(|ip| ip.to_canonical());
+        if !self.is_trusted_peer(peer) {
+            return peer;
+        }
+
+        self.client_hop(peer, headers).0
+    }
+
+    /// The client behind a trusted `peer`, and how many hops from the
+    /// nearest one it was found at.
+    fn client_hop(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> (Option<IpAddr>, usize) {
+        let mut client = (peer, 0);
+        for (depth, hop) in forwarded_for(headers).into_iter().rev().enumerate() {
+            let Some(hop) = hop else {
+                continue;
+            };
+            let hop = hop.to_canonical();
+            client = (Some(hop), depth);
+            if !self.is_trusted(hop) {
+                break;
+            }
+        }
+
+        client
+    }
+
+    /// The scheme the client used, as reported by a trusted `peer`. Taken
+    /// from the hop `client_ip` settles on, so a client cannot prepend its own.
+    pub(crate) fn scheme(&self, peer: Option<IpAddr>, headers: &HeaderMap, connection_scheme: &'static str) -> &'static str {
+        let peer = peer.map(|ip| ip.to_canonical());
+        if !self.is_trusted_peer(peer) {
+            return connection_scheme;
+        }
+
+        let (_, depth) = self.client_hop(peer, headers);
+        match forwarded_proto(headers, depth).as_deref() {
+            Some("https") => "https",
+            Some("http") => "http",
+            _ => connection_scheme,
+        }
+    }
+}
+
+/// Addresses from `Forwarded`, or `X-Forwarded-For` when that is absent,
+/// ordered client first. Obfuscated or unknown hops are `None`.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    if headers.contains_key(FORWARDED) {
+        return forwarded_params(headers, "for")
+            .iter()
+            .map(|node| node.as_deref().and_then(parse_node))
+            .collect();
+    }
+
+    comma_separated(headers, &X_FORWARDED_FOR).map(parse_node).collect()
+}
+
+/// The proto `depth` hops from the nearest one. Proxies that overwrite
+/// `X-Forwarded-Proto` rather than append to it leave fewer values than
+/// hops, and then the leftmost is the furthest a trusted proxy set.
+fn forwarded_proto(headers: &HeaderMap, depth: usize) -> Option<String> {
+    let protos: Vec<Option<String>> = match headers.contains_key(FORWARDED) {
+        true => forwarded_params(headers, "proto"),
+        false => comma_separated(headers, &X_FORWARDED_PROTO).map(|proto| Some(proto.to_string())).collect(),
+    };
+    let index = protos.len().checked_sub(1)?.saturating_sub(depth);
+
+    protos[index].as_ref().map(|proto| proto.to_ascii_lowercase())
+}
+
+fn comma_separated<'a>(headers: &'a HeaderMap, name: &HeaderName) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+}
+
+/// Values of `name` for each RFC 7239 `Forwarded` element, in order.
+fn forwarded_params(headers: &HeaderMap, name: &str) -> Vec<Option<String>> {
+    comma_separated(headers, &FORWARDED)
+        .map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.trim().eq_ignore_ascii_case(name).then(|| value.trim().trim_matches('"').to_string())
+            })
+        })
+        .collect()
+}
+
+/// Parse a node such as `192.0.2.60`, `192.0.2.60:4711`, `[2001:db8::1]` or
+/// `[2001:db8::1]:4711`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+
+    node.strip_prefix('[')?.split(']').next()?.parse().ok()
+}
+
+/// What a PROXY protocol header said about the connection.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ProxyHeader {
+    /// The original client address.
+    Proxied(SocketAddr),
+    /// A connection made by the proxy itself, e.g. a health check.
+    Local,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Bad PROXY protocol header - {}", message))
+}
+
+/// Read a PROXY protocol v1 or v2 header, consuming exactly its bytes.
+pub(crate) async fn read_proxy_header<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<ProxyHeader> {
+    // Every valid header, v1 or v2, is at least this long.
+    let mut prefix = [0u8; 12];
+    stream.read_exact(&mut prefix).await?;
+
+    if prefix == PROXY_V2_SIGNATURE {
+        return read_proxy_v2(stream).await;
+    }
+    if prefix.starts_with(b"PROXY ") {
+        return read_proxy_v1(stream, &prefix).await;
+    }
+
+    Err(invalid("missing signature"))
+}
+
+async fn read_proxy_v1<S: AsyncRead + Unpin>(stream: &mut S, prefix: &[u8]) -> io::Result<ProxyHeader> {
+    let mut line = prefix.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= PROXY_V1_MAX_LENGTH {
+            return Err(invalid("v1 header too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("v1 header not ASCII"))?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(ProxyHeader::Local),
+        ["PROXY", "TCP4" | "TCP6", source, _destination, source_port, _destination_port] => {
+            let ip: IpAddr = source.parse().map_err(|_| invalid("v1 source address"))?;
+            let port: u16 = source_port.parse().map_err(|_| invalid("v1 source port"))?;
+
+            Ok(ProxyHeader::Proxied(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("v1 header malformed")),
+    }
+}
+
+async fn read_proxy_v2<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<ProxyHeader> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("v2 version"));
+    }
+    match version_command & 0x0f {
+        0x0 => return Ok(ProxyHeader::Local),
+        0x1 => {}
+        _ => return Err(invalid("v2 command")),
+    }
+
+    match family >> 4 {
+        // AF_INET: source, destination, source port, destination port.
+        0x1 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            let port = u16::from_be_bytes([body[8], body[9]]);
+
+            Ok(ProxyHeader::Proxied(SocketAddr::new(ip.into(), port)))
+        }
+        0x2 if body.len() >= 36 => {
+            let octets: [u8; 16] = body[0..16].try_into().expect("slice is 16 bytes");
+            let port = u16::from_be_bytes([body[32], body[33]]);
+
+            Ok(ProxyHeader::Proxied(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        // AF_UNSPEC and AF_UNIX carry no usable client address.
+        0x0 | 0x3 => Ok(ProxyHeader::Local),
+        _ => Err(invalid("v2 address block")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use hyper::{header::HeaderValue, HeaderMap};
+
+    use super::{read_proxy_header, ProxyHeader, TrustedProxies};
+
+    fn trusted() -> TrustedProxies {
+        TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()])
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn resolves_x_forwarded_for() {
+        let proxies = trusted();
+        let forwarded = headers(&[("x-forwarded-for", "198.51.100.7, 203.0.113.9, 10.1.1.1")]);
+
+        // Untrusted peers cannot spoof their address.
+        assert_eq!(proxies.client_ip(ip("192.0.2.1"), &forwarded), ip("192.0.2.1"));
+        // The nearest untrusted hop is the client, not the leftmost entry.
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &forwarded), ip("203.0.113.9"));
+        // IPv4-mapped peers match IPv4 networks.
+        assert_eq!(proxies.client_ip(ip("::ffff:10.0.0.1"), &forwarded), ip("203.0.113.9"));
+        assert_eq!(proxies.client_ip(ip("10.0.0.1"), &HeaderMap::new()), ip("10.0.0.1"));
+        // Local socket peers are trusted.
+        assert_eq!(proxies.client_ip(None, &forwarded), ip("203.0.113.9"));
+        assert_eq!(proxies.client_ip(None, &HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn resolves_forwarded() {
+        let proxies = trusted();
+        let forwarded = headers(&[
+            ("forwarded", r#"for="[2001:db8:cafe::17]:4711";proto=https"#),
+            ("forwarded", "for=10.2.2.2;proto=http, for=_hidden"),
+            ("x-forwarded-for", "198.51.100.7"),
+        ]);
+
+        assert_eq!(proxies.client_ip(ip("::1"), &forwarded), ip("2001:db8:cafe::17"));
+        assert_eq!(proxies.scheme(ip("::1"), &forwarded, "http"), "https");
+        assert_eq!(proxies.scheme(ip("192.0.2.1"), &forwarded, "http"), "http");
+        assert_eq!(proxies.scheme(ip("10.0.0.1"), &headers(&[("x-forwarded-proto", "https")]), "http"), "https");
+    }
+
+    #[test]
+    fn ignores_spoofed_protos() {
+        let proxies = trusted();
+        // The client sent `https` itself; the trusted proxy appended `http`.
+        let spoofed = headers(&[("x-forwarded-for", "198.51.100.7, 203.0.113.9"), ("x-forwarded-proto", "https, http")]);
+        assert_eq!(proxies.scheme(ip("10.0.0.1"), &spoofed, "http"), "http");
+
+        let spoofed = headers(&[("forwarded", "for=198.51.100.7;proto=https, for=203.0.113.9;proto=http")]);
+        assert_eq!(proxies.scheme(ip("10.0.0.1"), &spoofed, "http"), "http");
+
+        // Two trusted proxies, the outer one setting the only proto.
+        let chained = headers(&[("x-forwarded-for", "203.0.113.9, 10.1.1.1"), ("x-forwarded-proto", "https")]);
+        assert_eq!(proxies.scheme(ip("10.0.0.1"), &chained, "http"), "https");
+    }
+
+    #[tokio::test]
+    async fn reads_proxy_v1() {
+        let mut stream: &[u8] = b"PROXY TCP4 198.51.100.22 203.0.113.7 35646 443\r\nGET / HTTP/1.1\r\n";
+        let header = read_proxy_header(&mut stream).await.unwrap();
+
+        assert_eq!(header, ProxyHeader::Proxied("198.51.100.22:35646".parse().unwrap()));
+        assert_eq!(stream, b"GET / HTTP/1.1\r\n");
+
+        let mut stream: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_proxy_header(&mut stream).await.unwrap(), ProxyHeader::Local);
+
+        let mut stream: &[u8] = b"GET / HTTP/1.1\r\nhost: test\r\n\r\n";
+        assert!(read_proxy_header(&mut stream).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_proxy_v2() {
+        let mut bytes = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+        bytes.extend_from_slice(&[0x21, 0x11, 0x00, 0x0c]);
+        bytes.extend_from_slice(&[198, 51, 100, 22, 203, 0, 113, 7]);
+        bytes.extend_from_slice(&35646u16.to_be_bytes());
+        bytes.extend_from_slice(&443u16.to_be_bytes());
+        bytes.extend_from_slice(b"GET");
+        let mut stream: &[u8] = &bytes;
+
+        let header = read_proxy_header(&mut stream).await.unwrap();
+        assert_eq!(header, ProxyHeader::Proxied("198.51.100.22:35646".parse().unwrap()));
+        assert_eq!(stream, b"GET");
+
+        let mut local = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        let mut stream: &[u8] = &local;
+        assert_eq!(read_proxy_header(&mut stream).await.unwrap(), ProxyHeader::Local);
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use auth_utils::hmac;
+use axum::{
+    extract::{OptionalFromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use super::context::ClientAddr;
+
+static PERMISSIONS_POLICY: HeaderName = HeaderName::from_static("permissions-policy");
+
+/// Headers added to every response the server generates itself. Each is
+/// left out when unset.
+#[derive(Debug, Clone)]
+pub struct SecurityHeadersConfiguration {
+    /// `Strict-Transport-Security`, only sent over HTTPS.
+    pub hsts: Option<String>,
+    /// `X-Content-Type-Options: nosniff`.
+    pub content_type_options: bool,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+    /// CSP `frame-ancestors`, e.g. `'none'` or `'self' https://portal.example.com`.
+    pub frame_ancestors: Option<String>,
+    /// Strict Content-Security-Policy with a per-request script and style nonce.
+    pub content_security_policy: bool,
+}
+
+impl Default for SecurityHeadersConfiguration {
+    fn default() -> Self {
+        Self {
+            hsts: Some("max-age=63072000; includeSubDomains".to_string()),
+            content_type_options: true,
+            referrer_policy: Some("strict-origin-when-cross-origin".to_string()),
+            permissions_policy: Some("camera=(), microphone=(), geolocation=(), payment=()".to_string()),
+            frame_ancestors: Some("'none'".to_string()),
+            content_security_policy: true,
+        }
+    }
+}
+
+/// Nonce of the current request's Content-Security-Policy.
+#[derive(Debug, Clone)]
+pub(crate) struct CspNonce(pub String);
+
+impl<S> OptionalFromRequestParts<S> for CspNonce
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<CspNonce>().cloned())
+    }
+}
+
+/// Header values, parsed once.
+#[derive(Debug, Clone)]
+pub(crate) struct SecurityHeaders {
+    hsts: Option<HeaderValue>,
+    content_type_options: bool,
+    referrer_policy: Option<HeaderValue>,
+    permissions_policy: Option<HeaderValue>,
+    frame_ancestors: Option<String>,
+    frame_options: Option<HeaderValue>,
+    content_security_policy: bool,
+}
+
+impl SecurityHeaders {
+    pub(crate) fn new(config: &SecurityHeadersConfiguration) -> Arc<Self> {
+        let value = |value: &Option<String>| value.as_deref().and_then(|value| HeaderValue::from_str(value).ok());
+        // For browsers that predate frame-ancestors.
+        let frame_options = match config.frame_ancestors.as_deref().map(str::trim) {
+            Some("'none'") => Some(HeaderValue::from_static("DENY")),
+            Some("'self'") => Some(HeaderValue::from_static("SAMEORIGIN")),
+            _ => None,
+        };
+
+        Arc::new(Self {
+            hsts: value(&config.hsts),
+            content_type_options: config.content_type_options,
+            referrer_policy: value(&config.referrer_policy),
+            permissions_policy: value(&config.permissions_policy),
+            frame_ancestors: config.frame_ancestors.clone(),
+            frame_options,
+            content_security_policy: config.content_security_policy,
+        })
+    }
+
+    fn content_security_policy(&self, nonce: &str) -> Option<HeaderValue> {
+        let mut directives = Vec::new();
+        if self.content_security_policy {
+            directives.extend([
+                "default-src 'self'".to_string(),
+                format!("script-src 'self' 'nonce-{}' 'strict-dynamic'", nonce),
+                format!("style-src 'self' 'nonce-{}'", nonce),
+                "img-src 'self' data:".to_string(),
+                "connect-src 'self'".to_string(),
+                "object-src 'none'".to_string(),
+                "base-uri 'none'".to_string(),
+                "form-action 'self'".to_string(),
+            ]);
+        }
+        if let Some(ref frame_ancestors) = self.frame_ancestors {
+            directives.push(format!("frame-ancestors {}", frame_ancestors));
+        }
+
+        match directives.is_empty() {
+            true => None,
+            false => HeaderValue::from_str(&directives.join("; ")).ok(),
+        }
+    }
+
+    fn apply(&self, headers: &mut HeaderMap, nonce: &str, https: bool) {
+        let mut set = |name: HeaderName, value: Option<HeaderValue>| {
+            if let Some(value) = value {
+                headers.entry(name).or_insert(value);
+            }
+        };
+
+        if https {
+            set(header::STRICT_TRANSPORT_SECURITY, self.hsts.clone());
+        }
+        if self.content_type_options {
+            set(header::X_CONTENT_TYPE_OPTIONS, Some(HeaderValue::from_static("nosniff")));
+        }
+        set(header::REFERRER_POLICY, self.referrer_policy.clone());
+        set(PERMISSIONS_POLICY.clone(), self.permissions_policy.clone());
+        set(header::X_FRAME_OPTIONS, self.frame_options.clone());
+        set(header::CONTENT_SECURITY_POLICY, self.content_security_policy(nonce));
+    }
+}
+
+/// Give the request a CSP nonce and add the security headers to its response.
+pub(crate) async fn apply(State(security_headers): State<Arc<SecurityHeaders>>, client_addr: ClientAddr, mut request: Request, next: Next) -> Response {
+    let nonce = hmac::random_token(16);
+    request.extensions_mut().insert(CspNonce(nonce.clone()));
+
+    let mut response = next.run(request).await;
+    security_headers.apply(response.headers_mut(), &nonce, client_addr.scheme == "https");
+
+    response
+}
+
+/// Add the nonce to the inline scripts and styles of a page.
+pub(crate) fn add_nonce(html: &str, nonce: &str) -> String {
+    html.replace("<script", &format!("<script nonce=\"{}\"", nonce))
+        .replace("<style", &format!("<style nonce=\"{}\"", nonce))
+        .replace("<link rel=\"modulepreload\"", &format!("<link rel=\"modulepreload\" nonce=\"{}\"", nonce))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::HeaderMap;
+
+    use super::{add_nonce, SecurityHeaders, SecurityHeadersConfiguration};
+
+    #[test]
+    fn sets_headers() {
+        let security_headers = SecurityHeaders::new(&SecurityHeadersConfiguration::default());
+
+        let mut headers = HeaderMap::new();
+        security_headers.apply(&mut headers, "abc", false);
+        assert!(!headers.contains_key("strict-transport-security"));
+        assert_eq!(headers["x-content-type-options"], "nosniff");
+        assert_eq!(headers["x-frame-options"], "DENY");
+        let csp = headers["content-security-policy"].to_str().unwrap();
+        assert!(csp.contains("script-src 'self' 'nonce-abc'"));
+        assert!(csp.contains("frame-ancestors 'none'"));
+        assert!(!csp.contains("unsafe-inline"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("referrer-policy", "no-referrer".parse().unwrap());
+        security_headers.apply(&mut headers, "abc", true);
+        assert!(headers.contains_key("strict-transport-security"));
+        assert_eq!(headers["referrer-policy"], "no-referrer");
+    }
+
+    #[test]
+    fn adds_nonce() {
+        let html = r#"<head><style>a{}</style></head><body><script>start()</script></body>"#;
+
+        assert_eq!(
+            add_nonce(html, "abc"),
+            r#"<head><style nonce="abc">a{}</style></head><body><script nonce="abc">start()</script></body>"#
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use std::{fmt, future::Future, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{extract::Request, Router};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::Semaphore,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tower::Service;
+
+use super::{
+    context::ConnectionInfo,
+    proxy::{read_proxy_header, ProxyHeader, TrustedProxies},
+    Configuration, ListenerConfiguration,
+};
+
+const ACCEPT_BACKOFF_MIN: Duration = Duration::from_millis(5);
+const ACCEPT_BACKOFF_MAX: Duration = Duration::from_secs(1);
+
+/// Source of incoming connections, so the accept loop can be driven by
+/// something other than a real socket. The peer address is `None` for
+/// transports without one, such as Unix domain sockets.
+pub(crate) trait Listener: Send + 'static {
+    type Io: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    fn accept(&mut self) -> impl Future<Output = io::Result<(Self::Io, Option<SocketAddr>)>> + Send;
+}
+
+impl Listener for TcpListener {
+    type Io = tokio::net::TcpStream;
+
+    async fn accept(&mut self) -> io::Result<(Self::Io, Option<SocketAddr>)> {
+        let (stream, remote_addr) = TcpListener::accept(self).await?;
+
+        Ok((stream, Some(remote_addr)))
+    }
+}
+
+struct Peer(Option<SocketAddr>);
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(addr) => write!(f, "{}", addr),
+            None => f.write_str("local peer"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ServerOptions {
+    pub builder: auto::Builder<TokioExecutor>,
+    pub tls_acceptor: Option<TlsAcceptor>,
+    /// Connections served at once; further clients wait in the listen backlog.
+    pub max_connections: usize,
+    /// Time allowed for the PROXY protocol header and TLS handshake.
+    pub handshake_timeout: Duration,
+    /// Time in-flight requests get to finish once shutdown starts.
+    pub drain_timeout: Duration,
+    /// Expect a PROXY protocol header at the start of each connection.
+    pub proxy_protocol: bool,
+    pub trusted_proxies: Arc<TrustedProxies>,
+}
+
+impl ServerOptions {
+    pub(crate) fn new(config: &Configuration, listener: &ListenerConfiguration, tls_acceptor: Option<TlsAcceptor>) -> Self {
+        Self {
+            builder: connection_builder(config),
+            tls_acceptor,
+            max_connections: config.max_connections,
+            handshake_timeout: config.header_read_timeout,
+            drain_timeout: config.drain_timeout,
+            proxy_protocol: listener.proxy_protocol,
+            trusted_proxies: Arc::new(TrustedProxies::new(config.trusted_proxies.clone())),
+        }
+    }
+}
+
+/// Connection builder speaking HTTP/1.1 and HTTP/2. The protocol is picked
+/// per connection from the client preface, so both ALPN over TLS and
+/// prior-knowledge h2c work.
+fn connection_builder(config: &Configuration) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.keep_alive)
+        .header_read_timeout(config.header_read_timeout);
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(config.max_concurrent_streams)
+        .keep_alive_interval(config.keep_alive_interval);
+
+    builder
+}
+
+/// Errors that only concern the connection being accepted, not the listener.
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused | io::ErrorKind::Interrupted
+    )
+}
+
+/// Accept and serve connections until `shutdown` is cancelled, then wait for
+/// the open connections to drain.
+pub(crate) async fn serve<L: Listener>(mut listener: L, routes: Router<()>, options: ServerOptions, shutdown: CancellationToken) {
+    let slots = Arc::new(Semaphore::new(options.max_connections));
+    let connections = TaskTracker::new();
+    let mut backoff = ACCEPT_BACKOFF_MIN;
+
+    loop {
+        // Hold a slot before accepting, so a full server leaves new clients
+        // queued in the kernel rather than accepting and stalling them.
+        let permit = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            permit = slots.clone().acquire_owned() => permit.expect("semaphore is never closed"),
+        };
+
+        let result = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            result = listener.accept() => result,
+        };
+        let (io, remote_addr) = match result {
+            Ok(accepted) => {
+                backoff = ACCEPT_BACKOFF_MIN;
+                accepted
+            }
+            Err(err) if is_connection_error(&err) => {
+                tracing::debug!("Failed to accept connection: {}", err);
+                continue;
+            }
+            Err(err) => {
+                // Typically EMFILE/ENFILE: wait for descriptors to free up.
+                tracing::warn!("Failed to accept connection, retrying in {:?}: {}", backoff, err);
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+                backoff = (backoff * 2).min(ACCEPT_BACKOFF_MAX);
+                continue;
+            }
+        };
+
+        tracing::debug!("Connection {} accepted", Peer(remote_addr));
+        let routes = routes.clone();
+        let options = options.clone();
+        let shutdown = shutdown.clone();
+        connections.spawn(async move {
+            let _permit = permit;
+            start_connection(io, remote_addr, routes, &options, shutdown).await;
+        });
+    }
+
+    connections.close();
+    tracing::debug!("Draining {} connections", connections.len());
+    connections.wait().await;
+}
+
+/// Read the PROXY protocol header, if expected, and complete the TLS
+/// handshake, if any, before serving the connection.
+async fn start_connection<S>(mut io: S, remote_addr: Option<SocketAddr>, routes: Router<()>, options: &ServerOptions, shutdown: CancellationToken)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut remote_addr = remote_addr;
+    if options.proxy_protocol {
+        if remote_addr.is_some_and(|addr| !options.trusted_proxies.is_trusted(addr.ip())) {
+            tracing::debug!("Rejected PROXY protocol connection from untrusted {}", Peer(remote_addr));
+            return;
+        }
+        match tokio::time::timeout(options.handshake_timeout, read_proxy_header(&mut io)).await {
+            Ok(Ok(ProxyHeader::Proxied(client_addr))) => remote_addr = Some(client_addr),
+            Ok(Ok(ProxyHeader::Local)) => {}
+            Ok(Err(err)) => {
+                tracing::debug!("PROXY protocol from {} failed: {}", Peer(remote_addr), err);
+                return;
+            }
+            Err(_) => {
+                tracing::debug!("PROXY protocol from {} timed out", Peer(remote_addr));
+                return;
+            }
+        }
+    }
+
+    match options.tls_acceptor {
+        Some(ref acceptor) => match tokio::time::timeout(options.handshake_timeout, acceptor.accept(io)).await {
+            Ok(Ok(stream)) => serve_connection(stream, remote_addr, routes, options, shutdown).await,
+            Ok(Err(err)) => tracing::debug!("TLS handshake with {} failed: {}", Peer(remote_addr), err),
+            Err(_) => tracing::debug!("TLS handshake with {} timed out", Peer(remote_addr)),
+        },
+        None => serve_connection(io, remote_addr, routes, options, shutdown).await,
+    }
+}
+
+async fn serve_connection<S>(socket: S, remote_addr: Option<SocketAddr>, routes: Router<()>, options: &ServerOptions, shutdown: CancellationToken)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let socket = TokioIo::new(socket);
+    let connection = ConnectionInfo {
+        peer: remote_addr,
+        tls: options.tls_acceptor.is_some(),
+        trusted_proxies: options.trusted_proxies.clone(),
+    };
+    let hyper_service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+        request.extensions_mut().insert(connection.clone());
+        routes.clone().call(request)
+    });
+    let conn = options.builder.serve_connection_with_upgrades(socket, hyper_service);
+    let mut conn = std::pin::pin!(conn);
+
+    let result = tokio::select! {
+        result = conn.as_mut() => result,
+
+        _ = shutdown.cancelled() => {
+            // Finish in-flight requests (and send GOAWAY on HTTP/2) but take no new ones.
+            conn.as_mut().graceful_shutdown();
+            match tokio::time::timeout(options.drain_timeout, conn.as_mut()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::debug!("Connection {} did not drain in time", Peer(remote_addr));
+                    Ok(())
+                }
+            }
+        }
+    };
+    if let Err(err) = result {
+        tracing::warn!("Failed to serve connection {}: {:#}", Peer(remote_addr), err);
+    }
+
+    tracing::debug!("Connection {} closed", Peer(remote_addr));
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::VecDeque,
+        io,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use axum::{routing::get, Router};
+    use hyper_util::{
+        rt::{TokioExecutor, TokioTimer},
+        server::conn::auto,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+    use tokio_util::sync::CancellationToken;
+
+    use super::{serve, Listener, ServerOptions};
+
+    fn options(max_connections: usize, drain_timeout: Duration) -> ServerOptions {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder.http1().timer(TokioTimer::new());
+
+        ServerOptions {
+            builder,
+            tls_acceptor: None,
+            max_connections,
+            handshake_timeout: Duration::from_secs(1),
+            drain_timeout,
+            proxy_protocol: false,
+            trusted_proxies: Default::default(),
+        }
+    }
+
+    fn routes() -> Router<()> {
+        Router::new().route("/", get(|| async { "ok" })).route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                "slow"
+            }),
+        )
+    }
+
+    async fn start(max_connections: usize, drain_timeout: Duration) -> (SocketAddr, CancellationToken, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+        let server = tokio::spawn(serve(listener, routes(), options(max_connections, drain_timeout), shutdown.clone()));
+
+        (addr, shutdown, server)
+    }
+
+    async fn get_request(stream: &mut TcpStream, path: &str) -> io::Result<String> {
+        let request = format!("GET {} HTTP/1.1\r\nhost: test\r\nconnection: close\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+
+        Ok(response)
+    }
+
+    /// Fails every accept with the queued errors before handing out a single
+    /// in-memory connection.
+    struct FailingListener {
+        errors: VecDeque<io::Error>,
+        attempts: Arc<AtomicUsize>,
+        connection: Option<DuplexStream>,
+    }
+
+    impl Listener for FailingListener {
+        type Io = DuplexStream;
+
+        async fn accept(&mut self) -> io::Result<(Self::Io, Option<SocketAddr>)> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if let Some(err) = self.errors.pop_front() {
+                return Err(err);
+            }
+            match self.connection.take() {
+                Some(connection) => Ok((connection, None)),
+                None => std::future::pending().await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn survives_accept_errors() {
+        let (mut client, server_io) = tokio::io::duplex(4096);
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let listener = FailingListener {
+            errors: (0..3)
+                .map(|_| io::Error::from_raw_os_error(24))
+                .chain([io::ErrorKind::ConnectionAborted.into()])
+                .collect(),
+            attempts: attempts.clone(),
+            connection: Some(server_io),
+        };
+        let shutdown = CancellationToken::new();
+        let started = tokio::time::Instant::now();
+        let server = tokio::spawn(serve(listener, routes(), options(8, Duration::from_secs(1)), shutdown.clone()));
+
+        client.write_all(b"GET / HTTP/1.1\r\nhost: test\r\nconnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("ok"));
+
+        // Three EMFILEs back off 5 + 10 + 20ms; the aborted connection does not.
+        // The loop is then waiting on a further accept.
+        assert!(started.elapsed() >= Duration::from_millis(35));
+        assert!(attempts.load(Ordering::SeqCst) >= 5);
+
+        shutdown.cancel();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn caps_connections() {
+        let (addr, shutdown, server) = start(1, Duration::from_secs(1)).await;
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(b"GET / HTTP/1.1\r\nhost: test\r\n\r\n").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let n = first.read(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200"));
+
+        // The first connection is kept alive, so the second is not served...
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        let pending = tokio::time::timeout(Duration::from_millis(200), get_request(&mut second, "/")).await;
+        assert!(pending.is_err());
+
+        // ...until it closes.
+        drop(first);
+        let mut third = TcpStream::connect(addr).await.unwrap();
+        drop(second);
+        let response = tokio::time::timeout(Duration::from_secs(2), get_request(&mut third, "/"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        shutdown.cancel();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn drains_in_flight_requests() {
+        let (addr, shutdown, server) = start(8, Duration::from_secs(2)).await;
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let request = tokio::spawn(async move { get_request(&mut client, "/slow").await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        shutdown.cancel();
+        let response = request.await.unwrap().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("slow"));
+        server.await.unwrap();
+
+        // No longer accepting.
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cuts_connections_after_drain_deadline() {
+        let (addr, shutdown, server) = start(8, Duration::from_millis(50)).await;
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let request = tokio::spawn(async move { get_request(&mut client, "/slow").await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        shutdown.cancel();
+        tokio::time::timeout(Duration::from_millis(200), server).await.unwrap().unwrap();
+        let response = request.await.unwrap();
+        assert!(!response.map(|response| response.starts_with("HTTP/1.1 200")).unwrap_or(false));
+    }
+}
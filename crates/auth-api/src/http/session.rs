@@ -1,3 +1,158 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use tower_sessions::{cookie::time::OffsetDateTime, Expiry, Session};
+
 pub(crate) mod adapter;
+pub(crate) mod binding;
+pub(crate) mod cookie;
+pub(crate) mod encryption;
+pub(crate) mod writes;
 
 pub(crate) use adapter::SessionAdapter;
+use adapter::{AuthSession, REMEMBER_KEY, ROLES_KEY, STARTED_AT_KEY};
+pub use binding::{DriftPolicy, SessionBindingConfiguration};
+pub use encryption::SessionEncryption;
+
+/// `SameSite` attribute of the session cookie.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    #[default]
+    Strict,
+    Lax,
+    None,
+}
+
+impl From<SameSite> for tower_sessions::cookie::SameSite {
+    fn from(same_site: SameSite) -> Self {
+        match same_site {
+            SameSite::Strict => tower_sessions::cookie::SameSite::Strict,
+            SameSite::Lax => tower_sessions::cookie::SameSite::Lax,
+            SameSite::None => tower_sessions::cookie::SameSite::None,
+        }
+    }
+}
+
+/// Where sessions are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStorage {
+    /// In the database, the cookie holding only the session id.
+    #[default]
+    Database,
+    /// Sealed in the cookie, with only revocations in the database. Needs
+    /// session encryption.
+    Cookie,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionConfiguration {
+    pub storage: SessionStorage,
+    pub cookie_name: String,
+    pub cookie_domain: Option<String>,
+    pub cookie_path: String,
+    pub same_site: SameSite,
+    /// Mark the cookie `Secure`. Follows whether TLS is configured when unset.
+    pub secure: Option<bool>,
+    /// Sessions end after this long without a request.
+    pub inactivity_timeout: Duration,
+    /// Sessions end this long after sign in, however active.
+    pub absolute_lifetime: Duration,
+    /// Lifetime of "remember me" sessions, which survive closing the browser
+    /// and do not time out on inactivity.
+    pub remember_lifetime: Duration,
+    /// Key under which axum-login stores its authentication data in the session.
+    pub data_key: String,
+    /// Binding of sessions to the client that signed in.
+    pub binding: SessionBindingConfiguration,
+    /// Encryption of session data at rest. Stored in the clear when unset.
+    pub encryption: Option<SessionEncryption>,
+    /// Largest cookie, name and value, a cookie session may take. Saving a
+    /// larger session fails.
+    pub max_cookie_size: usize,
+    /// How often cookie sessions refresh the revocations other instances made.
+    pub revocation_refresh: Duration,
+    /// How often the extended expiries of stored sessions that are otherwise
+    /// unchanged are written, batched.
+    pub extension_interval: Duration,
+    /// Stored sessions remembered to tell which saves can be skipped, at most.
+    pub write_capacity: u64,
+}
+
+impl Default for SessionConfiguration {
+    fn default() -> Self {
+        Self {
+            storage: SessionStorage::Database,
+            cookie_name: "id".to_string(),
+            cookie_domain: None,
+            cookie_path: "/".to_string(),
+            same_site: SameSite::Strict,
+            secure: None,
+            inactivity_timeout: Duration::from_secs(24 * 60 * 60),
+            absolute_lifetime: Duration::from_secs(7 * 24 * 60 * 60),
+            remember_lifetime: Duration::from_secs(30 * 24 * 60 * 60),
+            data_key: "auth-play".to_string(),
+            binding: SessionBindingConfiguration::default(),
+            encryption: None,
+            max_cookie_size: 4000,
+            revocation_refresh: Duration::from_secs(10),
+            extension_interval: Duration::from_secs(30),
+            write_capacity: 100_000,
+        }
+    }
+}
+
+/// Rotate the session id when the signed in user's roles have changed since
+/// it was last rotated.
+pub(crate) async fn rotate_on_role_change(
+    State(session_adapter): State<SessionAdapter>,
+    auth_session: AuthSession,
+    session: Session,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ref user) = auth_session.user {
+        let roles = session.get::<Vec<String>>(ROLES_KEY).await.ok().flatten();
+        if roles.as_ref() != Some(&user.roles) {
+            // Sessions from before roles were recorded just start recording them.
+            if roles.is_some() {
+                tracing::debug!("Roles of user {} changed, rotating session id", user.id);
+                if let Err(err) = session_adapter.cycle_id(&session).await {
+                    tracing::warn!("Couldn't rotate session id - {}", err);
+                }
+            }
+            let _ = session.insert(ROLES_KEY, &user.roles).await;
+        }
+    }
+
+    next.run(request).await
+}
+
+/// When a "remember me" session ends. `None` for other sessions.
+async fn remembered_until(session: &Session, config: &SessionConfiguration) -> Option<OffsetDateTime> {
+    if !session.get::<bool>(REMEMBER_KEY).await.ok().flatten().unwrap_or_default() {
+        return None;
+    }
+    let started_at = session.get::<i64>(STARTED_AT_KEY).await.ok().flatten()?;
+    let started_at = OffsetDateTime::from_unix_timestamp_nanos(started_at as i128 * 1_000_000).ok()?;
+    let lifetime = tower_sessions::cookie::time::Duration::try_from(config.remember_lifetime).unwrap_or(tower_sessions::cookie::time::Duration::MAX);
+
+    Some(started_at.saturating_add(lifetime))
+}
+
+/// Keep "remember me" sessions, and their persistent cookie, until the end of
+/// their lifetime. The session layer gives every session it loads the
+/// inactivity expiry, so theirs is set again on each request.
+pub(crate) async fn remember(State(session_adapter): State<SessionAdapter>, session: Session, request: Request, next: Next) -> Response {
+    if let Some(until) = remembered_until(&session, &session_adapter.config).await {
+        session.set_expiry(Some(Expiry::AtDateTime(until)));
+    }
+
+    next.run(request).await
+}
@@ -1,24 +1,69 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use auth_domain_api::AuthApi;
-use auth_domain_models::auth::{NewSession, Session};
+use auth_domain_api::{AuditApi, AuthApi, UserInfo};
+use auth_domain_models::auth::SessionRevocation;
+use auth_domain_models::{
+    audit::{AuditEventKind, AuditOutcome, NewAuditEvent, RequestContext},
+    auth::{NewSession, Session, ADMIN_ROLE},
+};
 use auth_utils::arcbox::ArcBox;
 use axum_login::{AuthUser, AuthnBackend, UserId};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tower_sessions::{
-    session::{Id, Record},
-    session_store, SessionStore,
+    session::{self, Id, Record},
+    session_store, Session as HttpSession, SessionStore,
 };
 use uuid::Uuid;
 
+use super::{
+    binding::{self, DriftPolicy, Fingerprint},
+    cookie::{self, Revocations},
+    encryption::Sealed,
+    writes::{self, SessionWrites},
+    SessionConfiguration, SessionStorage,
+};
 use crate::ApiError;
 
+/// Session key of the time the session started, in milliseconds since the epoch.
+pub(crate) const STARTED_AT_KEY: &str = "started_at";
+/// Session key set for "remember me" sessions.
+pub(crate) const REMEMBER_KEY: &str = "remember";
+/// Session key of the user's roles when the session id was last rotated.
+pub(crate) const ROLES_KEY: &str = "roles";
+/// Session key of the fingerprint of the client that signed in.
+pub(crate) const FINGERPRINT_KEY: &str = "fingerprint";
+/// Session key of the id a session was moved to by [`SessionAdapter::cycle_id`],
+/// until it is saved under it.
+const CYCLED_ID_KEY: &str = "cycled_id";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub name: String,
     pub email: String,
     pub password_sha: String,
+    pub roles: Vec<String>,
+}
+
+impl User {
+    pub fn is_admin(&self) -> bool {
+        self.roles.iter().any(|role| role == ADMIN_ROLE)
+    }
+}
+
+impl From<UserInfo> for User {
+    fn from(user_info: UserInfo) -> Self {
+        Self {
+            id: user_info.id,
+            name: user_info.name,
+            email: user_info.email,
+            password_sha: user_info.password_sha,
+            roles: user_info.roles,
+        }
+    }
 }
 
 impl AuthUser for User {
@@ -41,18 +86,39 @@ pub struct Credentials {
     pub email: String,
     pub password: String,
     pub _next: Option<String>,
+    #[serde(skip)]
+    pub context: RequestContext,
 }
 
 #[derive(Clone)]
 pub(crate) struct SessionAdapter {
     pub auth_api: ArcBox<dyn AuthApi>,
+    pub audit_api: ArcBox<dyn AuditApi>,
+    pub config: Arc<SessionConfiguration>,
+    revocations: Revocations,
+    writes: Arc<SessionWrites>,
 }
 
 impl SessionAdapter {
-    pub(crate) fn new(auth_api: ArcBox<dyn AuthApi>) -> Self {
-        Self { auth_api }
+    pub(crate) fn new(
+        auth_api: ArcBox<dyn AuthApi>,
+        audit_api: ArcBox<dyn AuditApi>,
+        config: SessionConfiguration,
+        writes: Arc<SessionWrites>,
+        revocations: Revocations,
+    ) -> Self {
+        Self {
+            auth_api,
+            audit_api,
+            revocations,
+            config: Arc::new(config),
+            writes,
+        }
     }
 
+    /// The database key of a session id. Sessions ids and their keys are the
+    /// same 128 bits, so the mapping loses nothing either way; ids are as
+    /// random as the keys the database draws.
     fn to_uuid(id: i128) -> Uuid {
         let bytes = id.to_le_bytes();
 
@@ -64,6 +130,238 @@ impl SessionAdapter {
 
         i128::from_le_bytes(bytes)
     }
+
+    pub(crate) fn record_user_id(&self, record: &Record) -> Option<i64> {
+        record.data.get(&self.config.data_key)?.get("user_id")?.as_i64()
+    }
+
+    pub(crate) fn record_auth_hash(&self, record: &Record) -> Option<Vec<u8>> {
+        let auth_hash = record.data.get(&self.config.data_key)?.get("auth_hash")?.as_array()?;
+
+        auth_hash.iter().map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok())).collect()
+    }
+
+    fn record_expired(&self, record: &Record) -> bool {
+        lifetime_exceeded(record, &self.config, Utc::now().timestamp_millis())
+    }
+
+    /// Encrypt the session stored under `id`, when configured to.
+    fn seal(&self, id: Uuid, data: Vec<u8>) -> Vec<u8> {
+        match self.config.encryption {
+            Some(ref encryption) => encryption.encrypt(&data, Sealed::Stored(id)),
+            None => data,
+        }
+    }
+
+    /// The stored session, and whether it is sealed as saving it would seal
+    /// it. Not when sealed with a retired key, or stored in the clear before
+    /// encryption was turned on.
+    fn open(&self, id: Uuid, data: &[u8]) -> Option<(Record, bool)> {
+        let (data, current) = match self.config.encryption {
+            Some(ref encryption) => (encryption.decrypt(data, Sealed::Stored(id))?, encryption.is_active(data)),
+            None => (data.into(), true),
+        };
+
+        Some((rmp_serde::from_slice(&data).ok()?, current))
+    }
+
+    /// The cookie value of a cookie session, which must fit the cookie.
+    fn seal_cookie(&self, record: &Record) -> session_store::Result<String> {
+        let Some(ref encryption) = self.config.encryption else {
+            return Err(session_store::Error::Encode("cookie sessions need session encryption".to_string()));
+        };
+        let data = rmp_serde::to_vec(record).map_err(|_| session_store::Error::Encode("save session record".to_string()))?;
+        let sealed = URL_SAFE_NO_PAD.encode(encryption.encrypt(&data, Sealed::Cookie));
+
+        let size = self.config.cookie_name.len() + 1 + sealed.len();
+        if size > self.config.max_cookie_size {
+            return Err(session_store::Error::Encode(format!(
+                "session is {} bytes, cookie sessions may take {}",
+                size, self.config.max_cookie_size
+            )));
+        }
+
+        Ok(sealed)
+    }
+
+    /// The session a cookie carries. `None` when it does not open, or has expired.
+    pub(crate) fn unseal_cookie(&self, sealed: &str) -> Option<Record> {
+        let encryption = self.config.encryption.as_ref()?;
+        let sealed = URL_SAFE_NO_PAD.decode(sealed).ok()?;
+        let record: Record = rmp_serde::from_slice(&encryption.decrypt(&sealed, Sealed::Cookie)?).ok()?;
+
+        (record.expiry_date > tower_sessions::cookie::time::OffsetDateTime::now_utc()).then_some(record)
+    }
+
+    /// Load the session a cookie carries, outside of the session layer.
+    pub(crate) async fn load_cookie(&self, sealed: &str) -> Option<Record> {
+        let record = self.unseal_cookie(sealed)?;
+        let id = record.id;
+
+        cookie::scope(record, self.load(&id)).await.ok().flatten()
+    }
+
+    /// Whether a cookie session was revoked, on this instance or another.
+    fn cookie_revoked(&self, record: &Record) -> bool {
+        let started_at = record.data.get(STARTED_AT_KEY).and_then(|started_at| started_at.as_i64());
+
+        self.revocations
+            .is_revoked(&SessionAdapter::to_uuid(record.id.0), self.record_user_id(record), started_at)
+    }
+
+    /// Revoke a cookie session, which lives on in any copy of the cookie.
+    /// Ids other than the one the cookie carried were never sent out, and
+    /// sessions nobody signed in to are not worth revoking.
+    async fn revoke_cookie(&self, session_id: &Id) -> session_store::Result<()> {
+        let Some(record) = cookie::forget(session_id) else {
+            return Ok(());
+        };
+        let Some(user_id) = self.record_user_id(&record) else {
+            return Ok(());
+        };
+
+        let revocation = SessionRevocation {
+            session_id: Some(SessionAdapter::to_uuid(session_id.0)),
+            user_id: Some(user_id),
+            revoked_at: Utc::now(),
+            expiry: DateTime::<Utc>::from_timestamp(record.expiry_date.unix_timestamp(), 0).unwrap_or(DateTime::<Utc>::MAX_UTC),
+        };
+        self.revocations.insert(&revocation);
+        self.auth_api
+            .revoke_session(
+                &SessionAdapter::to_uuid(session_id.0),
+                revocation.user_id,
+                revocation.expiry,
+                &binding::request_context(),
+            )
+            .await
+            .map_err(|_| session_store::Error::Backend("revoke session error".to_string()))
+    }
+
+    /// Sign the user out of all their sessions, here and on other instances.
+    pub(crate) async fn revoke_user_sessions(&self, user_id: i64, context: &RequestContext) -> Result<(), ApiError> {
+        let revocation = SessionRevocation {
+            session_id: None,
+            user_id: Some(user_id),
+            revoked_at: Utc::now(),
+            expiry: Utc::now() + self.config.absolute_lifetime.max(self.config.remember_lifetime),
+        };
+        self.auth_api.revoke_user_sessions(user_id, revocation.expiry, context).await?;
+        self.revocations.insert(&revocation);
+
+        Ok(())
+    }
+
+    /// Apply the binding policy to a session used by a client other than the
+    /// one that signed in. `None` when the session is revoked.
+    async fn check_binding(&self, mut record: Record) -> Option<Record> {
+        let binding = &self.config.binding;
+        let bound = record
+            .data
+            .get(FINGERPRINT_KEY)
+            .and_then(|bound| serde_json::from_value::<Fingerprint>(bound.clone()).ok());
+        let (true, Some(bound), Some(current)) = (binding.enabled, bound, binding::current()) else {
+            return Some(record);
+        };
+        let drift = bound.drift(&current.fingerprint);
+        if drift.is_empty() || binding.policy == DriftPolicy::Ignore {
+            return Some(record);
+        }
+
+        let session_id = SessionAdapter::to_uuid(record.id.0);
+        let detail = format!("session {} changed {}, policy {:?}", session_id, drift.join(", "), binding.policy).to_lowercase();
+        tracing::info!("Client drift - {}", detail);
+        let event = NewAuditEvent::new(AuditEventKind::SessionDrift, AuditOutcome::Failure, &current.context)
+            .with_actor(self.record_user_id(&record), None)
+            .with_detail(detail);
+        self.audit_api.record(event).await;
+
+        match binding.policy {
+            DriftPolicy::Ignore => Some(record),
+            // Bind to the new client, so the change is reported once.
+            DriftPolicy::Audit => {
+                record
+                    .data
+                    .insert(FINGERPRINT_KEY.to_string(), serde_json::to_value(&current.fingerprint).ok()?);
+                let _ = self.save(&record).await;
+                Some(record)
+            }
+            DriftPolicy::Reauthenticate => {
+                record.data.remove(&self.config.data_key);
+                record.data.remove(FINGERPRINT_KEY);
+                let _ = self.save(&record).await;
+                Some(record)
+            }
+            DriftPolicy::Revoke => {
+                let _ = self.delete(&record.id).await;
+                None
+            }
+        }
+    }
+
+    /// Move the session to a new id, so that an id planted or seen before a
+    /// change of privilege is useless after it. The old id is deleted in the
+    /// same transaction the new one is created, and the session is saved
+    /// under the new id at the end of the request.
+    pub(crate) async fn cycle_id(&self, session: &HttpSession) -> Result<(), session::Error> {
+        // Loading the record drops an id with nothing stored behind it. A
+        // session that was never stored has no id to leak.
+        session.get_value(CYCLED_ID_KEY).await?;
+        let Some(id) = session.id() else {
+            return Ok(());
+        };
+        // A cookie session gets its new id when next saved, the old one revoked.
+        if self.config.storage == SessionStorage::Cookie {
+            return session.cycle_id().await;
+        }
+        session.save().await?;
+
+        let uuid = SessionAdapter::to_uuid(id.0);
+        self.writes.forget(&uuid);
+        let cycled = self
+            .auth_api
+            .cycle_session(&uuid, &binding::request_context())
+            .await
+            .map_err(|_| session::Error::Store(session_store::Error::Backend("cycle session error".to_string())))?;
+        if let Some(cycled) = cycled {
+            session.insert(CYCLED_ID_KEY, cycled.id).await?;
+        }
+
+        session.cycle_id().await
+    }
+
+    async fn load_stored(&self, session_id: &Id) -> Option<Record> {
+        let uuid = SessionAdapter::to_uuid(session_id.0);
+        let session = self.auth_api.load_session(&uuid).await.ok()??;
+        let Some((mut record, current)) = self.open(session.id, &session.data) else {
+            tracing::warn!("Session {} cannot be decrypted or decoded", session.id);
+            return None;
+        };
+        record.id = Id(SessionAdapter::from_uuid(&session.id));
+        // Otherwise the next save is written in full, sealed with the active key.
+        match current {
+            true => self.writes.stored(session.id, writes::digest(&record), session.expiry),
+            false => self.writes.forget(&session.id),
+        }
+
+        Some(record)
+    }
+}
+
+/// Whether the session has outlived its absolute lifetime, `now` being in
+/// milliseconds. Sessions without a start time, created before lifetimes were
+/// tracked, are left to expire.
+fn lifetime_exceeded(record: &Record, config: &SessionConfiguration, now: i64) -> bool {
+    let Some(started_at) = record.data.get(STARTED_AT_KEY).and_then(|started_at| started_at.as_i64()) else {
+        return false;
+    };
+    let remember = record.data.get(REMEMBER_KEY).and_then(|remember| remember.as_bool()).unwrap_or(false);
+    let lifetime = match remember {
+        true => config.remember_lifetime,
+        false => config.absolute_lifetime,
+    };
+
+    started_at.saturating_add(lifetime.as_millis() as i64) < now
 }
 
 impl std::fmt::Debug for SessionAdapter {
@@ -76,65 +374,113 @@ impl std::fmt::Debug for SessionAdapter {
 impl SessionStore for SessionAdapter {
     #[tracing::instrument(level = "trace")]
     async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        // Created already by `cycle_id`.
+        if let Some(cycled_id) = record.data.remove(CYCLED_ID_KEY).and_then(|id| serde_json::from_value::<Uuid>(id).ok()) {
+            record.id = Id(SessionAdapter::from_uuid(&cycled_id));
+            return self.save(record).await;
+        }
+
+        record
+            .data
+            .entry(STARTED_AT_KEY.to_string())
+            .or_insert_with(|| Utc::now().timestamp_millis().into());
+        if self.config.storage == SessionStorage::Cookie {
+            // Ids of cookie sessions are random and never stored, so there is nothing to collide with.
+            record.id = Id::default();
+            return self.save(record).await;
+        }
         let data = rmp_serde::to_vec(record);
         if data.is_err() {
             return Err(session_store::Error::Encode("create session record".to_string()));
         }
 
+        // Encrypted sessions are sealed for their id, which the database draws.
+        // The row is created empty, and the session saved once it has its id.
+        let encrypted = self.config.encryption.is_some();
         let new_session = NewSession {
-            data: data.unwrap(),
+            user_id: self.record_user_id(record),
+            data: if encrypted { Vec::new() } else { data.unwrap() },
             expiry: DateTime::<Utc>::from_timestamp(record.expiry_date.unix_timestamp(), 0).unwrap(),
         };
 
-        match self.auth_api.create_session(&new_session).await {
-            Ok(session) => {
-                record.id = Id(SessionAdapter::from_uuid(&session.id));
-                Ok(())
-            }
-            Err(_) => Err(session_store::Error::Encode("create session record".to_string())),
+        let session = self
+            .auth_api
+            .create_session(&new_session, &binding::request_context())
+            .await
+            .map_err(|_| session_store::Error::Encode("create session record".to_string()))?;
+        record.id = Id(SessionAdapter::from_uuid(&session.id));
+        if encrypted {
+            return self.save(record).await;
         }
+        self.writes.stored(session.id, writes::digest(record), session.expiry);
+
+        Ok(())
     }
 
     #[tracing::instrument(level = "trace")]
     async fn save(&self, record: &Record) -> session_store::Result<()> {
+        if self.config.storage == SessionStorage::Cookie {
+            cookie::save(self.seal_cookie(record)?, record.expiry_date);
+            return Ok(());
+        }
+        let id = SessionAdapter::to_uuid(record.id.0);
+        let digest = writes::digest(record);
+        let expiry = DateTime::<Utc>::from_timestamp(record.expiry_date.unix_timestamp(), 0).unwrap();
+        if self.writes.defer(id, &digest, expiry) {
+            return Ok(());
+        }
         let data = rmp_serde::to_vec(record);
         if data.is_err() {
             return Err(session_store::Error::Encode("save session record".to_string()));
         }
 
         let session = Session {
-            id: SessionAdapter::to_uuid(record.id.0),
-            data: data.unwrap(),
-            expiry: DateTime::<Utc>::from_timestamp(record.expiry_date.unix_timestamp(), 0).unwrap(),
+            id,
+            user_id: self.record_user_id(record),
+            data: self.seal(id, data.unwrap()),
+            expiry,
         };
 
         match self.auth_api.save_session(&session).await {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                self.writes.stored(id, digest, expiry);
+                Ok(())
+            }
             Err(_) => Err(session_store::Error::Encode("save session record".to_string())),
         }
     }
 
     #[tracing::instrument(level = "trace")]
     async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
-        let uuid = SessionAdapter::to_uuid(session_id.0);
-
-        match self.auth_api.load_session(&uuid).await {
-            Ok(Some(session)) => {
-                let mut record: Record = rmp_serde::from_slice(&session.data).unwrap();
-                record.id = Id(SessionAdapter::from_uuid(&session.id));
-
-                Ok(Some(record))
-            }
-            Ok(None) => Ok(None),
-            Err(_) => Ok(None),
+        let record = match self.config.storage {
+            SessionStorage::Database => self.load_stored(session_id).await,
+            SessionStorage::Cookie => cookie::loaded(session_id),
+        };
+        let Some(record) = record else {
+            return Ok(None);
+        };
+        if self.record_expired(&record) {
+            tracing::debug!("Session {} reached its absolute lifetime", SessionAdapter::to_uuid(record.id.0));
+            let _ = self.delete(&record.id).await;
+            return Ok(None);
+        }
+        if self.config.storage == SessionStorage::Cookie && self.cookie_revoked(&record) {
+            tracing::debug!("Session {} is revoked", SessionAdapter::to_uuid(record.id.0));
+            return Ok(None);
         }
+
+        Ok(self.check_binding(record).await)
     }
 
     #[tracing::instrument(level = "trace")]
     async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        if self.config.storage == SessionStorage::Cookie {
+            return self.revoke_cookie(session_id).await;
+        }
         let uuid = SessionAdapter::to_uuid(session_id.0);
+        self.writes.forget(&uuid);
 
-        match self.auth_api.delete_session(&uuid).await {
+        match self.auth_api.delete_session(&uuid, &binding::request_context()).await {
             Ok(_) => Ok(()),
             Err(_) => Err(session_store::Error::Backend("delete session error".to_string())),
         }
@@ -149,27 +495,20 @@ impl AuthnBackend for SessionAdapter {
 
     #[tracing::instrument(level = "trace", skip(self, creds))]
     async fn authenticate(&self, creds: Self::Credentials) -> Result<Option<Self::User>, Self::Error> {
-        let result = self.auth_api.authenticate(&creds.email, &creds.password).await;
+        let result = self.auth_api.authenticate(&creds.email, &creds.password, &creds.context).await;
         match result {
-            Ok(user_info) => Ok(Some(Self::User {
-                id: user_info.id,
-                name: user_info.name.clone(),
-                email: user_info.email.clone(),
-                password_sha: user_info.password_sha.clone(),
-            })),
+            Ok(user_info) => Ok(Some(Self::User::from(user_info))),
             Err(_) => Err(Self::Error::UserNotFound(creds.email.clone())),
         }
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
-        let user_info = self.auth_api.get_user(*user_id).await?;
-        Ok(Some(Self::User {
-            id: user_info.id,
-            name: user_info.name.clone(),
-            email: user_info.email.clone(),
-            password_sha: user_info.password_sha.clone(),
-        }))
+        match self.auth_api.get_user(*user_id).await {
+            Ok(user_info) => Ok(Some(Self::User::from(user_info))),
+            Err(auth_domain_api::Error::NotFound) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
     }
 }
 
@@ -177,9 +516,13 @@ pub type AuthSession = axum_login::AuthSession<SessionAdapter>;
 
 #[cfg(test)]
 mod test {
+    use std::time::Duration;
+
+    use tower_sessions::{cookie::time::OffsetDateTime, session::Id, session::Record};
     use uuid::Uuid;
 
-    use super::SessionAdapter;
+    use super::{lifetime_exceeded, SessionAdapter, REMEMBER_KEY, STARTED_AT_KEY};
+    use crate::http::SessionConfiguration;
 
     #[test]
     fn id_uuid() {
@@ -191,8 +534,36 @@ mod test {
         let uuid = SessionAdapter::to_uuid(id);
         assert_eq!(id, SessionAdapter::from_uuid(&uuid));
 
-        let uuid = Uuid::now_v7();
+        let uuid = Uuid::new_v4();
         let id = SessionAdapter::from_uuid(&uuid);
         assert_eq!(uuid, SessionAdapter::to_uuid(id));
+
+        for id in [i128::MIN, i128::MAX, Id::default().0] {
+            assert_eq!(id, SessionAdapter::from_uuid(&SessionAdapter::to_uuid(id)));
+        }
+    }
+
+    #[test]
+    fn absolute_lifetime() {
+        let config = SessionConfiguration {
+            absolute_lifetime: Duration::from_secs(100),
+            remember_lifetime: Duration::from_secs(1000),
+            ..Default::default()
+        };
+        let mut record = Record {
+            id: Id::default(),
+            data: Default::default(),
+            expiry_date: OffsetDateTime::now_utc(),
+        };
+        assert!(!lifetime_exceeded(&record, &config, 1_800_000_000_000));
+
+        let started_at: i64 = 1_700_000_000_000;
+        record.data.insert(STARTED_AT_KEY.to_string(), started_at.into());
+        assert!(!lifetime_exceeded(&record, &config, started_at + 100_000));
+        assert!(lifetime_exceeded(&record, &config, started_at + 100_001));
+
+        record.data.insert(REMEMBER_KEY.to_string(), true.into());
+        assert!(!lifetime_exceeded(&record, &config, started_at + 100_001));
+        assert!(lifetime_exceeded(&record, &config, started_at + 1_000_001));
     }
 }
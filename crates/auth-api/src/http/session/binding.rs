@@ -0,0 +1,189 @@
+use std::{net::IpAddr, sync::Arc};
+
+use auth_domain_models::audit::RequestContext;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+use crate::http::context::ClientAddr;
+
+const SEC_CH_UA_PLATFORM: &str = "sec-ch-ua-platform";
+const SEC_CH_UA_MOBILE: &str = "sec-ch-ua-mobile";
+
+tokio::task_local! {
+    /// The client making the current request, for the session store, which
+    /// is not handed the request.
+    static CLIENT: Client;
+}
+
+/// What to do with a session used by a client other than the one that
+/// signed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DriftPolicy {
+    /// Let it through.
+    Ignore,
+    /// Let it through, recording an audit event.
+    #[default]
+    Audit,
+    /// Sign the user out, keeping the session.
+    Reauthenticate,
+    /// Delete the session.
+    Revoke,
+}
+
+/// Binds sessions to the client that signed in.
+#[derive(Debug, Clone)]
+pub struct SessionBindingConfiguration {
+    pub enabled: bool,
+    /// Leading bits of an IPv4 address that must stay the same.
+    pub ipv4_prefix: u8,
+    /// Leading bits of an IPv6 address that must stay the same.
+    pub ipv6_prefix: u8,
+    pub user_agent: bool,
+    /// The platform and mobile client hints.
+    pub device_hints: bool,
+    pub policy: DriftPolicy,
+}
+
+impl Default for SessionBindingConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ipv4_prefix: 24,
+            ipv6_prefix: 48,
+            user_agent: true,
+            device_hints: true,
+            policy: DriftPolicy::Audit,
+        }
+    }
+}
+
+/// What a session is bound to. Parts that are not bound are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Fingerprint {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ip_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mobile: Option<String>,
+}
+
+impl Fingerprint {
+    pub(crate) fn new(config: &SessionBindingConfiguration, ip: Option<IpAddr>, headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let ip_prefix = ip.and_then(|ip| {
+            let prefix = match ip {
+                IpAddr::V4(_) => config.ipv4_prefix,
+                IpAddr::V6(_) => config.ipv6_prefix,
+            };
+            IpNet::new(ip, prefix).ok().map(|net| net.trunc().to_string())
+        });
+
+        Self {
+            ip_prefix,
+            user_agent: header(header::USER_AGENT.as_str()).filter(|_| config.user_agent),
+            platform: header(SEC_CH_UA_PLATFORM).filter(|_| config.device_hints),
+            mobile: header(SEC_CH_UA_MOBILE).filter(|_| config.device_hints),
+        }
+    }
+
+    /// Parts of the fingerprint the session is bound to that the current
+    /// client does not match.
+    pub(crate) fn drift(&self, current: &Fingerprint) -> Vec<&'static str> {
+        [
+            ("ip_prefix", &self.ip_prefix, &current.ip_prefix),
+            ("user_agent", &self.user_agent, &current.user_agent),
+            ("platform", &self.platform, &current.platform),
+            ("mobile", &self.mobile, &current.mobile),
+        ]
+        .into_iter()
+        .filter(|(_, bound, current)| bound.is_some() && bound != current)
+        .map(|(name, _, _)| name)
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Client {
+    pub fingerprint: Fingerprint,
+    pub context: RequestContext,
+}
+
+/// The client making the current request. `None` outside of a request.
+pub(crate) fn current() -> Option<Client> {
+    CLIENT.try_with(Client::clone).ok()
+}
+
+/// Where the current request came from, for the audit events of the
+/// sessions it creates and ends.
+pub(crate) fn request_context() -> RequestContext {
+    CLIENT.try_with(|client| client.context.clone()).unwrap_or_default()
+}
+
+/// Run `future` as a request from `client`.
+#[cfg(test)]
+pub(crate) async fn scope<T>(client: Client, future: impl std::future::Future<Output = T>) -> T {
+    CLIENT.scope(client, future).await
+}
+
+/// Make the client available to the session store for the rest of the
+/// request: its fingerprint to check bound sessions against, and its address
+/// and user agent for the audit log.
+pub(crate) async fn bind_client(State(config): State<Arc<SessionBindingConfiguration>>, client_addr: ClientAddr, request: Request, next: Next) -> Response {
+    let client = Client {
+        fingerprint: Fingerprint::new(&config, client_addr.ip, request.headers()),
+        context: RequestContext {
+            ip: client_addr.ip.map(|ip| ip.to_string()),
+            user_agent: request
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        },
+    };
+
+    CLIENT.scope(client, next.run(request)).await
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::{Fingerprint, SessionBindingConfiguration};
+
+    #[test]
+    fn detects_drift() {
+        let config = SessionBindingConfiguration::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("user-agent", HeaderValue::from_static("Firefox"));
+        let bound = Fingerprint::new(&config, "192.0.2.10".parse().ok(), &headers);
+
+        let same_network = Fingerprint::new(&config, "192.0.2.99".parse().ok(), &headers);
+        assert!(bound.drift(&same_network).is_empty());
+
+        headers.insert("sec-ch-ua-platform", HeaderValue::from_static("\"Linux\""));
+        let new_hints = Fingerprint::new(&config, "192.0.2.99".parse().ok(), &headers);
+        assert!(bound.drift(&new_hints).is_empty());
+
+        headers.insert("user-agent", HeaderValue::from_static("curl"));
+        let elsewhere = Fingerprint::new(&config, "198.51.100.1".parse().ok(), &headers);
+        assert_eq!(bound.drift(&elsewhere), vec!["ip_prefix", "user_agent"]);
+
+        let unbound = Fingerprint::new(
+            &SessionBindingConfiguration { user_agent: false, ..config },
+            "2001:db8:1:2::1".parse().ok(),
+            &headers,
+        );
+        assert_eq!(unbound.ip_prefix.as_deref(), Some("2001:db8:1::/48"));
+        assert!(unbound.user_agent.is_none());
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use auth_domain_api::AuthApi;
+use auth_domain_models::auth::{SessionId, SessionRevocation};
+use auth_utils::arcbox::ArcBox;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use tokio_graceful_shutdown::SubsystemHandle;
+use tower_sessions::{
+    cookie::{time::OffsetDateTime, Cookie},
+    session::{Id, Record},
+};
+
+use super::{SessionAdapter, SessionStorage};
+use crate::{http::KeyRing, ApiError};
+
+tokio::task_local! {
+    /// The session the current request's cookie carries, for the session
+    /// store, which is not handed the request.
+    static COOKIE: Arc<Mutex<CookieSession>>;
+}
+
+#[derive(Debug, Default)]
+struct CookieSession {
+    /// The session the cookie carried in.
+    loaded: Option<Record>,
+    /// The sealed session to send back, and when it expires.
+    saved: Option<(String, OffsetDateTime)>,
+}
+
+/// The session the cookie carried in, if it has this id.
+pub(crate) fn loaded(id: &Id) -> Option<Record> {
+    COOKIE
+        .try_with(|session| session.lock().unwrap().loaded.clone().filter(|record| record.id == *id))
+        .ok()
+        .flatten()
+}
+
+/// Send the sealed session back in the cookie.
+pub(crate) fn save(sealed: String, expiry: OffsetDateTime) {
+    let _ = COOKIE.try_with(|session| session.lock().unwrap().saved = Some((sealed, expiry)));
+}
+
+/// Forget the session the cookie carried in, and send nothing back for it.
+/// Returns it when it has this id.
+pub(crate) fn forget(id: &Id) -> Option<Record> {
+    COOKIE
+        .try_with(|session| {
+            let mut session = session.lock().unwrap();
+            session.saved = None;
+            session.loaded.take_if(|record| record.id == *id)
+        })
+        .ok()
+        .flatten()
+}
+
+/// Run `f` with the session a cookie carries.
+pub(crate) async fn scope<F: Future>(record: Record, f: F) -> F::Output {
+    let session = CookieSession {
+        loaded: Some(record),
+        saved: None,
+    };
+
+    COOKIE.scope(Arc::new(Mutex::new(session)), f).await
+}
+
+/// What the cookie exchange needs besides the session store.
+#[derive(Debug, Clone)]
+pub(crate) struct CookieSessions {
+    session_adapter: SessionAdapter,
+    keys: KeyRing,
+    secure: bool,
+}
+
+impl CookieSessions {
+    pub(crate) fn new(session_adapter: SessionAdapter, keys: KeyRing, secure: bool) -> Self {
+        Self { session_adapter, keys, secure }
+    }
+
+    fn build_cookie(&self, sealed: String, expiry: OffsetDateTime) -> Cookie<'static> {
+        let config = &self.session_adapter.config;
+        let mut cookie = Cookie::build((config.cookie_name.clone(), sealed))
+            .path(config.cookie_path.clone())
+            .same_site(config.same_site.into())
+            .secure(self.secure)
+            .http_only(true)
+            .expires(expiry)
+            .build();
+        if let Some(ref domain) = config.cookie_domain {
+            cookie.set_domain(domain.clone());
+        }
+
+        cookie
+    }
+}
+
+/// Carry the whole session in the cookie. The session layer sees the sealed
+/// session swapped for its signed id, as if it were stored, and the session
+/// it saves is sealed into the cookie sent back.
+pub(crate) async fn exchange(State(cookie_sessions): State<CookieSessions>, mut request: Request, next: Next) -> Response {
+    if cookie_sessions.session_adapter.config.storage != SessionStorage::Cookie {
+        return next.run(request).await;
+    }
+    let loaded = open_cookie(&cookie_sessions, request.headers_mut());
+    let session = Arc::new(Mutex::new(CookieSession { loaded, saved: None }));
+
+    let mut response = COOKIE.scope(session.clone(), next.run(request)).await;
+
+    let saved = session.lock().unwrap().saved.take();
+    if let Some((sealed, expiry)) = saved {
+        let cookie = cookie_sessions.build_cookie(sealed, expiry);
+        replace_set_cookie(response.headers_mut(), &cookie);
+    }
+
+    response
+}
+
+/// Swap the sealed session in the `Cookie` headers for its signed id. A
+/// cookie that does not open is dropped, leaving the request without one.
+fn open_cookie(cookie_sessions: &CookieSessions, headers: &mut HeaderMap) -> Option<Record> {
+    let name = cookie_sessions.session_adapter.config.cookie_name.as_str();
+    let mut record = None;
+    let values: Vec<HeaderValue> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| {
+            let Ok(cookies) = value.to_str() else {
+                return Some(value.clone());
+            };
+            let cookies: Vec<String> = cookies
+                .split(';')
+                .map(str::trim)
+                .filter_map(|cookie| match cookie.split_once('=') {
+                    Some((cookie_name, sealed)) if cookie_name == name => {
+                        let opened = cookie_sessions.session_adapter.unseal_cookie(sealed)?;
+                        let signed = cookie_sessions.keys.sign(name, &opened.id.to_string());
+                        record = Some(opened);
+                        Some(format!("{}={}", name, signed))
+                    }
+                    _ => Some(cookie.to_string()),
+                })
+                .filter(|cookie| !cookie.is_empty())
+                .collect();
+            if cookies.is_empty() {
+                return None;
+            }
+
+            HeaderValue::from_str(&cookies.join("; ")).ok()
+        })
+        .collect();
+
+    headers.remove(header::COOKIE);
+    for value in values {
+        headers.append(header::COOKIE, value);
+    }
+
+    record
+}
+
+/// Replace whatever the session layer set for the session cookie.
+fn replace_set_cookie(headers: &mut HeaderMap, cookie: &Cookie<'static>) {
+    let others: Vec<HeaderValue> = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter(|value| {
+            let name = value.to_str().ok().and_then(|value| value.split_once('=')).map(|(name, _)| name.trim());
+            name != Some(cookie.name())
+        })
+        .cloned()
+        .collect();
+
+    headers.remove(header::SET_COOKIE);
+    for value in others {
+        headers.append(header::SET_COOKIE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&cookie.encoded().to_string()) {
+        headers.append(header::SET_COOKIE, value);
+    }
+}
+
+/// Revoked cookie sessions. Revocations made here apply at once, those made
+/// by other instances once the list is next refreshed from the database.
+#[derive(Clone, Default)]
+pub(crate) struct Revocations {
+    state: Arc<RwLock<RevocationState>>,
+}
+
+#[derive(Default)]
+struct RevocationState {
+    /// Revoked sessions, until they expire.
+    sessions: HashMap<SessionId, DateTime<Utc>>,
+    /// Sessions of these users started before the first time are revoked,
+    /// until the second.
+    users: HashMap<i64, (DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl RevocationState {
+    fn insert(&mut self, revocation: &SessionRevocation) {
+        match (revocation.session_id, revocation.user_id) {
+            (Some(session_id), _) => {
+                let expiry = self.sessions.entry(session_id).or_insert(revocation.expiry);
+                *expiry = revocation.expiry.max(*expiry);
+            }
+            (None, Some(user_id)) => {
+                let (revoked_at, expiry) = self.users.entry(user_id).or_insert((revocation.revoked_at, revocation.expiry));
+                *revoked_at = revocation.revoked_at.max(*revoked_at);
+                *expiry = revocation.expiry.max(*expiry);
+            }
+            (None, None) => (),
+        }
+    }
+}
+
+impl Revocations {
+    pub(crate) fn insert(&self, revocation: &SessionRevocation) {
+        self.state.write().unwrap().insert(revocation);
+    }
+
+    /// Whether the session is revoked. `started_at` is in milliseconds since
+    /// the epoch; sessions without it are revoked with their user's.
+    pub(crate) fn is_revoked(&self, session_id: &SessionId, user_id: Option<i64>, started_at: Option<i64>) -> bool {
+        let state = self.state.read().unwrap();
+        if state.sessions.contains_key(session_id) {
+            return true;
+        }
+        let revoked_at = user_id.and_then(|user_id| state.users.get(&user_id)).map(|(revoked_at, _)| revoked_at);
+
+        match (revoked_at, started_at) {
+            (Some(revoked_at), Some(started_at)) => started_at <= revoked_at.timestamp_millis(),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Pick up the revocations in the database, and drop expired ones.
+    pub(crate) async fn refresh(&self, auth_api: &ArcBox<dyn AuthApi>) {
+        let revocations = auth_api.list_session_revocations().await;
+        let mut state = self.state.write().unwrap();
+        match revocations {
+            Ok(revocations) => {
+                for revocation in revocations.iter() {
+                    state.insert(revocation);
+                }
+                let now = Utc::now();
+                state.sessions.retain(|_, expiry| *expiry > now);
+                state.users.retain(|_, (_, expiry)| *expiry > now);
+            }
+            Err(err) => tracing::warn!("Couldn't refresh session revocations - {}", err),
+        }
+    }
+}
+
+impl std::fmt::Debug for Revocations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Revocations").finish_non_exhaustive()
+    }
+}
+
+/// Refresh the revocations every interval, off the request path, so a slow
+/// database does not hold up cookie sessions. A failed refresh is retried at
+/// the next interval.
+pub(crate) async fn refresh_revocations(
+    revocations: Revocations,
+    auth_api: ArcBox<dyn AuthApi>,
+    interval: Duration,
+    subsys: SubsystemHandle,
+) -> Result<(), ApiError> {
+    tracing::trace!("Starting session revocation refreshes");
+
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            }
+
+            _ = ticker.tick() => revocations.refresh(&auth_api).await,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use auth_domain_models::auth::SessionRevocation;
+    use chrono::DateTime;
+    use uuid::Uuid;
+
+    use super::Revocations;
+
+    #[test]
+    fn revokes_sessions_started_before() {
+        let revocations = Revocations::default();
+        let revoked_at = DateTime::from_timestamp_millis(1_700_000_000_250).unwrap();
+        revocations.insert(&SessionRevocation {
+            session_id: None,
+            user_id: Some(1),
+            revoked_at,
+            expiry: revoked_at + chrono::Duration::hours(1),
+        });
+        let session_id = Uuid::new_v4();
+
+        assert!(revocations.is_revoked(&session_id, Some(1), Some(1_700_000_000_250)));
+        // Signed in again within the same second.
+        assert!(!revocations.is_revoked(&session_id, Some(1), Some(1_700_000_000_900)));
+        assert!(revocations.is_revoked(&session_id, Some(1), None));
+        assert!(!revocations.is_revoked(&session_id, Some(2), Some(0)));
+    }
+}
@@ -0,0 +1,150 @@
+use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+
+use auth_domain_models::auth::SessionId;
+use auth_utils::aead::{self, KEY_LEN};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::ApiError;
+
+/// First byte of an encrypted session. MessagePack never starts a record
+/// with it, which tells encrypted sessions apart from ones stored before
+/// encryption was turned on.
+const ENCRYPTED: u8 = 0xe1;
+
+/// Where a sealed session is kept. Part of what is authenticated, so a
+/// session sealed for the database does not open as a cookie, and one
+/// stored under a session id does not open when copied to another. A
+/// cookie carries its session id inside the seal, so there is no other
+/// record to move it to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Sealed {
+    Stored(SessionId),
+    Cookie,
+}
+
+impl Sealed {
+    /// The key id, length prefixed, then where the session is kept.
+    fn aad(self, key_id: &[u8]) -> Vec<u8> {
+        let prefix = [&[key_id.len() as u8], key_id].concat();
+        match self {
+            Sealed::Stored(session_id) => [&prefix, &b"stored"[..], session_id.as_bytes()].concat(),
+            Sealed::Cookie => [&prefix, &b"cookie"[..]].concat(),
+        }
+    }
+}
+
+/// Encrypts session data at rest, and seals sessions kept in the cookie.
+/// Sessions are sealed with the active key and prefixed with its id.
+/// Sessions sealed with another configured key are still read, and sealed
+/// with the active key when next saved.
+#[derive(Clone)]
+pub struct SessionEncryption {
+    active: String,
+    keys: Arc<BTreeMap<String, [u8; KEY_LEN]>>,
+}
+
+impl SessionEncryption {
+    /// Keys are base64 encoded, 32 bytes long, and named by ids of at most
+    /// 255 bytes.
+    pub fn new(active: &str, keys: &BTreeMap<String, String>) -> Result<Self, ApiError> {
+        let keys = keys
+            .iter()
+            .map(|(id, key)| {
+                let bad_key = |reason: &str| ApiError::SecretKey(format!("session encryption key {} {}", id, reason));
+                if id.is_empty() || id.len() > u8::MAX as usize {
+                    return Err(bad_key("needs an id of 1 to 255 bytes"));
+                }
+                let key = STANDARD.decode(key.trim()).map_err(|_| bad_key("is not base64"))?;
+                let key: [u8; KEY_LEN] = key.try_into().map_err(|_| bad_key(&format!("is not {} bytes", KEY_LEN)))?;
+
+                Ok((id.clone(), key))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()?;
+        if !keys.contains_key(active) {
+            return Err(ApiError::SecretKey(format!("no session encryption key {}", active)));
+        }
+
+        Ok(Self {
+            active: active.to_string(),
+            keys: Arc::new(keys),
+        })
+    }
+
+    pub(crate) fn encrypt(&self, data: &[u8], sealed: Sealed) -> Vec<u8> {
+        let id = self.active.as_bytes();
+        let ciphertext = aead::seal(&self.keys[&self.active], data, &sealed.aad(id));
+
+        [&[ENCRYPTED, id.len() as u8], id, &ciphertext].concat()
+    }
+
+    /// Whether the data is sealed with the active key.
+    pub(crate) fn is_active(&self, data: &[u8]) -> bool {
+        let id = self.active.as_bytes();
+
+        data.starts_with(&[ENCRYPTED, id.len() as u8]) && data[2..].starts_with(id)
+    }
+
+    /// The session data, decrypted when it was encrypted. Only stored
+    /// sessions may be in the clear. `None` when it was sealed with a key
+    /// that is not configured, or does not authenticate.
+    pub(crate) fn decrypt<'a>(&self, data: &'a [u8], sealed: Sealed) -> Option<Cow<'a, [u8]>> {
+        let Some((&ENCRYPTED, rest)) = data.split_first() else {
+            return matches!(sealed, Sealed::Stored(_)).then_some(Cow::Borrowed(data));
+        };
+        let (&id_len, rest) = rest.split_first()?;
+        if rest.len() < id_len as usize {
+            return None;
+        }
+        let (id, ciphertext) = rest.split_at(id_len as usize);
+        let key = self.keys.get(std::str::from_utf8(id).ok()?)?;
+
+        aead::open(key, ciphertext, &sealed.aad(id)).map(Cow::Owned)
+    }
+}
+
+impl std::fmt::Debug for SessionEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionEncryption").field("active", &self.active).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use uuid::Uuid;
+
+    use super::{Sealed, SessionEncryption};
+
+    #[test]
+    fn rotates_keys() {
+        let stored = Sealed::Stored(Uuid::new_v4());
+        let mut keys = BTreeMap::new();
+        keys.insert("old".to_string(), "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=".to_string());
+        let old = SessionEncryption::new("old", &keys).unwrap();
+        let sealed = old.encrypt(b"\x83record", stored);
+        assert!(!sealed.windows(6).any(|window| window == b"record"));
+
+        keys.insert("new".to_string(), "AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=".to_string());
+        let new = SessionEncryption::new("new", &keys).unwrap();
+        assert_eq!(new.decrypt(&sealed, stored).as_deref(), Some(&b"\x83record"[..]));
+        assert_eq!(new.decrypt(b"\x83plain", stored).as_deref(), Some(&b"\x83plain"[..]));
+        assert!(!new.is_active(&sealed) && !new.is_active(b"\x83plain"));
+        assert!(new.is_active(&new.encrypt(b"\x83record", stored)));
+        assert_eq!(new.decrypt(&new.encrypt(b"\x83record", stored), stored).as_deref(), Some(&b"\x83record"[..]));
+
+        assert_eq!(new.decrypt(b"\x83plain", Sealed::Cookie), None);
+        assert_eq!(new.decrypt(&sealed, Sealed::Cookie), None);
+        assert_eq!(new.decrypt(&sealed, Sealed::Stored(Uuid::new_v4())), None);
+        let cookie = new.encrypt(b"\x83record", Sealed::Cookie);
+        assert_eq!(new.decrypt(&cookie, Sealed::Cookie).as_deref(), Some(&b"\x83record"[..]));
+
+        keys.remove("old");
+        let retired = SessionEncryption::new("new", &keys).unwrap();
+        assert_eq!(retired.decrypt(&sealed, stored), None);
+
+        assert!(SessionEncryption::new("missing", &keys).is_err());
+        keys.insert("short".to_string(), "AQID".to_string());
+        assert!(SessionEncryption::new("new", &keys).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use auth_domain_api::AuthApi;
+use auth_domain_models::auth::SessionId;
+use auth_utils::arcbox::ArcBox;
+use chrono::{DateTime, Utc};
+use moka::{sync::Cache, Expiry};
+use sha2::{Digest, Sha256};
+use tokio_graceful_shutdown::SubsystemHandle;
+use tower_sessions::session::Record;
+
+use crate::ApiError;
+
+/// Digest of a session's data, to tell whether a save changes it.
+pub(crate) type SessionDigest = [u8; 32];
+
+/// Stored sessions whose save would only extend their expiry. Those saves are
+/// skipped, and the extensions written in batches.
+#[derive(Debug)]
+pub(crate) struct SessionWrites {
+    interval: Duration,
+    /// What the database holds of sessions loaded or saved here, up to a
+    /// capacity. Saves of sessions evicted from it are written in full.
+    stored: Cache<SessionId, Stored>,
+    /// Expiries to extend stored sessions to, at the next flush. Changes to
+    /// either are made under its lock.
+    extensions: Mutex<HashMap<SessionId, DateTime<Utc>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Stored {
+    digest: SessionDigest,
+    expiry: DateTime<Utc>,
+}
+
+/// Sessions are forgotten once their stored expiry passes.
+struct StoredExpiry;
+
+impl Expiry<SessionId, Stored> for StoredExpiry {
+    fn expire_after_create(&self, _id: &SessionId, stored: &Stored, _created_at: Instant) -> Option<Duration> {
+        Some((stored.expiry - Utc::now()).to_std().unwrap_or_default())
+    }
+
+    fn expire_after_update(&self, id: &SessionId, stored: &Stored, updated_at: Instant, _duration_until_expiry: Option<Duration>) -> Option<Duration> {
+        self.expire_after_create(id, stored, updated_at)
+    }
+}
+
+/// Digest of the record's data, independent of the order of its keys.
+pub(crate) fn digest(record: &Record) -> SessionDigest {
+    let data: BTreeMap<&String, &serde_json::Value> = record.data.iter().collect();
+    let data = serde_json::to_vec(&data).unwrap_or_default();
+
+    Sha256::digest(data).into()
+}
+
+impl SessionWrites {
+    pub(crate) fn new(interval: Duration, capacity: u64) -> Self {
+        Self {
+            interval,
+            stored: Cache::builder().max_capacity(capacity).expire_after(StoredExpiry).build(),
+            extensions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The session was loaded, or saved, as the database now holds it.
+    pub(crate) fn stored(&self, id: SessionId, digest: SessionDigest, expiry: DateTime<Utc>) {
+        let mut extensions = self.extensions.lock().unwrap();
+        self.stored.insert(id, Stored { digest, expiry });
+        extensions.remove(&id);
+    }
+
+    pub(crate) fn forget(&self, id: &SessionId) {
+        let mut extensions = self.extensions.lock().unwrap();
+        self.stored.invalidate(id);
+        extensions.remove(id);
+    }
+
+    /// Whether saving the session can be skipped, any extension of its
+    /// expiry being left to the next flush. Not when the data changed, or the
+    /// stored expiry would pass before then.
+    pub(crate) fn defer(&self, id: SessionId, digest: &SessionDigest, expiry: DateTime<Utc>) -> bool {
+        let mut extensions = self.extensions.lock().unwrap();
+        let Some(stored) = self.stored.get(&id) else {
+            return false;
+        };
+        if stored.digest != *digest {
+            return false;
+        }
+        if expiry <= stored.expiry {
+            return true;
+        }
+        if stored.expiry <= Utc::now() + self.interval * 2 {
+            return false;
+        }
+        let extension = extensions.entry(id).or_insert(expiry);
+        *extension = expiry.max(*extension);
+
+        true
+    }
+
+    /// Write the pending extensions. Extensions that fail to be written are
+    /// kept for the next flush.
+    pub(crate) async fn flush(&self, auth_api: &ArcBox<dyn AuthApi>) {
+        let extensions: Vec<(SessionId, DateTime<Utc>)> = self.extensions.lock().unwrap().drain().collect();
+        if extensions.is_empty() {
+            return;
+        }
+
+        match auth_api.extend_sessions(&extensions).await {
+            Ok(extended) => {
+                tracing::debug!("Extended {} of {} sessions", extended, extensions.len());
+                let _extensions = self.extensions.lock().unwrap();
+                for (id, expiry) in extensions {
+                    if let Some(stored) = self.stored.get(&id).filter(|stored| stored.expiry < expiry) {
+                        self.stored.insert(id, Stored { expiry, ..stored });
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!("Couldn't extend {} sessions - {}", extensions.len(), err);
+                let mut pending = self.extensions.lock().unwrap();
+                for (id, expiry) in extensions {
+                    let extension = pending.entry(id).or_insert(expiry);
+                    *extension = expiry.max(*extension);
+                }
+            }
+        }
+    }
+}
+
+/// Flush session extensions every interval. The last are flushed once the
+/// listeners have drained.
+pub(crate) async fn flush_extensions(writes: Arc<SessionWrites>, auth_api: ArcBox<dyn AuthApi>, subsys: SubsystemHandle) -> Result<(), ApiError> {
+    tracing::trace!("Starting session extension flushes");
+
+    let mut ticker = tokio::time::interval(writes.interval);
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            }
+
+            _ = ticker.tick() => writes.flush(&auth_api).await,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::SessionWrites;
+
+    #[test]
+    fn defers_unchanged_sessions() {
+        let writes = SessionWrites::new(Duration::from_secs(10), 100);
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+        assert!(!writes.defer(id, &[1; 32], now + chrono::Duration::hours(1)));
+
+        writes.stored(id, [1; 32], now + chrono::Duration::hours(1));
+        assert!(writes.defer(id, &[1; 32], now + chrono::Duration::minutes(30)));
+        assert!(writes.defer(id, &[1; 32], now + chrono::Duration::hours(2)));
+        assert_eq!(writes.extensions.lock().unwrap().get(&id), Some(&(now + chrono::Duration::hours(2))));
+        assert!(!writes.defer(id, &[2; 32], now + chrono::Duration::hours(2)));
+
+        // Too close to expiring to wait for the next flush.
+        writes.stored(id, [1; 32], now + chrono::Duration::seconds(15));
+        assert!(!writes.defer(id, &[1; 32], now + chrono::Duration::hours(1)));
+
+        writes.forget(&id);
+        assert!(!writes.defer(id, &[1; 32], now + chrono::Duration::hours(1)));
+
+        // Beyond the capacity, sessions are forgotten and written in full.
+        for _ in 0..200 {
+            writes.stored(Uuid::new_v4(), [1; 32], now + chrono::Duration::hours(1));
+        }
+        writes.stored.run_pending_tasks();
+        assert!(writes.stored.entry_count() <= 100);
+    }
+}
@@ -0,0 +1,260 @@
+//! Test doubles for the domain APIs the HTTP layer calls.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use auth_domain_api::{
+    AccountApi, AuditApi, AuthApi, AuthDomainApi, CacheApi, CacheMetrics, Error, HealthApi, ProfileExport, UserExport, UserInfo, WebhookApi,
+};
+use auth_domain_models::{
+    audit::{AuditFilter, AuditPage, NewAuditEvent, RequestContext},
+    auth::{NewSession, NewUser, Session, SessionId, SessionRevocation, User},
+    webhook::{WebhookDelivery, WebhookEndpoint, WebhookEventType},
+};
+use auth_utils::arcbox;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Sessions and revocations in memory, and a single user.
+#[derive(Default)]
+pub(crate) struct TestAuthApi {
+    pub sessions: Arc<Mutex<HashMap<SessionId, Session>>>,
+    pub revocations: Arc<Mutex<Vec<SessionRevocation>>>,
+    /// Where the requests that created and deleted sessions came from.
+    pub contexts: Arc<Mutex<Vec<RequestContext>>>,
+    /// The roles the user signs in with.
+    pub roles: Vec<String>,
+}
+
+/// For the parts of the APIs these tests do not exercise.
+pub(crate) fn not_used() -> Error {
+    Error::Message("not used in this test".to_string())
+}
+
+fn user_info(roles: &[String]) -> UserInfo {
+    UserInfo {
+        id: 1,
+        name: "Test".to_string(),
+        email: "test@example.com".to_string(),
+        password_sha: "sha".to_string(),
+        roles: roles.to_vec(),
+    }
+}
+
+#[async_trait]
+impl AuthApi for TestAuthApi {
+    async fn register(&self, _user: &NewUser, _context: &RequestContext) -> Result<User, Error> {
+        Err(not_used())
+    }
+
+    async fn authenticate(&self, _email: &str, _password: &str, _context: &RequestContext) -> Result<UserInfo, Error> {
+        Ok(user_info(&self.roles))
+    }
+
+    async fn logout(&self, _id: i64, _context: &RequestContext) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn get_user(&self, _id: i64) -> Result<UserInfo, Error> {
+        Ok(user_info(&self.roles))
+    }
+
+    async fn change_password(&self, _id: i64, _current_password: &str, _new_password: &str, _context: &RequestContext) -> Result<UserInfo, Error> {
+        Err(not_used())
+    }
+
+    async fn create_session(&self, new_session: &NewSession, context: &RequestContext) -> Result<Session, Error> {
+        self.contexts.lock().unwrap().push(context.clone());
+        let session = Session {
+            id: Uuid::new_v4(),
+            user_id: new_session.user_id,
+            data: new_session.data.clone(),
+            expiry: new_session.expiry,
+        };
+        self.sessions.lock().unwrap().insert(session.id, session.clone());
+
+        Ok(session)
+    }
+
+    async fn save_session(&self, session: &Session) -> Result<(), Error> {
+        match self.sessions.lock().unwrap().get_mut(&session.id) {
+            Some(stored) => *stored = session.clone(),
+            None => return Err(Error::NotFound),
+        }
+
+        Ok(())
+    }
+
+    async fn extend_sessions(&self, extensions: &[(SessionId, DateTime<Utc>)]) -> Result<u64, Error> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let mut extended = 0;
+        for (id, expiry) in extensions {
+            if let Some(session) = sessions.get_mut(id).filter(|session| session.expiry < *expiry) {
+                session.expiry = *expiry;
+                extended += 1;
+            }
+        }
+
+        Ok(extended)
+    }
+
+    async fn load_session(&self, id: &SessionId) -> Result<Option<Session>, Error> {
+        Ok(self.sessions.lock().unwrap().get(id).cloned())
+    }
+
+    async fn delete_session(&self, id: &SessionId, context: &RequestContext) -> Result<(), Error> {
+        self.contexts.lock().unwrap().push(context.clone());
+        self.sessions.lock().unwrap().remove(id);
+
+        Ok(())
+    }
+
+    async fn cycle_session(&self, id: &SessionId, _context: &RequestContext) -> Result<Option<Session>, Error> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(mut session) = sessions.remove(id) else {
+            return Ok(None);
+        };
+        session.id = Uuid::new_v4();
+        sessions.insert(session.id, session.clone());
+
+        Ok(Some(session))
+    }
+
+    async fn revoke_session(&self, id: &SessionId, user_id: Option<i64>, expiry: DateTime<Utc>, _context: &RequestContext) -> Result<(), Error> {
+        self.revocations.lock().unwrap().push(SessionRevocation {
+            session_id: Some(*id),
+            user_id,
+            revoked_at: Utc::now(),
+            expiry,
+        });
+
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, _user_id: i64, _expiry: DateTime<Utc>, _context: &RequestContext) -> Result<(), Error> {
+        Err(not_used())
+    }
+
+    async fn list_session_revocations(&self) -> Result<Vec<SessionRevocation>, Error> {
+        Ok(self.revocations.lock().unwrap().clone())
+    }
+}
+
+/// Keeps the events recorded.
+#[derive(Default)]
+pub(crate) struct TestAuditApi {
+    pub events: Arc<Mutex<Vec<NewAuditEvent>>>,
+}
+
+#[async_trait]
+impl AuditApi for TestAuditApi {
+    async fn record(&self, event: NewAuditEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    async fn list_events(&self, _filter: &AuditFilter) -> Result<AuditPage, Error> {
+        Err(not_used())
+    }
+}
+
+/// Exports a bare profile for any user.
+pub(crate) struct TestAccountApi;
+
+#[async_trait]
+impl AccountApi for TestAccountApi {
+    async fn export_user(&self, id: i64) -> Result<UserExport, Error> {
+        Ok(UserExport {
+            exported_at: Utc::now(),
+            profile: ProfileExport {
+                id,
+                name: "Test".to_string(),
+                email: "test@example.com".to_string(),
+                roles: vec![],
+                deleted_at: None,
+                purge_at: None,
+            },
+            sessions: vec![],
+            audit_events: vec![],
+        })
+    }
+
+    async fn schedule_deletion(&self, _id: i64, _context: &RequestContext) -> Result<User, Error> {
+        Err(not_used())
+    }
+
+    async fn cancel_deletion(&self, _id: i64, _context: &RequestContext) -> Result<User, Error> {
+        Err(not_used())
+    }
+
+    async fn purge_deleted_users(&self) -> Result<usize, Error> {
+        Err(not_used())
+    }
+}
+
+/// Stands in for the APIs no test calls.
+#[derive(Clone, Copy)]
+pub(crate) struct UnusedApi;
+
+#[async_trait]
+impl CacheApi for UnusedApi {
+    async fn follow_invalidations(&self) -> Result<(), Error> {
+        Err(not_used())
+    }
+}
+
+#[async_trait]
+impl HealthApi for UnusedApi {
+    async fn is_healthy(&self) -> bool {
+        true
+    }
+
+    fn cache_metrics(&self) -> Vec<CacheMetrics> {
+        vec![]
+    }
+}
+
+#[async_trait]
+impl WebhookApi for UnusedApi {
+    async fn add_endpoint(&self, _url: &str, _events: &[WebhookEventType], _secret: Option<String>) -> Result<WebhookEndpoint, Error> {
+        Err(not_used())
+    }
+
+    async fn list_endpoints(&self) -> Result<Vec<WebhookEndpoint>, Error> {
+        Err(not_used())
+    }
+
+    async fn delete_endpoint(&self, _id: i64) -> Result<(), Error> {
+        Err(not_used())
+    }
+
+    async fn list_deliveries(&self, _endpoint_id: i64, _offset: u64, _limit: u64) -> Result<Vec<WebhookDelivery>, Error> {
+        Err(not_used())
+    }
+
+    async fn deliver_due(&self) -> Result<usize, Error> {
+        Err(not_used())
+    }
+
+    async fn prune_events(&self) -> Result<u64, Error> {
+        Err(not_used())
+    }
+}
+
+/// The domain, with sessions and users from `auth_api`.
+pub(crate) fn auth_domain_api(auth_api: TestAuthApi) -> AuthDomainApi {
+    let account_api = TestAccountApi;
+    let audit_api = TestAuditApi::default();
+    let unused = UnusedApi;
+
+    AuthDomainApi {
+        account_api: arcbox!(account_api),
+        audit_api: arcbox!(audit_api),
+        auth_api: arcbox!(auth_api),
+        cache_api: arcbox!(unused),
+        health_api: arcbox!(unused),
+        webhook_api: arcbox!(unused),
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    crypto::ring::sign::any_supported_type,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_graceful_shutdown::SubsystemHandle;
+use tokio_rustls::TlsAcceptor;
+
+use crate::ApiError;
+
+/// How often the certificate files are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TlsConfiguration {
+    /// PEM file holding the certificate chain, leaf first.
+    pub cert_path: PathBuf,
+    /// PEM file holding the private key.
+    pub key_path: PathBuf,
+}
+
+/// Serves whichever certificate was loaded last. Handshakes already in
+/// progress, and connections already established, keep the certificate they
+/// started with.
+pub(crate) struct ReloadingCertResolver {
+    config: TlsConfiguration,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl fmt::Debug for ReloadingCertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadingCertResolver").field("config", &self.config).finish()
+    }
+}
+
+impl ReloadingCertResolver {
+    pub(crate) fn new(config: TlsConfiguration) -> Result<Self, ApiError> {
+        let current = load_certified_key(&config)?;
+
+        Ok(Self {
+            config,
+            current: RwLock::new(Arc::new(current)),
+        })
+    }
+
+    pub(crate) fn reload(&self) -> Result<(), ApiError> {
+        let certified_key = load_certified_key(&self.config)?;
+        *self.current.write().unwrap_or_else(|err| err.into_inner()) = Arc::new(certified_key);
+
+        Ok(())
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let cert = std::fs::metadata(&self.config.cert_path).and_then(|m| m.modified()).ok()?;
+        let key = std::fs::metadata(&self.config.key_path).and_then(|m| m.modified()).ok()?;
+
+        Some((cert, key))
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap_or_else(|err| err.into_inner()).clone())
+    }
+}
+
+fn load_certified_key(config: &TlsConfiguration) -> Result<CertifiedKey, ApiError> {
+    let certs = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| tls_error(&config.cert_path, err))?;
+    if certs.is_empty() {
+        return Err(ApiError::Tls(format!("No certificates in {}", config.cert_path.display())));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(&config.key_path).map_err(|err| tls_error(&config.key_path, err))?;
+    let key = any_supported_type(&key).map_err(|err| tls_error(&config.key_path, err))?;
+
+    let certified_key = CertifiedKey::new(certs, key);
+    certified_key.keys_match().map_err(|err| tls_error(&config.key_path, err))?;
+
+    Ok(certified_key)
+}
+
+fn tls_error(path: &Path, err: impl fmt::Display) -> ApiError {
+    ApiError::Tls(format!("{} - {}", path.display(), err))
+}
+
+pub(crate) fn create_acceptor(resolver: Arc<ReloadingCertResolver>) -> TlsAcceptor {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    TlsAcceptor::from(Arc::new(config))
+}
+
+/// Reload the certificate when its files change on disk or on SIGHUP. A
+/// certificate that fails to load is logged and the previous one kept.
+pub(crate) async fn watch_certificate(resolver: Arc<ReloadingCertResolver>, subsys: SubsystemHandle) -> Result<(), ApiError> {
+    tracing::trace!("Watching TLS certificate");
+
+    let mut hangup = signal(SignalKind::hangup()).map_err(|err| ApiError::Tls(err.to_string()))?;
+    let mut ticker = tokio::time::interval(WATCH_INTERVAL);
+    let mut modified = resolver.modified();
+
+    loop {
+        let reason = tokio::select! {
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            }
+
+            _ = hangup.recv() => "SIGHUP",
+
+            _ = ticker.tick() => {
+                let latest = resolver.modified();
+                if latest.is_none() || latest == modified {
+                    continue;
+                }
+                "file change"
+            }
+        };
+
+        modified = resolver.modified();
+        match resolver.reload() {
+            Ok(()) => tracing::info!("Reloaded TLS certificate after {}", reason),
+            Err(err) => tracing::warn!("Failed to reload TLS certificate after {}: {}", reason, err),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use rcgen::{generate_simple_self_signed, CertifiedKey};
+
+    use super::{ReloadingCertResolver, TlsConfiguration};
+
+    fn write_cert(dir: &std::path::Path, name: &str) -> Vec<u8> {
+        let CertifiedKey { cert, key_pair } = generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), key_pair.serialize_pem()).unwrap();
+
+        cert.der().to_vec()
+    }
+
+    fn current(resolver: &ReloadingCertResolver) -> Vec<u8> {
+        resolver.current.read().unwrap().cert[0].to_vec()
+    }
+
+    #[test]
+    fn reloads_certificate() {
+        let dir = std::env::temp_dir().join(format!("auth-play-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first = write_cert(&dir, "first.test");
+        let resolver = Arc::new(
+            ReloadingCertResolver::new(TlsConfiguration {
+                cert_path: dir.join("cert.pem"),
+                key_path: dir.join("key.pem"),
+            })
+            .unwrap(),
+        );
+        assert_eq!(current(&resolver), first);
+
+        let second = write_cert(&dir, "second.test");
+        resolver.reload().unwrap();
+        assert_eq!(current(&resolver), second);
+
+        // A broken key keeps the certificate that was already loaded.
+        std::fs::write(dir.join("key.pem"), "not a key").unwrap();
+        assert!(resolver.reload().is_err());
+        assert_eq!(current(&resolver), second);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,118 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
+use auth_domain_api::AuthDomainApi;
 use axum::Router;
 use tower_http::timeout::TimeoutLayer;
 
-pub(crate) fn get_routes() -> Router<()> {
-    axum::Router::new().layer(TimeoutLayer::new(Duration::from_secs(2)))
+pub(crate) mod admin;
+pub(crate) mod me;
+
+pub(crate) fn get_routes(auth_domain_api: Arc<AuthDomainApi>, with_admin: bool) -> Router<()> {
+    let routes = axum::Router::new().nest("/me", me::get_routes(auth_domain_api.clone()));
+    let routes = match with_admin {
+        true => routes.nest("/admin", admin::get_routes(auth_domain_api)),
+        false => routes,
+    };
+
+    routes.layer(TimeoutLayer::new(Duration::from_secs(2)))
+}
+
+pub(crate) fn get_admin_routes(auth_domain_api: Arc<AuthDomainApi>) -> Router<()> {
+    axum::Router::new()
+        .nest("/admin", admin::get_routes(auth_domain_api))
+        .layer(TimeoutLayer::new(Duration::from_secs(2)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use auth_domain_models::auth::ADMIN_ROLE;
+    use auth_utils::arcbox;
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        Router,
+    };
+    use axum_login::{tower_sessions::SessionManagerLayer, AuthManagerLayerBuilder};
+    use tower::ServiceExt;
+
+    use crate::http::{
+        auth,
+        session::{cookie::Revocations, writes::SessionWrites, SessionAdapter},
+        testing::{self, TestAuditApi, TestAuthApi},
+        SessionConfiguration,
+    };
+
+    fn routes(roles: &[&str]) -> Router {
+        let auth_api = TestAuthApi {
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            ..Default::default()
+        };
+        let auth_domain_api = Arc::new(testing::auth_domain_api(auth_api));
+        let audit_api = TestAuditApi::default();
+        let config = SessionConfiguration::default();
+        let writes = Arc::new(SessionWrites::new(config.extension_interval, config.write_capacity));
+        let session_adapter = SessionAdapter::new(auth_domain_api.auth_api.clone(), arcbox!(audit_api), config, writes, Revocations::default());
+        let session_layer = SessionManagerLayer::new(session_adapter.clone()).with_secure(false);
+        let auth_layer = AuthManagerLayerBuilder::new(session_adapter.clone(), session_layer).build();
+
+        Router::new()
+            .nest("/auth", auth::get_routes(session_adapter))
+            .nest("/api/v1", super::get_routes(auth_domain_api, true))
+            .layer(auth_layer)
+    }
+
+    async fn sign_in(routes: &Router) -> String {
+        let request = Request::post("/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"email":"test@example.com","password":"password"}"#))
+            .unwrap();
+        let response = routes.clone().oneshot(request).await.unwrap();
+        let cookie = response.headers().get(header::SET_COOKIE).unwrap().to_str().unwrap();
+
+        cookie.split(';').next().unwrap().to_string()
+    }
+
+    async fn get(routes: &Router, path: &str, cookie: Option<&str>) -> (StatusCode, Option<String>) {
+        let request = match cookie {
+            Some(cookie) => Request::get(path).header(header::COOKIE, cookie),
+            None => Request::get(path),
+        };
+        let response = routes.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+        let challenge = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_string());
+
+        (response.status(), challenge)
+    }
+
+    #[tokio::test]
+    async fn challenges_anonymous_requests() {
+        let routes = routes(&[]);
+
+        for path in ["/api/v1/me/export", "/api/v1/admin/users/1/export"] {
+            let (status, challenge) = get(&routes, path, None).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", path);
+            assert_eq!(challenge.as_deref(), Some(r#"Session realm="auth-play""#), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn exports_for_signed_in_users() {
+        let routes = routes(&[]);
+        let cookie = sign_in(&routes).await;
+
+        assert_eq!(get(&routes, "/api/v1/me/export", Some(&cookie)).await, (StatusCode::OK, None));
+        assert_eq!(get(&routes, "/api/v1/admin/users/1/export", Some(&cookie)).await, (StatusCode::FORBIDDEN, None));
+    }
+
+    #[tokio::test]
+    async fn exports_any_user_for_admins() {
+        let routes = routes(&[ADMIN_ROLE]);
+        let cookie = sign_in(&routes).await;
+
+        assert_eq!(get(&routes, "/api/v1/admin/users/1/export", Some(&cookie)).await, (StatusCode::OK, None));
+    }
 }
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+
+use auth_domain_api::AuthDomainApi;
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+use crate::{http::session::adapter::AuthSession, ApiError};
+
+pub(crate) fn get_routes(auth_domain_api: Arc<AuthDomainApi>) -> Router<()> {
+    axum::Router::new()
+        .route("/audit", get(self::get::audit_events))
+        .route("/users/{id}", delete(self::delete::user))
+        .route("/users/{id}/export", get(self::get::user_export))
+        .route("/users/{id}/restore", post(self::post::user_restore))
+        .route("/webhooks", get(self::get::webhooks).post(self::post::webhook))
+        .route("/webhooks/{id}", delete(self::delete::webhook))
+        .route("/webhooks/{id}/deliveries", get(self::get::webhook_deliveries))
+        .with_state(auth_domain_api)
+}
+
+fn require_admin(auth_session: &AuthSession) -> Result<(), ApiError> {
+    match auth_session.user {
+        Some(ref user) if user.is_admin() => Ok(()),
+        Some(_) => Err(ApiError::Forbidden),
+        None => Err(ApiError::Unauthorized),
+    }
+}
+
+mod get {
+    use std::sync::Arc;
+
+    use auth_domain_api::{AuditEventInfo, AuthDomainApi, WebhookDeliveryInfo, WebhookEndpointInfo};
+    use auth_domain_models::audit::AuditFilter;
+    use axum::{
+        extract::{Path, Query, State},
+        response::Response,
+        Json,
+    };
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        http::{session::adapter::AuthSession, v1::me::export_response},
+        ApiError,
+    };
+
+    use super::require_admin;
+
+    const DEFAULT_PER_PAGE: u64 = 50;
+    const MAX_PER_PAGE: u64 = 500;
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct AuditQuery {
+        kind: Option<String>,
+        outcome: Option<String>,
+        actor_id: Option<i64>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        page: Option<u64>,
+        per_page: Option<u64>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub(crate) struct AuditResponse {
+        events: Vec<AuditEventInfo>,
+        page: u64,
+        per_page: u64,
+        total: u64,
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn audit_events(
+        auth_session: AuthSession,
+        State(auth_domain_api): State<Arc<AuthDomainApi>>,
+        Query(query): Query<AuditQuery>,
+    ) -> Result<Json<AuditResponse>, ApiError> {
+        require_admin(&auth_session)?;
+
+        let page = query.page.unwrap_or(1).max(1);
+        let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+        let filter = AuditFilter {
+            kind: query.kind.map(|kind| kind.parse()).transpose().map_err(ApiError::BadRequest)?,
+            outcome: query.outcome.map(|outcome| outcome.parse()).transpose().map_err(ApiError::BadRequest)?,
+            actor_id: query.actor_id,
+            from: query.from,
+            to: query.to,
+            offset: (page - 1) * per_page,
+            limit: per_page,
+        };
+
+        let result = auth_domain_api.audit_api.list_events(&filter).await?;
+
+        Ok(Json(AuditResponse {
+            events: result.events.into_iter().map(AuditEventInfo::from).collect(),
+            page,
+            per_page,
+            total: result.total,
+        }))
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn user_export(auth_session: AuthSession, State(auth_domain_api): State<Arc<AuthDomainApi>>, Path(id): Path<i64>) -> Result<Response, ApiError> {
+        require_admin(&auth_session)?;
+        let export = auth_domain_api.account_api.export_user(id).await?;
+
+        Ok(export_response(id, export))
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn webhooks(auth_session: AuthSession, State(auth_domain_api): State<Arc<AuthDomainApi>>) -> Result<Json<Vec<WebhookEndpointInfo>>, ApiError> {
+        require_admin(&auth_session)?;
+        let endpoints = auth_domain_api.webhook_api.list_endpoints().await?;
+
+        Ok(Json(endpoints.into_iter().map(WebhookEndpointInfo::from).collect()))
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct DeliveriesQuery {
+        page: Option<u64>,
+        per_page: Option<u64>,
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn webhook_deliveries(
+        auth_session: AuthSession,
+        State(auth_domain_api): State<Arc<AuthDomainApi>>,
+        Path(id): Path<i64>,
+        Query(query): Query<DeliveriesQuery>,
+    ) -> Result<Json<Vec<WebhookDeliveryInfo>>, ApiError> {
+        require_admin(&auth_session)?;
+
+        let page = query.page.unwrap_or(1).max(1);
+        let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+        let deliveries = auth_domain_api.webhook_api.list_deliveries(id, (page - 1) * per_page, per_page).await?;
+
+        Ok(Json(deliveries.into_iter().map(WebhookDeliveryInfo::from).collect()))
+    }
+}
+
+mod post {
+    use std::sync::Arc;
+
+    use auth_domain_api::{AuthDomainApi, WebhookEndpointInfo};
+    use auth_domain_models::webhook::WebhookEventType;
+    use axum::{
+        extract::{Path, State},
+        Json,
+    };
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        http::{context::ClientContext, session::adapter::AuthSession, v1::me::DeletionResponse},
+        ApiError,
+    };
+
+    use super::require_admin;
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn user_restore(
+        auth_session: AuthSession,
+        State(auth_domain_api): State<Arc<AuthDomainApi>>,
+        ClientContext(context): ClientContext,
+        Path(id): Path<i64>,
+    ) -> Result<Json<DeletionResponse>, ApiError> {
+        require_admin(&auth_session)?;
+        let user = auth_domain_api.account_api.cancel_deletion(id, &context).await?;
+
+        Ok(Json(DeletionResponse { purge_at: user.purge_at }))
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct NewWebhook {
+        url: String,
+        events: Vec<String>,
+        secret: Option<String>,
+    }
+
+    /// The secret is only ever returned here, when the endpoint is created.
+    #[derive(Debug, Serialize)]
+    pub(crate) struct WebhookCreated {
+        #[serde(flatten)]
+        endpoint: WebhookEndpointInfo,
+        secret: String,
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api, new_webhook))]
+    pub async fn webhook(
+        auth_session: AuthSession,
+        State(auth_domain_api): State<Arc<AuthDomainApi>>,
+        Json(new_webhook): Json<NewWebhook>,
+    ) -> Result<Json<WebhookCreated>, ApiError> {
+        require_admin(&auth_session)?;
+
+        let events = new_webhook
+            .events
+            .iter()
+            .map(|event| event.parse::<WebhookEventType>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ApiError::BadRequest)?;
+        if events.is_empty() {
+            return Err(ApiError::BadRequest("No webhook events given".to_string()));
+        }
+
+        let endpoint = auth_domain_api
+            .webhook_api
+            .add_endpoint(&new_webhook.url, &events, new_webhook.secret)
+            .await
+            .map_err(|err| match err {
+                auth_domain_api::Error::Message(message) => ApiError::BadRequest(message),
+                err => ApiError::DomainError(err),
+            })?;
+        let secret = endpoint.secret.clone();
+
+        Ok(Json(WebhookCreated {
+            endpoint: WebhookEndpointInfo::from(endpoint),
+            secret,
+        }))
+    }
+}
+
+mod delete {
+    use std::sync::Arc;
+
+    use auth_domain_api::AuthDomainApi;
+    use axum::{
+        extract::{Path, State},
+        http::StatusCode,
+        Json,
+    };
+
+    use crate::{
+        http::{context::ClientContext, session::adapter::AuthSession, v1::me::DeletionResponse},
+        ApiError,
+    };
+
+    use super::require_admin;
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn user(
+        auth_session: AuthSession,
+        State(auth_domain_api): State<Arc<AuthDomainApi>>,
+        ClientContext(context): ClientContext,
+        Path(id): Path<i64>,
+    ) -> Result<Json<DeletionResponse>, ApiError> {
+        require_admin(&auth_session)?;
+        let user = auth_domain_api.account_api.schedule_deletion(id, &context).await?;
+
+        Ok(Json(DeletionResponse { purge_at: user.purge_at }))
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn webhook(auth_session: AuthSession, State(auth_domain_api): State<Arc<AuthDomainApi>>, Path(id): Path<i64>) -> Result<StatusCode, ApiError> {
+        require_admin(&auth_session)?;
+        auth_domain_api.webhook_api.delete_endpoint(id).await?;
+
+        Ok(StatusCode::NO_CONTENT)
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use auth_domain_api::AuthDomainApi;
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+pub(crate) fn get_routes(auth_domain_api: Arc<AuthDomainApi>) -> Router<()> {
+    axum::Router::new()
+        .route("/", delete(self::delete::me))
+        .route("/export", get(self::get::export))
+        .route("/password", post(self::post::password))
+        .with_state(auth_domain_api)
+}
+
+mod get {
+    use std::sync::Arc;
+
+    use auth_domain_api::{AuthDomainApi, UserExport};
+    use axum::{
+        extract::State,
+        response::{IntoResponse, Response},
+        Json,
+    };
+    use hyper::header;
+
+    use crate::{http::session::adapter::AuthSession, ApiError};
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn export(auth_session: AuthSession, State(auth_domain_api): State<Arc<AuthDomainApi>>) -> Result<Response, ApiError> {
+        let user = auth_session.user.ok_or(ApiError::Unauthorized)?;
+        let export = auth_domain_api.account_api.export_user(user.id).await?;
+
+        Ok(export_response(user.id, export))
+    }
+
+    pub(crate) fn export_response(id: i64, export: UserExport) -> Response {
+        let disposition = format!("attachment; filename=\"auth-play-export-{}.json\"", id);
+
+        ([(header::CONTENT_DISPOSITION, disposition)], Json(export)).into_response()
+    }
+}
+
+mod post {
+    use std::sync::Arc;
+
+    use auth_domain_api::AuthDomainApi;
+    use axum::{extract::State, http::StatusCode, Json};
+    use serde::Deserialize;
+
+    use crate::{
+        http::{
+            context::ClientContext,
+            session::adapter::{AuthSession, User},
+        },
+        ApiError,
+    };
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct PasswordRequest {
+        current_password: String,
+        new_password: String,
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api, payload))]
+    pub async fn password(
+        mut auth_session: AuthSession,
+        State(auth_domain_api): State<Arc<AuthDomainApi>>,
+        ClientContext(context): ClientContext,
+        Json(payload): Json<PasswordRequest>,
+    ) -> Result<StatusCode, ApiError> {
+        let user = auth_session.user.clone().ok_or(ApiError::Unauthorized)?;
+        let user_info = auth_domain_api
+            .auth_api
+            .change_password(user.id, &payload.current_password, &payload.new_password, &context)
+            .await?;
+
+        // The session auth hash is derived from the password, so refresh it
+        // to keep this session logged in while invalidating all others.
+        let _result = auth_session.login(&User::from(user_info)).await;
+
+        Ok(StatusCode::NO_CONTENT)
+    }
+}
+
+mod delete {
+    use std::sync::Arc;
+
+    use auth_domain_api::AuthDomainApi;
+    use axum::{extract::State, Json};
+    use chrono::{DateTime, Utc};
+    use serde::Serialize;
+
+    use crate::{
+        http::{context::ClientContext, session::adapter::AuthSession},
+        ApiError,
+    };
+
+    #[derive(Debug, Serialize)]
+    pub(crate) struct DeletionResponse {
+        pub purge_at: Option<DateTime<Utc>>,
+    }
+
+    #[tracing::instrument(level = "trace", skip(auth_session, auth_domain_api))]
+    pub async fn me(
+        mut auth_session: AuthSession,
+        State(auth_domain_api): State<Arc<AuthDomainApi>>,
+        ClientContext(context): ClientContext,
+    ) -> Result<Json<DeletionResponse>, ApiError> {
+        let user = auth_session.user.clone().ok_or(ApiError::Unauthorized)?;
+        let user = auth_domain_api.account_api.schedule_deletion(user.id, &context).await?;
+        let _result = auth_session.logout().await;
+
+        Ok(Json(DeletionResponse { purge_at: user.purge_at }))
+    }
+}
+
+pub(crate) use delete::DeletionResponse;
+pub(crate) use get::export_response;
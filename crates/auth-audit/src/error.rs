@@ -0,0 +1,8 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Bad syslog address - {}", _0)]
+    BadSyslogAddress(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
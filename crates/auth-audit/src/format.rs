@@ -0,0 +1,184 @@
+use auth_domain_models::audit::{AuditEvent, AuditOutcome};
+use chrono::SecondsFormat;
+use serde::Deserialize;
+use serde_json::json;
+
+const PRODUCT: &str = "auth-play";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Structured data ID used in RFC 5424 messages.
+const SD_ID: &str = "audit@32473";
+
+// RFC 5424 facility 10 is security/authorization (authpriv).
+const FACILITY_AUTHPRIV: u8 = 10;
+const SEVERITY_WARNING: u8 = 4;
+const SEVERITY_INFO: u8 = 6;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat {
+    #[default]
+    Json,
+    Cef,
+}
+
+impl AuditFormat {
+    pub fn format(&self, event: &AuditEvent) -> String {
+        match self {
+            AuditFormat::Json => to_json(event),
+            AuditFormat::Cef => to_cef(event),
+        }
+    }
+}
+
+pub fn to_json(event: &AuditEvent) -> String {
+    json!({
+        "id": event.id,
+        "occurred_at": event.occurred_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        "kind": event.kind.as_str(),
+        "outcome": event.outcome.as_str(),
+        "actor_id": event.actor_id,
+        "actor_email": event.actor_email,
+        "ip": event.ip,
+        "user_agent": event.user_agent,
+        "detail": event.detail,
+    })
+    .to_string()
+}
+
+/// Format an event as an ArcSight Common Event Format record.
+pub fn to_cef(event: &AuditEvent) -> String {
+    let severity = match event.outcome {
+        AuditOutcome::Success => 3,
+        AuditOutcome::Failure => 6,
+    };
+
+    let mut extensions = vec![
+        format!("rt={}", event.occurred_at.timestamp_millis()),
+        format!("outcome={}", event.outcome.as_str()),
+        format!("externalId={}", event.id),
+    ];
+    if let Some(ref ip) = event.ip {
+        extensions.push(format!("src={}", cef_extension(ip)));
+    }
+    if let Some(actor_id) = event.actor_id {
+        extensions.push(format!("suid={}", actor_id));
+    }
+    if let Some(ref actor_email) = event.actor_email {
+        extensions.push(format!("suser={}", cef_extension(actor_email)));
+    }
+    if let Some(ref user_agent) = event.user_agent {
+        extensions.push(format!("requestClientApplication={}", cef_extension(user_agent)));
+    }
+    if let Some(ref detail) = event.detail {
+        extensions.push(format!("msg={}", cef_extension(detail)));
+    }
+
+    format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        cef_header(PRODUCT),
+        cef_header(PRODUCT),
+        cef_header(VERSION),
+        cef_header(event.kind.as_str()),
+        cef_header(&format!("{} {}", event.kind, event.outcome)),
+        severity,
+        extensions.join(" ")
+    )
+}
+
+/// Wrap a message in an RFC 5424 syslog header, carrying the event fields as
+/// structured data.
+pub fn to_rfc5424(event: &AuditEvent, message: &str, hostname: &str, app_name: &str) -> String {
+    let severity = match event.outcome {
+        AuditOutcome::Success => SEVERITY_INFO,
+        AuditOutcome::Failure => SEVERITY_WARNING,
+    };
+    let priority = FACILITY_AUTHPRIV * 8 + severity;
+
+    let mut params = vec![format!("outcome=\"{}\"", event.outcome.as_str())];
+    if let Some(actor_id) = event.actor_id {
+        params.push(format!("actorId=\"{}\"", actor_id));
+    }
+    if let Some(ref actor_email) = event.actor_email {
+        params.push(format!("actorEmail=\"{}\"", sd_param(actor_email)));
+    }
+    if let Some(ref ip) = event.ip {
+        params.push(format!("ip=\"{}\"", sd_param(ip)));
+    }
+
+    format!(
+        "<{}>1 {} {} {} {} {} [{} {}] {}",
+        priority,
+        event.occurred_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        header_field(hostname, 255),
+        header_field(app_name, 48),
+        std::process::id(),
+        header_field(event.kind.as_str(), 32),
+        SD_ID,
+        params.join(" "),
+        message
+    )
+}
+
+fn cef_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+fn cef_extension(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('=', "\\=").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn sd_param(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]")
+}
+
+fn header_field(value: &str, max_len: usize) -> String {
+    let value: String = value.chars().filter(|c| c.is_ascii_graphic()).take(max_len).collect();
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use auth_domain_models::audit::{AuditEvent, AuditEventKind, AuditOutcome};
+    use chrono::{TimeZone, Utc};
+
+    use super::{to_cef, to_json, to_rfc5424};
+
+    fn event() -> AuditEvent {
+        AuditEvent {
+            id: 7,
+            occurred_at: Utc.with_ymd_and_hms(2025, 2, 1, 10, 15, 0).unwrap(),
+            kind: AuditEventKind::Login,
+            outcome: AuditOutcome::Failure,
+            actor_id: None,
+            actor_email: Some("a=b@example.com".to_string()),
+            ip: Some("10.0.0.1".to_string()),
+            user_agent: Some("curl/8.0".to_string()),
+            detail: Some("Invalid email or password".to_string()),
+        }
+    }
+
+    #[test]
+    fn cef() {
+        let cef = to_cef(&event());
+
+        assert!(cef.starts_with("CEF:0|auth-play|auth-play|"));
+        assert!(cef.contains("|login|login failure|6|rt=1738404900000 outcome=failure externalId=7 src=10.0.0.1 "));
+        assert!(cef.contains("suser=a\\=b@example.com"));
+        assert!(cef.ends_with("msg=Invalid email or password"));
+    }
+
+    #[test]
+    fn rfc5424() {
+        let event = event();
+        let message = to_rfc5424(&event, &to_json(&event), "host name", "auth-play");
+        let header = format!("<84>1 2025-02-01T10:15:00.000000Z hostname auth-play {} login ", std::process::id());
+
+        assert!(message.starts_with(&header));
+        assert!(message.contains("[audit@32473 outcome=\"failure\" actorEmail=\"a=b@example.com\" ip=\"10.0.0.1\"] {"));
+    }
+}
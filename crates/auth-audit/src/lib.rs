@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+
+use auth_domain_models::audit::AuditEvent;
+use sinks::{syslog::SyslogTransport, AuditSink, FileSink, SyslogSink};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+pub mod error;
+pub mod format;
+pub mod sinks;
+
+pub use error::*;
+pub use format::AuditFormat;
+
+#[derive(Debug, Clone)]
+pub struct FileSinkConfiguration {
+    pub path: PathBuf,
+    pub format: AuditFormat,
+    pub max_bytes: u64,
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyslogSinkConfiguration {
+    pub address: String,
+    pub format: AuditFormat,
+    pub hostname: String,
+    pub app_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    /// Events queued beyond this are dropped rather than blocking callers.
+    pub buffer_size: usize,
+    pub file: Option<FileSinkConfiguration>,
+    pub syslog: Option<SyslogSinkConfiguration>,
+}
+
+/// Cheap handle used to hand events to the dispatcher without waiting on
+/// any sink.
+#[derive(Clone)]
+pub struct AuditSender {
+    sender: Sender<AuditEvent>,
+}
+
+impl AuditSender {
+    pub fn send(&self, event: AuditEvent) {
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => tracing::warn!("Audit sink buffer full, dropping {} event {}", event.kind, event.id),
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+pub struct AuditDispatcher {
+    receiver: Receiver<AuditEvent>,
+    sinks: Vec<Box<dyn AuditSink>>,
+}
+
+impl AuditDispatcher {
+    async fn dispatch(&mut self, event: &AuditEvent) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.write(event).await {
+                tracing::warn!("Audit sink {} failed: {}", sink.name(), err);
+            }
+        }
+    }
+
+    async fn flush(&mut self) {
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.flush().await {
+                tracing::warn!("Audit sink {} failed to flush: {}", sink.name(), err);
+            }
+        }
+    }
+}
+
+pub fn create_audit_sinks(config: &Configuration) -> Result<(AuditSender, AuditDispatcher), Error> {
+    let mut sinks: Vec<Box<dyn AuditSink>> = Vec::new();
+
+    if let Some(ref file) = config.file {
+        sinks.push(Box::new(FileSink::new(file.path.clone(), file.format, file.max_bytes, file.max_files)));
+    }
+    if let Some(ref syslog) = config.syslog {
+        let transport: SyslogTransport = syslog.address.parse()?;
+        sinks.push(Box::new(SyslogSink::new(
+            transport,
+            syslog.format,
+            syslog.hostname.clone(),
+            syslog.app_name.clone(),
+        )));
+    }
+
+    let (sender, receiver) = mpsc::channel(config.buffer_size.max(1));
+
+    Ok((AuditSender { sender }, AuditDispatcher { receiver, sinks }))
+}
+
+pub async fn start_dispatcher(mut dispatcher: AuditDispatcher, subsys: SubsystemHandle) -> Result<(), Error> {
+    tracing::trace!("Starting audit dispatcher with {} sinks", dispatcher.sinks.len());
+
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            }
+
+            event = dispatcher.receiver.recv() => {
+                match event {
+                    Some(event) => {
+                        dispatcher.dispatch(&event).await;
+                        if dispatcher.receiver.is_empty() {
+                            dispatcher.flush().await;
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+
+    // Deliver whatever was queued before shutdown was requested.
+    dispatcher.receiver.close();
+    while let Some(event) = dispatcher.receiver.recv().await {
+        dispatcher.dispatch(&event).await;
+    }
+    dispatcher.flush().await;
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use async_trait::async_trait;
+use auth_domain_models::audit::AuditEvent;
+
+use crate::Error;
+
+pub mod file;
+pub mod syslog;
+
+pub use file::FileSink;
+pub use syslog::SyslogSink;
+
+#[async_trait]
+pub trait AuditSink: Send {
+    fn name(&self) -> &str;
+    async fn write(&mut self, event: &AuditEvent) -> Result<(), Error>;
+    async fn flush(&mut self) -> Result<(), Error>;
+}
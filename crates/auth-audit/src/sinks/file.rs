@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use auth_domain_models::audit::AuditEvent;
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::{format::AuditFormat, Error};
+
+use super::AuditSink;
+
+/// Appends one event per line to a file, rotating it once it reaches
+/// `max_bytes`. Rotated files are suffixed `.1` (newest) to `.max_files`.
+pub struct FileSink {
+    path: PathBuf,
+    format: AuditFormat,
+    max_bytes: u64,
+    max_files: usize,
+    file: Option<File>,
+    size: u64,
+}
+
+impl FileSink {
+    pub fn new(path: PathBuf, format: AuditFormat, max_bytes: u64, max_files: usize) -> Self {
+        Self {
+            path,
+            format,
+            max_bytes,
+            max_files,
+            file: None,
+            size: 0,
+        }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+
+        path.into()
+    }
+
+    async fn open(&mut self) -> Result<&mut File, Error> {
+        if self.file.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(&self.path).await?;
+            self.size = file.metadata().await?.len();
+            self.file = Some(file);
+        }
+
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    async fn rotate(&mut self) -> Result<(), Error> {
+        if let Some(mut file) = self.file.take() {
+            file.flush().await?;
+        }
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path).await?;
+            return Ok(());
+        }
+
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if fs::try_exists(&from).await? {
+                fs::rename(&from, self.rotated_path(index + 1)).await?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1)).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditSink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn write(&mut self, event: &AuditEvent) -> Result<(), Error> {
+        let mut line = self.format.format(event);
+        line.push('\n');
+
+        self.open().await?;
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate().await?;
+        }
+
+        let file = self.open().await?;
+        if let Err(err) = file.write_all(line.as_bytes()).await {
+            self.file = None;
+            return Err(err.into());
+        }
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        if let Some(ref mut file) = self.file {
+            file.flush().await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use auth_domain_models::audit::{AuditEvent, AuditEventKind, AuditOutcome};
+    use chrono::Utc;
+
+    use crate::{format::AuditFormat, sinks::AuditSink};
+
+    use super::FileSink;
+
+    #[tokio::test]
+    async fn rotates() {
+        let dir = std::env::temp_dir().join(format!("auth-audit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+
+        let event = AuditEvent {
+            id: 1,
+            occurred_at: Utc::now(),
+            kind: AuditEventKind::Logout,
+            outcome: AuditOutcome::Success,
+            actor_id: Some(1),
+            actor_email: None,
+            ip: None,
+            user_agent: None,
+            detail: None,
+        };
+        let line_len = AuditFormat::Json.format(&event).len() as u64 + 1;
+
+        let mut sink = FileSink::new(path.clone(), AuditFormat::Json, line_len * 2, 2);
+        for _ in 0..7 {
+            sink.write(&event).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), line_len);
+        assert_eq!(std::fs::metadata(dir.join("audit.log.1")).unwrap().len(), line_len * 2);
+        assert_eq!(std::fs::metadata(dir.join("audit.log.2")).unwrap().len(), line_len * 2);
+        assert!(!dir.join("audit.log.3").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
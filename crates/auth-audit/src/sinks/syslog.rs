@@ -0,0 +1,127 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use async_trait::async_trait;
+use auth_domain_models::audit::AuditEvent;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{lookup_host, TcpStream, UdpSocket, UnixDatagram},
+};
+
+use crate::{
+    format::{to_rfc5424, AuditFormat},
+    Error,
+};
+
+use super::AuditSink;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTransport {
+    Udp(String),
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl std::str::FromStr for SyslogTransport {
+    type Err = Error;
+
+    /// Parse `udp://host:port`, `tcp://host:port` or `unix:///path/to/socket`.
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        match address.split_once("://") {
+            Some(("udp", host)) if !host.is_empty() => Ok(SyslogTransport::Udp(host.to_string())),
+            Some(("tcp", host)) if !host.is_empty() => Ok(SyslogTransport::Tcp(host.to_string())),
+            Some(("unix", path)) if !path.is_empty() => Ok(SyslogTransport::Unix(PathBuf::from(path))),
+            _ => Err(Error::BadSyslogAddress(address.to_string())),
+        }
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Unix(UnixDatagram),
+}
+
+/// Sends RFC 5424 messages to a syslog collector. TCP uses octet-counting
+/// framing (RFC 6587). Connections are re-established after a failed write.
+pub struct SyslogSink {
+    transport: SyslogTransport,
+    format: AuditFormat,
+    hostname: String,
+    app_name: String,
+    connection: Option<Connection>,
+}
+
+impl SyslogSink {
+    pub fn new(transport: SyslogTransport, format: AuditFormat, hostname: String, app_name: String) -> Self {
+        Self {
+            transport,
+            format,
+            hostname,
+            app_name,
+            connection: None,
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection, Error> {
+        match self.transport {
+            SyslogTransport::Udp(ref host) => {
+                let addr = resolve(host).await?;
+                let bind: SocketAddr = if addr.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+                let socket = UdpSocket::bind(bind).await?;
+                socket.connect(addr).await?;
+
+                Ok(Connection::Udp(socket))
+            }
+            SyslogTransport::Tcp(ref host) => Ok(Connection::Tcp(TcpStream::connect(resolve(host).await?).await?)),
+            SyslogTransport::Unix(ref path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+
+                Ok(Connection::Unix(socket))
+            }
+        }
+    }
+
+    async fn send(&mut self, message: &str) -> Result<(), Error> {
+        if self.connection.is_none() {
+            self.connection = Some(self.connect().await?);
+        }
+
+        match self.connection.as_mut().unwrap() {
+            Connection::Udp(socket) => socket.send(message.as_bytes()).await.map(|_| ()),
+            Connection::Tcp(stream) => stream.write_all(format!("{} {}", message.len(), message).as_bytes()).await,
+            Connection::Unix(socket) => socket.send(message.as_bytes()).await.map(|_| ()),
+        }
+        .map_err(Error::from)
+    }
+}
+
+async fn resolve(host: &str) -> Result<SocketAddr, Error> {
+    lookup_host(host).await?.next().ok_or_else(|| Error::BadSyslogAddress(host.to_string()))
+}
+
+#[async_trait]
+impl AuditSink for SyslogSink {
+    fn name(&self) -> &str {
+        "syslog"
+    }
+
+    async fn write(&mut self, event: &AuditEvent) -> Result<(), Error> {
+        let message = to_rfc5424(event, &self.format.format(event), &self.hostname, &self.app_name);
+
+        let result = self.send(&message).await;
+        if result.is_err() {
+            self.connection = None;
+        }
+
+        result
+    }
+
+    async fn flush(&mut self) -> Result<(), Error> {
+        if let Some(Connection::Tcp(ref mut stream)) = self.connection {
+            stream.flush().await?;
+        }
+
+        Ok(())
+    }
+}
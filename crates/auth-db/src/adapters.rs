@@ -1,5 +1,11 @@
+pub mod audit;
+pub mod notification;
 pub mod session;
 pub mod user;
+pub mod webhook;
 
+pub use audit::AuditAdapter;
+pub use notification::{NotificationAdapter, NotificationListener};
 pub use session::SessionAdapter;
 pub use user::UserAdapter;
+pub use webhook::WebhookAdapter;
@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use auth_domain_models::audit::{AuditEvent, AuditFilter, AuditPage, NewAuditEvent};
+use chrono::{TimeZone, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, Condition, DatabaseTransaction, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+
+use crate::{
+    entities::{audit_events, prelude},
+    Error,
+};
+
+#[async_trait]
+pub trait AuditAdapter: Send + Sync {
+    async fn add_event(&self, tx: &mut DatabaseTransaction, event: &NewAuditEvent) -> Result<AuditEvent, Error>;
+    async fn list_events(&self, tx: &mut DatabaseTransaction, filter: &AuditFilter) -> Result<AuditPage, Error>;
+    async fn anonymize_actor(&self, tx: &mut DatabaseTransaction, actor_id: i64) -> Result<u64, Error>;
+}
+
+pub(crate) struct AuditAdapterImpl {}
+
+impl AuditAdapterImpl {
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+
+    fn from_model(model: audit_events::Model) -> Result<AuditEvent, Error> {
+        Ok(AuditEvent {
+            id: model.id,
+            occurred_at: Utc.from_utc_datetime(&model.occurred_at),
+            kind: model.kind.parse().map_err(Error::Message)?,
+            outcome: model.outcome.parse().map_err(Error::Message)?,
+            actor_id: model.actor_id,
+            actor_email: model.actor_email,
+            ip: model.ip,
+            user_agent: model.user_agent,
+            detail: model.detail,
+        })
+    }
+
+    fn condition(filter: &AuditFilter) -> Condition {
+        let mut condition = Condition::all();
+
+        if let Some(kind) = filter.kind {
+            condition = condition.add(audit_events::Column::Kind.eq(kind.as_str()));
+        }
+        if let Some(outcome) = filter.outcome {
+            condition = condition.add(audit_events::Column::Outcome.eq(outcome.as_str()));
+        }
+        if let Some(actor_id) = filter.actor_id {
+            condition = condition.add(audit_events::Column::ActorId.eq(actor_id));
+        }
+        if let Some(from) = filter.from {
+            condition = condition.add(audit_events::Column::OccurredAt.gte(from.naive_utc()));
+        }
+        if let Some(to) = filter.to {
+            condition = condition.add(audit_events::Column::OccurredAt.lt(to.naive_utc()));
+        }
+
+        condition
+    }
+}
+
+#[async_trait]
+impl AuditAdapter for AuditAdapterImpl {
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn add_event(&self, tx: &mut DatabaseTransaction, event: &NewAuditEvent) -> Result<AuditEvent, Error> {
+        let new_event = audit_events::ActiveModel {
+            occurred_at: Set(Utc::now().naive_utc()),
+            kind: Set(event.kind.as_str().to_string()),
+            outcome: Set(event.outcome.as_str().to_string()),
+            actor_id: Set(event.actor_id),
+            actor_email: Set(event.actor_email.clone()),
+            ip: Set(event.ip.clone()),
+            user_agent: Set(event.user_agent.clone()),
+            detail: Set(event.detail.clone()),
+            ..Default::default()
+        };
+        let event_model = new_event.insert(tx).await?;
+
+        AuditAdapterImpl::from_model(event_model)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn list_events(&self, tx: &mut DatabaseTransaction, filter: &AuditFilter) -> Result<AuditPage, Error> {
+        let condition = AuditAdapterImpl::condition(filter);
+
+        let total = prelude::AuditEvents::find().filter(condition.clone()).count(&*tx).await?;
+        let models = prelude::AuditEvents::find()
+            .filter(condition)
+            .order_by_desc(audit_events::Column::OccurredAt)
+            .order_by_desc(audit_events::Column::Id)
+            .offset(filter.offset)
+            .limit(filter.limit)
+            .all(&*tx)
+            .await?;
+
+        let events = models.into_iter().map(AuditAdapterImpl::from_model).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AuditPage { events, total })
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn anonymize_actor(&self, tx: &mut DatabaseTransaction, actor_id: i64) -> Result<u64, Error> {
+        let result = prelude::AuditEvents::update_many()
+            .col_expr(audit_events::Column::ActorEmail, Expr::value(Option::<String>::None))
+            .col_expr(audit_events::Column::Ip, Expr::value(Option::<String>::None))
+            .col_expr(audit_events::Column::UserAgent, Expr::value(Option::<String>::None))
+            .filter(audit_events::Column::ActorId.eq(actor_id))
+            .exec(tx)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
@@ -0,0 +1,114 @@
+use async_trait::async_trait;
+use sea_orm::{
+    sqlx::postgres::{PgListener, PgPool},
+    ConnectionTrait, DatabaseTransaction, DbBackend, Statement,
+};
+
+use crate::Error;
+
+#[async_trait]
+pub trait NotificationAdapter: Send + Sync {
+    /// Notify the channel's listeners, once and only if the transaction
+    /// commits.
+    async fn notify(&self, tx: &mut DatabaseTransaction, channel: &str, payload: &str) -> Result<(), Error>;
+}
+
+pub(crate) struct NotificationAdapterImpl {}
+
+impl NotificationAdapterImpl {
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+}
+
+#[async_trait]
+impl NotificationAdapter for NotificationAdapterImpl {
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn notify(&self, tx: &mut DatabaseTransaction, channel: &str, payload: &str) -> Result<(), Error> {
+        let statement = Statement::from_sql_and_values(DbBackend::Postgres, "SELECT pg_notify($1, $2)", [channel.into(), payload.into()]);
+        tx.execute(statement).await?;
+
+        Ok(())
+    }
+}
+
+/// A connection listening on a notification channel.
+pub struct NotificationListener {
+    listener: PgListener,
+}
+
+impl NotificationListener {
+    pub(crate) async fn connect(pool: &PgPool, channel: &str) -> Result<Self, Error> {
+        let mut listener = PgListener::connect_with(pool).await.map_err(|err| Error::Any(err.into()))?;
+        listener.eager_reconnect(false);
+        listener.listen(channel).await.map_err(|err| Error::Any(err.into()))?;
+
+        Ok(Self { listener })
+    }
+
+    /// The next notification's payload. Fails once the connection is lost,
+    /// as notifications sent meanwhile are missed.
+    pub async fn recv(&mut self) -> Result<String, Error> {
+        match self.listener.try_recv().await {
+            Ok(Some(notification)) => Ok(notification.payload().to_string()),
+            Ok(None) => Err(Error::Message("Lost the notification connection".to_string())),
+            Err(err) => Err(Error::Any(err.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use sea_orm::{ConnectionTrait, DbBackend, Statement};
+
+    use crate::{connect_database, testing::database_url, Configuration, Error};
+
+    #[tokio::test]
+    #[ignore = "needs a local Postgres"]
+    async fn notifies_on_commit_only() {
+        let adapters = connect_database(&Configuration::new(database_url())).await.unwrap();
+        let channel = format!("test_{}", uuid::Uuid::new_v4().simple());
+        let mut listener = adapters.repository.listen(&channel).await.unwrap();
+
+        for (payload, commit) in [("rolled back", false), ("committed", true)] {
+            let _ = adapters
+                .repository
+                .transaction(|tx| {
+                    let notification_adapter = adapters.notification_adapter.clone();
+                    let channel = channel.clone();
+                    Box::pin(async move {
+                        notification_adapter.notify(tx, &channel, payload).await?;
+                        match commit {
+                            true => Ok(()),
+                            false => Err(Error::NotFound),
+                        }
+                    })
+                })
+                .await;
+        }
+
+        let payload = tokio::time::timeout(Duration::from_secs(5), listener.recv()).await.unwrap().unwrap();
+        assert_eq!(payload, "committed");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local Postgres"]
+    async fn fails_once_the_connection_is_lost() {
+        let adapters = connect_database(&Configuration::new(database_url())).await.unwrap();
+        let channel = format!("test_{}", uuid::Uuid::new_v4().simple());
+        let mut listener = adapters.repository.listen(&channel).await.unwrap();
+
+        let terminate = format!("SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE query = 'LISTEN \"{}\"'", channel);
+        adapters
+            .repository
+            .database
+            .execute(Statement::from_string(DbBackend::Postgres, terminate))
+            .await
+            .unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), listener.recv()).await.unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -1,11 +1,13 @@
 use async_trait::async_trait;
-use auth_domain_models::auth::{NewSession, Session, SessionId};
-use chrono::{TimeZone, Utc};
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, Set};
+use auth_domain_models::auth::{NewSession, Session, SessionId, SessionRevocation};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseTransaction, DbBackend, EntityTrait, QueryFilter, Set, Statement, TryInsertResult,
+};
 use uuid::Uuid;
 
 use crate::{
-    entities::{prelude, sessions},
+    entities::{prelude, session_revocations, sessions},
     Error,
 };
 
@@ -13,10 +15,26 @@ use crate::{
 pub trait SessionAdapter: Send + Sync {
     async fn create_session(&self, tx: &mut DatabaseTransaction, new_session: &NewSession) -> Result<Session, Error>;
     async fn save_session(&self, tx: &mut DatabaseTransaction, session: &Session) -> Result<Session, Error>;
+    /// Extend the expiries of sessions, but never shorten them. Returns how
+    /// many were extended.
+    async fn extend_sessions(&self, tx: &mut DatabaseTransaction, extensions: &[(SessionId, DateTime<Utc>)]) -> Result<u64, Error>;
     async fn load_session(&self, tx: &mut DatabaseTransaction, session_id: &SessionId) -> Result<Option<Session>, Error>;
-    async fn delete_session(&self, tx: &mut DatabaseTransaction, session_id: &SessionId) -> Result<(), Error>;
+    async fn delete_session(&self, tx: &mut DatabaseTransaction, session_id: &SessionId) -> Result<Option<Session>, Error>;
+    /// Move a session to a new id. The old id stops working in the same
+    /// transaction the new one is created.
+    async fn cycle_session(&self, tx: &mut DatabaseTransaction, session_id: &SessionId) -> Result<Option<Session>, Error>;
+
+    async fn list_user_sessions(&self, tx: &mut DatabaseTransaction, user_id: i64) -> Result<Vec<Session>, Error>;
+    async fn delete_user_sessions(&self, tx: &mut DatabaseTransaction, user_id: i64) -> Result<u64, Error>;
+
+    /// Record a revocation. Revocations that have expired are dropped.
+    async fn revoke_sessions(&self, tx: &mut DatabaseTransaction, revocation: &SessionRevocation) -> Result<(), Error>;
+    async fn list_revocations(&self, tx: &mut DatabaseTransaction) -> Result<Vec<SessionRevocation>, Error>;
 }
 
+/// Ids drawn for a new session before giving up.
+const INSERT_ATTEMPTS: usize = 3;
+
 pub(crate) struct SessionAdapterImpl {}
 
 impl SessionAdapterImpl {
@@ -24,17 +42,42 @@ impl SessionAdapterImpl {
         Self {}
     }
 
+    /// Insert a session under a fresh id. Ids are random, and the primary key
+    /// keeps them unique: on the all but impossible collision, another is
+    /// drawn.
+    async fn insert_session(&self, tx: &mut DatabaseTransaction, user_id: Option<i64>, data: Vec<u8>, expiry: NaiveDateTime) -> Result<sessions::Model, Error> {
+        for _ in 0..INSERT_ATTEMPTS {
+            let new_session = sessions::ActiveModel {
+                uuid: Set(Uuid::new_v4()),
+                user_id: Set(user_id),
+                data: Set(data.clone()),
+                expiry: Set(expiry),
+            };
+            match prelude::Sessions::insert(new_session).on_conflict_do_nothing().exec_with_returning(tx).await? {
+                TryInsertResult::Inserted(model) => return Ok(model),
+                TryInsertResult::Conflicted | TryInsertResult::Empty => tracing::warn!("Session id collision, drawing another"),
+            }
+        }
+
+        Err(Error::Message("Couldn't draw a free session id".to_string()))
+    }
+
     fn from_model(model: sessions::Model) -> Session {
         Session {
             id: model.uuid,
+            user_id: model.user_id,
             data: model.data,
             expiry: Utc.from_local_datetime(&model.expiry).unwrap(),
         }
     }
-    async fn id_exists(&self, tx: &mut DatabaseTransaction, id: &SessionId) -> Result<bool, Error> {
-        let model = prelude::Sessions::find().filter(sessions::Column::Uuid.eq(*id)).one(tx).await?;
 
-        Ok(model.is_some())
+    fn from_revocation_model(model: session_revocations::Model) -> SessionRevocation {
+        SessionRevocation {
+            session_id: model.session_id,
+            user_id: model.user_id,
+            revoked_at: Utc.from_local_datetime(&model.revoked_at).unwrap(),
+            expiry: Utc.from_local_datetime(&model.expiry).unwrap(),
+        }
     }
 }
 
@@ -42,39 +85,43 @@ impl SessionAdapterImpl {
 impl SessionAdapter for SessionAdapterImpl {
     #[tracing::instrument(level = "trace", skip(self, tx, new_session))]
     async fn create_session(&self, tx: &mut DatabaseTransaction, new_session: &NewSession) -> Result<Session, Error> {
-        let mut session_id = Uuid::now_v7();
-
-        while self.id_exists(tx, &session_id).await? {
-            session_id = Uuid::now_v7();
-        }
-
-        let new_session = sessions::ActiveModel {
-            uuid: Set(session_id),
-            data: Set(new_session.data.clone()),
-            expiry: Set(new_session.expiry.naive_utc()),
-            ..Default::default()
-        };
-        let session_model = new_session.insert(tx).await?;
+        let session_model = self
+            .insert_session(tx, new_session.user_id, new_session.data.clone(), new_session.expiry.naive_utc())
+            .await?;
 
         Ok(SessionAdapterImpl::from_model(session_model))
     }
 
     #[tracing::instrument(level = "trace", skip(self, tx, session))]
     async fn save_session(&self, tx: &mut DatabaseTransaction, session: &Session) -> Result<Session, Error> {
-        let model = prelude::Sessions::find().filter(sessions::Column::Uuid.eq(session.id)).one(tx).await?;
-        if model.is_none() {
+        let result = prelude::Sessions::update_many()
+            .col_expr(sessions::Column::UserId, Expr::value(session.user_id))
+            .col_expr(sessions::Column::Data, Expr::value(session.data.clone()))
+            .col_expr(sessions::Column::Expiry, Expr::value(session.expiry.naive_utc()))
+            .filter(sessions::Column::Uuid.eq(session.id))
+            .exec(tx)
+            .await?;
+        if result.rows_affected == 0 {
             return Err(Error::NotFound);
         }
-        let model = model.unwrap();
 
-        let mut active_session: sessions::ActiveModel = model.into();
-
-        active_session.data = Set(session.data.clone());
-        active_session.expiry = Set(session.expiry.naive_utc());
+        Ok(session.clone())
+    }
 
-        let model = active_session.update(tx).await?;
+    #[tracing::instrument(level = "trace", skip(self, tx, extensions))]
+    async fn extend_sessions(&self, tx: &mut DatabaseTransaction, extensions: &[(SessionId, DateTime<Utc>)]) -> Result<u64, Error> {
+        let ids: Vec<SessionId> = extensions.iter().map(|(id, _)| *id).collect();
+        let expiries: Vec<NaiveDateTime> = extensions.iter().map(|(_, expiry)| expiry.naive_utc()).collect();
+        let statement = Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "UPDATE sessions SET expiry = extension.expiry \
+             FROM unnest($1::uuid[], $2::timestamp[]) AS extension(uuid, expiry) \
+             WHERE sessions.uuid = extension.uuid AND sessions.expiry < extension.expiry",
+            [ids.into(), expiries.into()],
+        );
+        let result = tx.execute(statement).await?;
 
-        Ok(SessionAdapterImpl::from_model(model))
+        Ok(result.rows_affected())
     }
 
     #[tracing::instrument(level = "trace", skip(self, tx))]
@@ -91,17 +138,124 @@ impl SessionAdapter for SessionAdapterImpl {
     }
 
     #[tracing::instrument(level = "trace", skip(self, tx))]
-    async fn delete_session(&self, tx: &mut DatabaseTransaction, session_id: &SessionId) -> Result<(), Error> {
+    async fn delete_session(&self, tx: &mut DatabaseTransaction, session_id: &SessionId) -> Result<Option<Session>, Error> {
         let model = prelude::Sessions::find().filter(sessions::Column::Uuid.eq(*session_id)).one(tx).await?;
 
         match model {
             Some(session) => {
-                let active_session: sessions::ActiveModel = session.into();
+                let active_session: sessions::ActiveModel = session.clone().into();
                 active_session.delete(tx).await?;
 
-                Ok(())
+                Ok(Some(SessionAdapterImpl::from_model(session)))
             }
-            None => Ok(()),
+            None => Ok(None),
         }
     }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn cycle_session(&self, tx: &mut DatabaseTransaction, session_id: &SessionId) -> Result<Option<Session>, Error> {
+        let Some(model) = prelude::Sessions::find().filter(sessions::Column::Uuid.eq(*session_id)).one(tx).await? else {
+            return Ok(None);
+        };
+        let active_session: sessions::ActiveModel = model.clone().into();
+        active_session.delete(tx).await?;
+        let session_model = self.insert_session(tx, model.user_id, model.data, model.expiry).await?;
+
+        Ok(Some(SessionAdapterImpl::from_model(session_model)))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn list_user_sessions(&self, tx: &mut DatabaseTransaction, user_id: i64) -> Result<Vec<Session>, Error> {
+        let models = prelude::Sessions::find().filter(sessions::Column::UserId.eq(user_id)).all(tx).await?;
+
+        Ok(models.into_iter().map(SessionAdapterImpl::from_model).collect())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn delete_user_sessions(&self, tx: &mut DatabaseTransaction, user_id: i64) -> Result<u64, Error> {
+        let result = prelude::Sessions::delete_many().filter(sessions::Column::UserId.eq(user_id)).exec(tx).await?;
+
+        Ok(result.rows_affected)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn revoke_sessions(&self, tx: &mut DatabaseTransaction, revocation: &SessionRevocation) -> Result<(), Error> {
+        prelude::SessionRevocations::delete_many()
+            .filter(session_revocations::Column::Expiry.lte(Utc::now()))
+            .exec(tx)
+            .await?;
+        let revocation = session_revocations::ActiveModel {
+            session_id: Set(revocation.session_id),
+            user_id: Set(revocation.user_id),
+            revoked_at: Set(revocation.revoked_at.naive_utc()),
+            expiry: Set(revocation.expiry.naive_utc()),
+            ..Default::default()
+        };
+        revocation.insert(tx).await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn list_revocations(&self, tx: &mut DatabaseTransaction) -> Result<Vec<SessionRevocation>, Error> {
+        let models = prelude::SessionRevocations::find()
+            .filter(session_revocations::Column::Expiry.gt(Utc::now()))
+            .all(tx)
+            .await?;
+
+        Ok(models.into_iter().map(SessionAdapterImpl::from_revocation_model).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use auth_domain_models::auth::NewSession;
+    use chrono::{Duration, DurationRound, Utc};
+
+    use crate::{connect_database, testing::database_url, Configuration, Error};
+
+    #[tokio::test]
+    #[ignore = "needs a local Postgres"]
+    async fn saves_and_extends_sessions() {
+        let adapters = connect_database(&Configuration::new(database_url())).await.unwrap();
+        let expiry = Utc::now().duration_trunc(Duration::seconds(1)).unwrap() + Duration::hours(1);
+
+        let result: Result<(), Error> = adapters
+            .repository
+            .transaction(|tx| {
+                let session_adapter = adapters.session_adapter.clone();
+                Box::pin(async move {
+                    let mut session = session_adapter
+                        .create_session(
+                            tx,
+                            &NewSession {
+                                user_id: None,
+                                data: b"before".to_vec(),
+                                expiry,
+                            },
+                        )
+                        .await?;
+                    session.data = b"after".to_vec();
+                    session_adapter.save_session(tx, &session).await?;
+                    assert_eq!(session_adapter.load_session(tx, &session.id).await?.unwrap().data, b"after");
+
+                    let missing = uuid::Uuid::new_v4();
+                    let mut unknown = session.clone();
+                    unknown.id = missing;
+                    assert!(matches!(session_adapter.save_session(tx, &unknown).await, Err(Error::NotFound)));
+
+                    let extensions = [(session.id, expiry + Duration::hours(1)), (missing, expiry + Duration::hours(1))];
+                    assert_eq!(session_adapter.extend_sessions(tx, &extensions).await?, 1);
+                    // Never shortened.
+                    assert_eq!(session_adapter.extend_sessions(tx, &[(session.id, expiry)]).await?, 0);
+                    let loaded = session_adapter.load_session(tx, &session.id).await?.unwrap();
+                    assert_eq!(loaded.expiry, expiry + Duration::hours(1));
+
+                    // Leave nothing behind.
+                    Err(Error::NotFound)
+                })
+            })
+            .await;
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
 }
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use auth_domain_models::auth::{NewUser, User};
 use auth_utils::argon2::{check_password, hash_password, hash_string};
+use chrono::{DateTime, TimeZone, Utc};
 use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, Set};
 
 use crate::{
@@ -14,6 +15,12 @@ pub trait UserAdapter: Send + Sync {
     async fn get_user(&self, tx: &mut DatabaseTransaction, email: &str) -> Result<User, Error>;
     async fn get_user_by_id(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<User, Error>;
     async fn authenticate_user(&self, tx: &mut DatabaseTransaction, email: &str, password: &str) -> Result<User, Error>;
+    async fn update_password(&self, tx: &mut DatabaseTransaction, id: i64, current_password: &str, new_password: &str) -> Result<User, Error>;
+
+    async fn schedule_deletion(&self, tx: &mut DatabaseTransaction, id: i64, purge_at: DateTime<Utc>) -> Result<User, Error>;
+    async fn cancel_deletion(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<User, Error>;
+    async fn list_purgeable_users(&self, tx: &mut DatabaseTransaction, now: DateTime<Utc>) -> Result<Vec<User>, Error>;
+    async fn purge_user(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<(), Error>;
 }
 
 pub(crate) struct UserAdapterImpl {}
@@ -29,8 +36,17 @@ impl UserAdapterImpl {
             name: model.name,
             email: model.email,
             password_sha: hash_string(&model.password),
+            roles: model.roles,
+            deleted_at: model.deleted_at.map(|deleted_at| Utc.from_utc_datetime(&deleted_at)),
+            purge_at: model.purge_at.map(|purge_at| Utc.from_utc_datetime(&purge_at)),
         }
     }
+
+    async fn find_by_id(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<users::Model, Error> {
+        let model = prelude::Users::find().filter(users::Column::Id.eq(id)).one(tx).await?;
+
+        model.ok_or(Error::NotFound)
+    }
 }
 
 #[async_trait]
@@ -65,17 +81,17 @@ impl UserAdapter for UserAdapterImpl {
 
     #[tracing::instrument(level = "trace", skip(self, tx))]
     async fn get_user_by_id(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<User, Error> {
-        let model = prelude::Users::find().filter(users::Column::Id.eq(id)).one(tx).await?;
+        let model = self.find_by_id(tx, id).await?;
 
-        match model {
-            Some(user) => Ok(UserAdapterImpl::from_model(user)),
-            None => Err(Error::NotFound),
-        }
+        Ok(UserAdapterImpl::from_model(model))
     }
 
     #[tracing::instrument(level = "trace", skip(self, tx, password))]
     async fn authenticate_user(&self, tx: &mut DatabaseTransaction, email: &str, password: &str) -> Result<User, Error> {
-        let model = prelude::Users::find().filter(users::Column::Email.eq(email)).one(tx).await?;
+        let model = prelude::Users::find()
+            .filter(users::Column::Email.eq(email).and(users::Column::DeletedAt.is_null()))
+            .one(tx)
+            .await?;
 
         match model {
             Some(user) => match check_password(password, &user.password) {
@@ -91,4 +107,70 @@ impl UserAdapter for UserAdapterImpl {
             None => Err(Error::NotFound),
         }
     }
+
+    #[tracing::instrument(level = "trace", skip(self, tx, current_password, new_password))]
+    async fn update_password(&self, tx: &mut DatabaseTransaction, id: i64, current_password: &str, new_password: &str) -> Result<User, Error> {
+        let model = self.find_by_id(tx, id).await?;
+        if !check_password(current_password, &model.password).unwrap_or(false) {
+            return Err(Error::InvalidPassword);
+        }
+
+        let hashed_password = match hash_password(new_password) {
+            Ok(hash) => hash,
+            Err(_) => return Err(Error::Message("System error".to_string())),
+        };
+
+        let mut active_user: users::ActiveModel = model.into();
+        active_user.password = Set(hashed_password);
+
+        let model = active_user.update(tx).await?;
+
+        Ok(UserAdapterImpl::from_model(model))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn schedule_deletion(&self, tx: &mut DatabaseTransaction, id: i64, purge_at: DateTime<Utc>) -> Result<User, Error> {
+        let model = self.find_by_id(tx, id).await?;
+        if model.deleted_at.is_some() {
+            return Ok(UserAdapterImpl::from_model(model));
+        }
+
+        let mut active_user: users::ActiveModel = model.into();
+        active_user.deleted_at = Set(Some(Utc::now().naive_utc()));
+        active_user.purge_at = Set(Some(purge_at.naive_utc()));
+
+        let model = active_user.update(tx).await?;
+
+        Ok(UserAdapterImpl::from_model(model))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn cancel_deletion(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<User, Error> {
+        let model = self.find_by_id(tx, id).await?;
+
+        let mut active_user: users::ActiveModel = model.into();
+        active_user.deleted_at = Set(None);
+        active_user.purge_at = Set(None);
+
+        let model = active_user.update(tx).await?;
+
+        Ok(UserAdapterImpl::from_model(model))
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn list_purgeable_users(&self, tx: &mut DatabaseTransaction, now: DateTime<Utc>) -> Result<Vec<User>, Error> {
+        let models = prelude::Users::find()
+            .filter(users::Column::DeletedAt.is_not_null().and(users::Column::PurgeAt.lte(now.naive_utc())))
+            .all(tx)
+            .await?;
+
+        Ok(models.into_iter().map(UserAdapterImpl::from_model).collect())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn purge_user(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<(), Error> {
+        prelude::Users::delete_by_id(id).exec(tx).await?;
+
+        Ok(())
+    }
 }
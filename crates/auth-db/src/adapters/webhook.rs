@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use auth_domain_models::webhook::{
+    NewWebhookEndpoint, NewWebhookEvent, PendingWebhookDelivery, WebhookAttempt, WebhookDelivery, WebhookDeliveryStatus, WebhookEndpoint,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use sea_orm::{
+    sea_query::{Expr, LockBehavior, LockType, Query},
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+};
+
+use crate::{
+    entities::{prelude, webhook_deliveries, webhook_endpoints, webhook_outbox},
+    Error,
+};
+
+#[async_trait]
+pub trait WebhookAdapter: Send + Sync {
+    async fn add_endpoint(&self, tx: &mut DatabaseTransaction, endpoint: &NewWebhookEndpoint) -> Result<WebhookEndpoint, Error>;
+    async fn list_endpoints(&self, tx: &mut DatabaseTransaction) -> Result<Vec<WebhookEndpoint>, Error>;
+    async fn delete_endpoint(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<(), Error>;
+
+    /// Write an event to the outbox and queue a delivery for every endpoint
+    /// subscribed to it. Call this in the transaction making the change.
+    async fn enqueue_event(&self, tx: &mut DatabaseTransaction, event: &NewWebhookEvent) -> Result<u64, Error>;
+
+    /// Claim up to `limit` due deliveries, pushing their next attempt out to
+    /// `lease_until` so that concurrent workers skip them.
+    async fn claim_due_deliveries(
+        &self,
+        tx: &mut DatabaseTransaction,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<PendingWebhookDelivery>, Error>;
+    async fn record_attempt(&self, tx: &mut DatabaseTransaction, delivery_id: i64, attempt: &WebhookAttempt) -> Result<WebhookDelivery, Error>;
+    async fn list_deliveries(&self, tx: &mut DatabaseTransaction, endpoint_id: i64, offset: u64, limit: u64) -> Result<Vec<WebhookDelivery>, Error>;
+
+    /// Delete the events written before `before` that have no delivery left
+    /// to attempt, along with their deliveries, returning how many events
+    /// were deleted.
+    async fn prune_events(&self, tx: &mut DatabaseTransaction, before: DateTime<Utc>) -> Result<u64, Error>;
+}
+
+pub(crate) struct WebhookAdapterImpl {}
+
+impl WebhookAdapterImpl {
+    pub(crate) fn new() -> Self {
+        Self {}
+    }
+
+    fn endpoint_from_model(model: webhook_endpoints::Model) -> Result<WebhookEndpoint, Error> {
+        Ok(WebhookEndpoint {
+            id: model.id,
+            url: model.url,
+            secret: model.secret,
+            events: model
+                .events
+                .iter()
+                .map(|event| event.parse())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Error::Message)?,
+            created_at: Utc.from_utc_datetime(&model.created_at),
+        })
+    }
+
+    fn delivery_from_model(model: webhook_deliveries::Model) -> Result<WebhookDelivery, Error> {
+        Ok(WebhookDelivery {
+            id: model.id,
+            endpoint_id: model.endpoint_id,
+            event_id: model.event_id,
+            event_type: model.event_type.parse().map_err(Error::Message)?,
+            status: model.status.parse().map_err(Error::Message)?,
+            attempts: model.attempts,
+            next_attempt_at: Utc.from_utc_datetime(&model.next_attempt_at),
+            last_attempt_at: model.last_attempt_at.map(|at| Utc.from_utc_datetime(&at)),
+            last_response_status: model.last_response_status,
+            last_error: model.last_error,
+            created_at: Utc.from_utc_datetime(&model.created_at),
+        })
+    }
+}
+
+#[async_trait]
+impl WebhookAdapter for WebhookAdapterImpl {
+    #[tracing::instrument(level = "trace", skip(self, tx, endpoint))]
+    async fn add_endpoint(&self, tx: &mut DatabaseTransaction, endpoint: &NewWebhookEndpoint) -> Result<WebhookEndpoint, Error> {
+        let new_endpoint = webhook_endpoints::ActiveModel {
+            url: Set(endpoint.url.clone()),
+            secret: Set(endpoint.secret.clone()),
+            events: Set(endpoint.events.iter().map(|event| event.as_str().to_string()).collect()),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        };
+        let endpoint_model = new_endpoint.insert(tx).await?;
+
+        WebhookAdapterImpl::endpoint_from_model(endpoint_model)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn list_endpoints(&self, tx: &mut DatabaseTransaction) -> Result<Vec<WebhookEndpoint>, Error> {
+        let models = prelude::WebhookEndpoints::find().order_by_asc(webhook_endpoints::Column::Id).all(tx).await?;
+
+        models.into_iter().map(WebhookAdapterImpl::endpoint_from_model).collect()
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn delete_endpoint(&self, tx: &mut DatabaseTransaction, id: i64) -> Result<(), Error> {
+        let result = prelude::WebhookEndpoints::delete_by_id(id).exec(tx).await?;
+
+        match result.rows_affected {
+            0 => Err(Error::NotFound),
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx, event), fields(event_type = %event.event_type))]
+    async fn enqueue_event(&self, tx: &mut DatabaseTransaction, event: &NewWebhookEvent) -> Result<u64, Error> {
+        let endpoints = prelude::WebhookEndpoints::find()
+            .filter(Expr::cust_with_values("$1 = ANY(events)", [event.event_type.as_str()]))
+            .all(&*tx)
+            .await?;
+        if endpoints.is_empty() {
+            return Ok(0);
+        }
+
+        let now = Utc::now().naive_utc();
+        let outbox = webhook_outbox::ActiveModel {
+            id: Set(event.id),
+            event_type: Set(event.event_type.as_str().to_string()),
+            payload: Set(event.payload.clone()),
+            created_at: Set(now),
+        };
+        outbox.insert(&*tx).await?;
+
+        let deliveries = endpoints.iter().map(|endpoint| webhook_deliveries::ActiveModel {
+            endpoint_id: Set(endpoint.id),
+            event_id: Set(event.id),
+            event_type: Set(event.event_type.as_str().to_string()),
+            status: Set(WebhookDeliveryStatus::Pending.as_str().to_string()),
+            attempts: Set(0),
+            next_attempt_at: Set(now),
+            created_at: Set(now),
+            ..Default::default()
+        });
+        prelude::WebhookDeliveries::insert_many(deliveries).exec(tx).await?;
+
+        Ok(endpoints.len() as u64)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn claim_due_deliveries(
+        &self,
+        tx: &mut DatabaseTransaction,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: u64,
+    ) -> Result<Vec<PendingWebhookDelivery>, Error> {
+        let models = prelude::WebhookDeliveries::find()
+            .filter(
+                webhook_deliveries::Column::Status
+                    .eq(WebhookDeliveryStatus::Pending.as_str())
+                    .and(webhook_deliveries::Column::NextAttemptAt.lte(now.naive_utc())),
+            )
+            .order_by_asc(webhook_deliveries::Column::NextAttemptAt)
+            .limit(limit)
+            .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+            .all(&*tx)
+            .await?;
+        if models.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<i64> = models.iter().map(|model| model.id).collect();
+        prelude::WebhookDeliveries::update_many()
+            .col_expr(webhook_deliveries::Column::NextAttemptAt, Expr::value(lease_until.naive_utc()))
+            .filter(webhook_deliveries::Column::Id.is_in(ids))
+            .exec(&*tx)
+            .await?;
+
+        let endpoint_ids: Vec<i64> = models.iter().map(|model| model.endpoint_id).collect();
+        let endpoints: HashMap<i64, webhook_endpoints::Model> = prelude::WebhookEndpoints::find()
+            .filter(webhook_endpoints::Column::Id.is_in(endpoint_ids))
+            .all(&*tx)
+            .await?
+            .into_iter()
+            .map(|endpoint| (endpoint.id, endpoint))
+            .collect();
+
+        let event_ids: Vec<uuid::Uuid> = models.iter().map(|model| model.event_id).collect();
+        let events: HashMap<uuid::Uuid, webhook_outbox::Model> = prelude::WebhookOutbox::find()
+            .filter(webhook_outbox::Column::Id.is_in(event_ids))
+            .all(&*tx)
+            .await?
+            .into_iter()
+            .map(|event| (event.id, event))
+            .collect();
+
+        let mut pending = Vec::with_capacity(models.len());
+        for model in models {
+            let (Some(endpoint), Some(event)) = (endpoints.get(&model.endpoint_id), events.get(&model.event_id)) else {
+                continue;
+            };
+            pending.push(PendingWebhookDelivery {
+                url: endpoint.url.clone(),
+                secret: endpoint.secret.clone(),
+                payload: event.payload.clone(),
+                delivery: WebhookAdapterImpl::delivery_from_model(model)?,
+            });
+        }
+
+        Ok(pending)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn record_attempt(&self, tx: &mut DatabaseTransaction, delivery_id: i64, attempt: &WebhookAttempt) -> Result<WebhookDelivery, Error> {
+        let model = prelude::WebhookDeliveries::find_by_id(delivery_id).one(&*tx).await?.ok_or(Error::NotFound)?;
+        let attempts = model.attempts + 1;
+
+        let status = match (attempt.succeeded, attempt.next_attempt_at) {
+            (true, _) => WebhookDeliveryStatus::Succeeded,
+            (false, Some(_)) => WebhookDeliveryStatus::Pending,
+            (false, None) => WebhookDeliveryStatus::Failed,
+        };
+
+        let mut active_delivery: webhook_deliveries::ActiveModel = model.into();
+        active_delivery.status = Set(status.as_str().to_string());
+        active_delivery.attempts = Set(attempts);
+        active_delivery.last_attempt_at = Set(Some(Utc::now().naive_utc()));
+        active_delivery.last_response_status = Set(attempt.response_status);
+        active_delivery.last_error = Set(attempt.error.clone());
+        if let Some(next_attempt_at) = attempt.next_attempt_at {
+            active_delivery.next_attempt_at = Set(next_attempt_at.naive_utc());
+        }
+
+        let model = active_delivery.update(tx).await?;
+
+        WebhookAdapterImpl::delivery_from_model(model)
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn list_deliveries(&self, tx: &mut DatabaseTransaction, endpoint_id: i64, offset: u64, limit: u64) -> Result<Vec<WebhookDelivery>, Error> {
+        let models = prelude::WebhookDeliveries::find()
+            .filter(webhook_deliveries::Column::EndpointId.eq(endpoint_id))
+            .order_by_desc(webhook_deliveries::Column::Id)
+            .offset(offset)
+            .limit(limit)
+            .all(tx)
+            .await?;
+
+        models.into_iter().map(WebhookAdapterImpl::delivery_from_model).collect()
+    }
+
+    #[tracing::instrument(level = "trace", skip(self, tx))]
+    async fn prune_events(&self, tx: &mut DatabaseTransaction, before: DateTime<Utc>) -> Result<u64, Error> {
+        let pending = Query::select()
+            .column(webhook_deliveries::Column::EventId)
+            .from(webhook_deliveries::Entity)
+            .and_where(webhook_deliveries::Column::Status.eq(WebhookDeliveryStatus::Pending.as_str()))
+            .to_owned();
+        // Deliveries go with their event.
+        let result = prelude::WebhookOutbox::delete_many()
+            .filter(webhook_outbox::Column::CreatedAt.lt(before.naive_utc()))
+            .filter(webhook_outbox::Column::Id.not_in_subquery(pending))
+            .exec(tx)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use auth_domain_models::webhook::{NewWebhookEndpoint, NewWebhookEvent, WebhookAttempt, WebhookEventType};
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use crate::{connect_database, testing::database_url, Configuration, Error};
+
+    #[tokio::test]
+    #[ignore = "needs a local Postgres"]
+    async fn prunes_delivered_events() {
+        let adapters = connect_database(&Configuration::new(database_url())).await.unwrap();
+
+        let result: Result<(), Error> = adapters
+            .repository
+            .transaction(|tx| {
+                let webhook_adapter = adapters.webhook_adapter.clone();
+                Box::pin(async move {
+                    // Only this endpoint is subscribed.
+                    for endpoint in webhook_adapter.list_endpoints(tx).await? {
+                        webhook_adapter.delete_endpoint(tx, endpoint.id).await?;
+                    }
+                    let endpoint = webhook_adapter
+                        .add_endpoint(
+                            tx,
+                            &NewWebhookEndpoint {
+                                url: "http://127.0.0.1/hook".to_string(),
+                                secret: "secret".to_string(),
+                                events: vec![WebhookEventType::UserRegistered],
+                            },
+                        )
+                        .await?;
+                    let event = || NewWebhookEvent {
+                        id: Uuid::new_v4(),
+                        event_type: WebhookEventType::UserRegistered,
+                        payload: "{}".to_string(),
+                    };
+                    let (delivered, pending) = (event(), event());
+                    webhook_adapter.enqueue_event(tx, &delivered).await?;
+                    webhook_adapter.enqueue_event(tx, &pending).await?;
+
+                    let deliveries = webhook_adapter.list_deliveries(tx, endpoint.id, 0, 10).await?;
+                    let delivery = deliveries.iter().find(|delivery| delivery.event_id == delivered.id).unwrap();
+                    let attempt = WebhookAttempt {
+                        response_status: Some(204),
+                        error: None,
+                        next_attempt_at: None,
+                        succeeded: true,
+                    };
+                    webhook_adapter.record_attempt(tx, delivery.id, &attempt).await?;
+
+                    // Kept until past the retention.
+                    webhook_adapter.prune_events(tx, Utc::now() - Duration::hours(1)).await?;
+                    assert_eq!(webhook_adapter.list_deliveries(tx, endpoint.id, 0, 10).await?.len(), 2);
+
+                    assert!(webhook_adapter.prune_events(tx, Utc::now() + Duration::minutes(1)).await? > 0);
+                    let deliveries = webhook_adapter.list_deliveries(tx, endpoint.id, 0, 10).await?;
+                    assert_eq!(deliveries.len(), 1);
+                    assert_eq!(deliveries[0].event_id, pending.id);
+
+                    // Leave nothing behind.
+                    Err(Error::NotFound)
+                })
+            })
+            .await;
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
+}
@@ -2,5 +2,10 @@
 
 pub mod prelude;
 
+pub mod audit_events;
+pub mod session_revocations;
 pub mod sessions;
 pub mod users;
+pub mod webhook_deliveries;
+pub mod webhook_endpoints;
+pub mod webhook_outbox;
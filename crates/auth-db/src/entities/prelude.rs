@@ -1,4 +1,9 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.3
 
+pub use super::audit_events::Entity as AuditEvents;
+pub use super::session_revocations::Entity as SessionRevocations;
 pub use super::sessions::Entity as Sessions;
 pub use super::users::Entity as Users;
+pub use super::webhook_deliveries::Entity as WebhookDeliveries;
+pub use super::webhook_endpoints::Entity as WebhookEndpoints;
+pub use super::webhook_outbox::Entity as WebhookOutbox;
@@ -12,6 +12,9 @@ pub struct Model {
     #[sea_orm(unique)]
     pub email: String,
     pub password: String,
+    pub roles: Vec<String>,
+    pub deleted_at: Option<DateTime>,
+    pub purge_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,4 +1,4 @@
-use sea_orm::DbErr;
+use sea_orm::{sqlx, ConnAcquireErr, DbErr, RuntimeErr};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -14,6 +14,35 @@ pub enum Error {
     #[error(transparent)]
     SeaOrm(#[from] DbErr),
 
+    /// The commit failed, possibly after it took effect.
+    #[error("Commit failed - {0}")]
+    Commit(DbErr),
+
     #[error(transparent)]
     Any(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
+
+impl Error {
+    /// Whether running the transaction again may succeed: it hit a
+    /// serialization failure or deadlock, or lost its connection.
+    pub fn is_transient(&self) -> bool {
+        let Error::SeaOrm(err) = self else {
+            return false;
+        };
+        if matches!(err, DbErr::ConnectionAcquire(ConnAcquireErr::ConnectionClosed)) {
+            return true;
+        }
+        let (DbErr::Conn(RuntimeErr::SqlxError(err)) | DbErr::Exec(RuntimeErr::SqlxError(err)) | DbErr::Query(RuntimeErr::SqlxError(err))) = err else {
+            return false;
+        };
+
+        match err {
+            sqlx::Error::Io(_) => true,
+            // Serialization failure, deadlock, connection exceptions and shutdowns.
+            sqlx::Error::Database(err) => err
+                .code()
+                .is_some_and(|code| matches!(code.as_ref(), "40001" | "40P01" | "57P01" | "57P02" | "57P03") || code.starts_with("08")),
+            _ => false,
+        }
+    }
+}
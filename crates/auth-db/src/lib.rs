@@ -1,15 +1,21 @@
 pub mod adapters;
 pub(crate) mod entities;
 pub mod error;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
-use adapters::{session::SessionAdapterImpl, user::UserAdapterImpl, SessionAdapter, UserAdapter};
+use adapters::{
+    audit::AuditAdapterImpl, notification::NotificationAdapterImpl, session::SessionAdapterImpl, user::UserAdapterImpl, webhook::WebhookAdapterImpl,
+    AuditAdapter, NotificationAdapter, NotificationListener, SessionAdapter, UserAdapter, WebhookAdapter,
+};
 use auth_utils::{arcbox, arcbox::ArcBox};
 pub use error::*;
+pub use sea_orm::DatabaseTransaction;
 
-use std::{future::Future, pin::Pin, sync::Arc};
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use sea_orm::{ConnectOptions, Database, DatabaseConnection, DatabaseTransaction, TransactionTrait};
+use sea_orm::{ConnectOptions, ConnectionTrait, Database, DatabaseConnection, TransactionTrait};
 use sea_orm_migration::{cli, MigrationTrait, MigratorTrait};
 use tracing_log::log;
 
@@ -18,64 +24,176 @@ pub struct Migrator;
 #[async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20250106_194018_users::Migration), Box::new(m20250106_205738_sessions::Migration)]
+        vec![
+            Box::new(m20250106_194018_users::Migration),
+            Box::new(m20250106_205738_sessions::Migration),
+            Box::new(m20250125_093000_account_deletion::Migration),
+            Box::new(m20250201_101500_audit_events::Migration),
+            Box::new(m20250208_143000_webhooks::Migration),
+            Box::new(m20250215_090000_session_revocations::Migration),
+        ]
+    }
+}
+
+/// Delay before the first retry of a transaction; doubled on each further attempt.
+const RETRY_BACKOFF: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    /// Fully qualified URL of the Postgres server.
+    pub url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    /// How long to wait for a pooled connection before giving up.
+    pub acquire_timeout: Duration,
+    /// How long a connection may sit idle in the pool before it is closed.
+    pub idle_timeout: Option<Duration>,
+    /// How long a connection is kept before it is replaced.
+    pub max_lifetime: Option<Duration>,
+    /// Statement timeout set on every transaction. None when unset.
+    pub statement_timeout: Option<Duration>,
+    /// Times a transaction is run again after a transient error.
+    pub transaction_retries: u32,
+    /// Apply pending migrations on connecting. Otherwise pending migrations
+    /// fail the connection.
+    pub run_migrations: bool,
+}
+
+impl Configuration {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            max_connections: 100,
+            min_connections: 5,
+            acquire_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(600)),
+            max_lifetime: Some(Duration::from_secs(1800)),
+            statement_timeout: None,
+            transaction_retries: 3,
+            run_migrations: true,
+        }
     }
 }
 
 pub struct Repository {
     pub database: DatabaseConnection,
+    statement_timeout: Option<Duration>,
+    retries: u32,
 }
 
 impl Repository {
+    /// Run the operation in a transaction, committed when it succeeds. It is
+    /// run again, in a new transaction, after a transient error such as a
+    /// serialization failure or a lost connection, but never once the commit
+    /// was attempted.
     pub async fn transaction<F, T>(&self, operation: F) -> Result<T, Error>
     where
-        F: FnOnce(&mut DatabaseTransaction) -> Pin<Box<dyn Future<Output = Result<T, Error>> + '_ + Send>>,
+        F: Fn(&mut DatabaseTransaction) -> Pin<Box<dyn Future<Output = Result<T, Error>> + '_ + Send>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.try_transaction(&operation).await {
+                Err(err) if attempt < self.retries && err.is_transient() => {
+                    let backoff = RETRY_BACKOFF * 2u32.pow(attempt);
+                    attempt += 1;
+                    tracing::debug!("Retrying transaction in {:?}, attempt {} - {}", backoff, attempt, err);
+                    tokio::time::sleep(backoff).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_transaction<F, T>(&self, operation: &F) -> Result<T, Error>
+    where
+        F: Fn(&mut DatabaseTransaction) -> Pin<Box<dyn Future<Output = Result<T, Error>> + '_ + Send>>,
     {
         let mut tx = self.database.begin().await?;
+        if let Some(timeout) = self.statement_timeout {
+            tx.execute_unprepared(&format!("SET LOCAL statement_timeout = {}", timeout.as_millis())).await?;
+        }
 
         let result = operation(&mut tx).await;
         if result.is_err() {
             tx.rollback().await?
         } else {
-            tx.commit().await?;
+            tx.commit().await.map_err(Error::Commit)?;
         }
 
         result
     }
+
+    /// Listen on a notification channel, over a connection of its own.
+    pub async fn listen(&self, channel: &str) -> Result<NotificationListener, Error> {
+        NotificationListener::connect(self.database.get_postgres_connection_pool(), channel).await
+    }
 }
 
 pub struct RepositoryAdapters {
     pub repository: Arc<Repository>,
+    pub audit_adapter: ArcBox<dyn AuditAdapter>,
+    pub notification_adapter: ArcBox<dyn NotificationAdapter>,
     pub session_adapter: ArcBox<dyn SessionAdapter>,
     pub user_adapter: ArcBox<dyn UserAdapter>,
+    pub webhook_adapter: ArcBox<dyn WebhookAdapter>,
 }
 
-pub async fn connect_database(url: &str) -> Result<Arc<RepositoryAdapters>, Error> {
+pub async fn connect_database(config: &Configuration) -> Result<Arc<RepositoryAdapters>, Error> {
     tracing::debug!("Connecting to database...");
-    let mut opt = ConnectOptions::new(url);
-    opt.max_connections(100)
-        .min_connections(5)
+    let mut opt = ConnectOptions::new(&config.url);
+    opt.max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout)
         .sqlx_logging(true)
         .sqlx_logging_level(log::LevelFilter::Info);
+    if let Some(idle_timeout) = config.idle_timeout {
+        opt.idle_timeout(idle_timeout);
+    }
+    if let Some(max_lifetime) = config.max_lifetime {
+        opt.max_lifetime(max_lifetime);
+    }
     let database = Database::connect(opt).await?;
 
-    tracing::debug!("Applying migrations...");
-    Migrator::up(&database, None).await?;
-    tracing::debug!("...migrations applied");
+    if config.run_migrations {
+        tracing::debug!("Applying migrations...");
+        Migrator::up(&database, None).await?;
+        tracing::debug!("...migrations applied");
+    } else {
+        let pending = Migrator::get_pending_migrations(&database).await?;
+        if !pending.is_empty() {
+            return Err(Error::Message(format!(
+                "{} migrations are pending, apply them with the migrator",
+                pending.len()
+            )));
+        }
+    }
 
     tracing::debug!("...connected to database");
 
-    let repository = Arc::new(Repository { database });
+    let repository = Arc::new(Repository {
+        database,
+        statement_timeout: config.statement_timeout,
+        retries: config.transaction_retries,
+    });
 
+    let audit_adapter = AuditAdapterImpl::new();
+    let audit_adapter: ArcBox<dyn AuditAdapter> = arcbox!(audit_adapter);
+    let notification_adapter = NotificationAdapterImpl::new();
+    let notification_adapter: ArcBox<dyn NotificationAdapter> = arcbox!(notification_adapter);
     let session_adapter = SessionAdapterImpl::new();
     let session_adapter: ArcBox<dyn SessionAdapter> = arcbox!(session_adapter);
     let user_adapter = UserAdapterImpl::new();
     let user_adapter: ArcBox<dyn UserAdapter> = arcbox!(user_adapter);
+    let webhook_adapter = WebhookAdapterImpl::new();
+    let webhook_adapter: ArcBox<dyn WebhookAdapter> = arcbox!(webhook_adapter);
 
     let adapters = Arc::new(RepositoryAdapters {
         repository,
+        audit_adapter,
+        notification_adapter,
         session_adapter,
         user_adapter,
+        webhook_adapter,
     });
 
     Ok(adapters)
@@ -87,3 +205,67 @@ pub async fn run_migration_cli() {
 
 pub(crate) mod m20250106_194018_users;
 pub(crate) mod m20250106_205738_sessions;
+pub(crate) mod m20250125_093000_account_deletion;
+pub(crate) mod m20250201_101500_audit_events;
+pub(crate) mod m20250208_143000_webhooks;
+pub(crate) mod m20250215_090000_session_revocations;
+
+/// Tests against a local Postgres are ignored by default, run them with
+/// `cargo test -p auth-db -- --ignored`
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicU32, Ordering},
+        time::Duration,
+    };
+
+    use sea_orm::ConnectionTrait;
+
+    use crate::{connect_database, testing::database_url, Configuration};
+
+    #[tokio::test]
+    #[ignore = "needs a local Postgres"]
+    async fn retries_transient_errors() {
+        let adapters = connect_database(&Configuration {
+            statement_timeout: Some(Duration::from_millis(100)),
+            ..Configuration::new(database_url())
+        })
+        .await
+        .unwrap();
+        let attempts = AtomicU32::new(0);
+
+        // A serialization failure on the first attempt.
+        let result = adapters
+            .repository
+            .transaction(|tx| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move {
+                    if attempt == 0 {
+                        tx.execute_unprepared("DO $$ BEGIN RAISE EXCEPTION 'conflict' USING ERRCODE = '40001'; END $$")
+                            .await?;
+                    }
+
+                    Ok(attempt)
+                })
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+
+        attempts.store(0, Ordering::SeqCst);
+        let result = adapters
+            .repository
+            .transaction(|tx| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Box::pin(async move {
+                    tx.execute_unprepared("SELECT pg_sleep(1)").await?;
+
+                    Ok(())
+                })
+            })
+            .await;
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("statement timeout"), "{}", err);
+        assert!(!err.is_transient());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
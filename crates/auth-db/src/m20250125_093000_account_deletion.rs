@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::Roles)
+                            .array(ColumnType::Text)
+                            .not_null()
+                            .default(Expr::cust("'{}'::text[]")),
+                    )
+                    .add_column(ColumnDef::new(Users::DeletedAt).date_time().null())
+                    .add_column(ColumnDef::new(Users::PurgeAt).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Sessions::Table)
+                    .add_column(ColumnDef::new(Sessions::UserId).big_integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(Sessions::IndexUserId.to_string())
+                    .table(Sessions::Table)
+                    .col(Sessions::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(Index::drop().name(Sessions::IndexUserId.to_string()).to_owned()).await?;
+        manager
+            .alter_table(Table::alter().table(Sessions::Table).drop_column(Sessions::UserId).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::Roles)
+                    .drop_column(Users::DeletedAt)
+                    .drop_column(Users::PurgeAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum Users {
+    Table,
+    Roles,
+    DeletedAt,
+    PurgeAt,
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum Sessions {
+    Table,
+    UserId,
+    #[sea_orm(iden = "idx_sessions_user_id")]
+    IndexUserId,
+}
@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvents::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(AuditEvents::Id).big_integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(AuditEvents::OccurredAt).date_time().not_null())
+                    .col(ColumnDef::new(AuditEvents::Kind).string().not_null())
+                    .col(ColumnDef::new(AuditEvents::Outcome).string().not_null())
+                    .col(ColumnDef::new(AuditEvents::ActorId).big_integer().null())
+                    .col(ColumnDef::new(AuditEvents::ActorEmail).string().null())
+                    .col(ColumnDef::new(AuditEvents::Ip).string().null())
+                    .col(ColumnDef::new(AuditEvents::UserAgent).string().null())
+                    .col(ColumnDef::new(AuditEvents::Detail).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(AuditEvents::IndexOccurredAt.to_string())
+                    .table(AuditEvents::Table)
+                    .col(AuditEvents::OccurredAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(AuditEvents::IndexActorId.to_string())
+                    .table(AuditEvents::Table)
+                    .col(AuditEvents::ActorId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(Index::drop().name(AuditEvents::IndexActorId.to_string()).to_owned()).await?;
+        manager
+            .drop_index(Index::drop().name(AuditEvents::IndexOccurredAt.to_string()).to_owned())
+            .await?;
+        manager.drop_table(Table::drop().table(AuditEvents::Table).to_owned()).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum AuditEvents {
+    Table,
+    Id,
+    OccurredAt,
+    Kind,
+    Outcome,
+    ActorId,
+    ActorEmail,
+    Ip,
+    UserAgent,
+    Detail,
+    #[sea_orm(iden = "idx_audit_events_occurred_at")]
+    IndexOccurredAt,
+    #[sea_orm(iden = "idx_audit_events_actor_id")]
+    IndexActorId,
+}
@@ -0,0 +1,138 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookEndpoints::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(WebhookEndpoints::Id).big_integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(WebhookEndpoints::Url).string().not_null())
+                    .col(ColumnDef::new(WebhookEndpoints::Secret).string().not_null())
+                    .col(ColumnDef::new(WebhookEndpoints::Events).array(ColumnType::Text).not_null())
+                    .col(ColumnDef::new(WebhookEndpoints::CreatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookOutbox::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(WebhookOutbox::Id).uuid().not_null().primary_key())
+                    .col(ColumnDef::new(WebhookOutbox::EventType).string().not_null())
+                    .col(ColumnDef::new(WebhookOutbox::Payload).text().not_null())
+                    .col(ColumnDef::new(WebhookOutbox::CreatedAt).date_time().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(WebhookDeliveries::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(WebhookDeliveries::Id).big_integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(WebhookDeliveries::EndpointId).big_integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::EventId).uuid().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::EventType).string().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::Status).string().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::Attempts).integer().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::NextAttemptAt).date_time().not_null())
+                    .col(ColumnDef::new(WebhookDeliveries::LastAttemptAt).date_time().null())
+                    .col(ColumnDef::new(WebhookDeliveries::LastResponseStatus).integer().null())
+                    .col(ColumnDef::new(WebhookDeliveries::LastError).string().null())
+                    .col(ColumnDef::new(WebhookDeliveries::CreatedAt).date_time().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(WebhookDeliveries::Table, WebhookDeliveries::EndpointId)
+                            .to(WebhookEndpoints::Table, WebhookEndpoints::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(WebhookDeliveries::Table, WebhookDeliveries::EventId)
+                            .to(WebhookOutbox::Table, WebhookOutbox::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(WebhookDeliveries::IndexStatusNextAttemptAt.to_string())
+                    .table(WebhookDeliveries::Table)
+                    .col(WebhookDeliveries::Status)
+                    .col(WebhookDeliveries::NextAttemptAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(WebhookDeliveries::IndexEndpointId.to_string())
+                    .table(WebhookDeliveries::Table)
+                    .col(WebhookDeliveries::EndpointId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(WebhookDeliveries::Table).to_owned()).await?;
+        manager.drop_table(Table::drop().table(WebhookOutbox::Table).to_owned()).await?;
+        manager.drop_table(Table::drop().table(WebhookEndpoints::Table).to_owned()).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum WebhookEndpoints {
+    Table,
+    Id,
+    Url,
+    Secret,
+    Events,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum WebhookOutbox {
+    Table,
+    Id,
+    EventType,
+    Payload,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum WebhookDeliveries {
+    Table,
+    Id,
+    EndpointId,
+    EventId,
+    EventType,
+    Status,
+    Attempts,
+    NextAttemptAt,
+    LastAttemptAt,
+    LastResponseStatus,
+    LastError,
+    CreatedAt,
+    #[sea_orm(iden = "idx_webhook_deliveries_status_next_attempt_at")]
+    IndexStatusNextAttemptAt,
+    #[sea_orm(iden = "idx_webhook_deliveries_endpoint_id")]
+    IndexEndpointId,
+}
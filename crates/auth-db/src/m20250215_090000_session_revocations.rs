@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(SessionRevocations::Table)
+                    .if_not_exists()
+                    .col(ColumnDef::new(SessionRevocations::Id).big_integer().not_null().auto_increment().primary_key())
+                    .col(ColumnDef::new(SessionRevocations::SessionId).uuid().null())
+                    .col(ColumnDef::new(SessionRevocations::UserId).big_integer().null())
+                    .col(ColumnDef::new(SessionRevocations::RevokedAt).date_time().not_null())
+                    .col(ColumnDef::new(SessionRevocations::Expiry).date_time().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name(SessionRevocations::IndexExpiry.to_string())
+                    .table(SessionRevocations::Table)
+                    .col(SessionRevocations::Expiry)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name(SessionRevocations::IndexExpiry.to_string()).to_owned())
+            .await?;
+        manager.drop_table(Table::drop().table(SessionRevocations::Table).to_owned()).await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+pub(crate) enum SessionRevocations {
+    Table,
+    Id,
+    SessionId,
+    UserId,
+    RevokedAt,
+    Expiry,
+    #[sea_orm(iden = "idx_session_revocations_expiry")]
+    IndexExpiry,
+}
@@ -0,0 +1,8 @@
+//! Helpers for tests against a real database.
+
+/// The database tests marked `needs a local Postgres` run against. Set
+/// `AUTH_PLAY_TEST_DATABASE_URL` to point them elsewhere, and run them with
+/// `cargo test --workspace -- --ignored`.
+pub fn database_url() -> String {
+    std::env::var("AUTH_PLAY_TEST_DATABASE_URL").unwrap_or_else(|_| "postgres://postgres@127.0.0.1/authplay".to_string())
+}
@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use auth_domain_models::{
+    audit::RequestContext,
+    auth::{SessionId, User},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{AuditEventInfo, Error};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileExport {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub purge_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub id: SessionId,
+    pub expiry: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserExport {
+    pub exported_at: DateTime<Utc>,
+    pub profile: ProfileExport,
+    pub sessions: Vec<SessionExport>,
+    pub audit_events: Vec<AuditEventInfo>,
+}
+
+#[async_trait]
+pub trait AccountApi: Send + Sync {
+    /// Collect everything stored about a user.
+    async fn export_user(&self, id: i64) -> Result<UserExport, Error>;
+
+    /// Soft-delete a user and revoke their sessions. The account is purged
+    /// once the grace period has elapsed.
+    async fn schedule_deletion(&self, id: i64, context: &RequestContext) -> Result<User, Error>;
+    async fn cancel_deletion(&self, id: i64, context: &RequestContext) -> Result<User, Error>;
+
+    /// Hard-delete every user whose grace period has elapsed, returning the
+    /// number of users purged.
+    async fn purge_deleted_users(&self) -> Result<usize, Error>;
+}
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use auth_domain_models::audit::{AuditEvent, AuditFilter, AuditPage, NewAuditEvent};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEventInfo {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub kind: String,
+    pub outcome: String,
+    pub actor_id: Option<i64>,
+    pub actor_email: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+impl From<AuditEvent> for AuditEventInfo {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            id: event.id,
+            occurred_at: event.occurred_at,
+            kind: event.kind.to_string(),
+            outcome: event.outcome.to_string(),
+            actor_id: event.actor_id,
+            actor_email: event.actor_email,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            detail: event.detail,
+        }
+    }
+}
+
+#[async_trait]
+pub trait AuditApi: Send + Sync {
+    /// Record an event. Failures are logged rather than returned so that
+    /// auditing never fails the operation being audited.
+    async fn record(&self, event: NewAuditEvent);
+    async fn list_events(&self, filter: &AuditFilter) -> Result<AuditPage, Error>;
+}
@@ -1,5 +1,9 @@
 use async_trait::async_trait;
-use auth_domain_models::auth::{NewSession, NewUser, Session, SessionId, User};
+use auth_domain_models::{
+    audit::RequestContext,
+    auth::{NewSession, NewUser, Session, SessionId, SessionRevocation, User},
+};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::Error;
@@ -10,16 +14,44 @@ pub struct UserInfo {
     pub name: String,
     pub email: String,
     pub password_sha: String,
+    pub roles: Vec<String>,
+}
+
+impl From<User> for UserInfo {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            password_sha: user.password_sha,
+            roles: user.roles,
+        }
+    }
 }
 
 #[async_trait]
 pub trait AuthApi: Send + Sync {
-    async fn register(&self, user: &NewUser) -> Result<User, Error>;
-    async fn authenticate(&self, email: &str, password: &str) -> Result<UserInfo, Error>;
+    async fn register(&self, user: &NewUser, context: &RequestContext) -> Result<User, Error>;
+    async fn authenticate(&self, email: &str, password: &str, context: &RequestContext) -> Result<UserInfo, Error>;
+    async fn logout(&self, id: i64, context: &RequestContext) -> Result<(), Error>;
     async fn get_user(&self, id: i64) -> Result<UserInfo, Error>;
+    async fn change_password(&self, id: i64, current_password: &str, new_password: &str, context: &RequestContext) -> Result<UserInfo, Error>;
 
-    async fn create_session(&self, new_session: &NewSession) -> Result<Session, Error>;
+    async fn create_session(&self, new_session: &NewSession, context: &RequestContext) -> Result<Session, Error>;
     async fn save_session(&self, session: &Session) -> Result<(), Error>;
+    /// Extend the expiries of unchanged sessions, never shortening them.
+    /// Returns how many were extended.
+    async fn extend_sessions(&self, extensions: &[(SessionId, DateTime<Utc>)]) -> Result<u64, Error>;
     async fn load_session(&self, id: &SessionId) -> Result<Option<Session>, Error>;
-    async fn delete_session(&self, id: &SessionId) -> Result<(), Error>;
+    async fn delete_session(&self, id: &SessionId, context: &RequestContext) -> Result<(), Error>;
+    /// Move a session to a new id, returning it. `None` when there is no such session.
+    async fn cycle_session(&self, id: &SessionId, context: &RequestContext) -> Result<Option<Session>, Error>;
+    /// Revoke a session that is not stored, such as one kept in a cookie,
+    /// until it expires.
+    async fn revoke_session(&self, id: &SessionId, user_id: Option<i64>, expiry: DateTime<Utc>, context: &RequestContext) -> Result<(), Error>;
+    /// Sign a user out everywhere. Their stored sessions are deleted, and
+    /// sessions that are not stored are revoked until `expiry`.
+    async fn revoke_user_sessions(&self, user_id: i64, expiry: DateTime<Utc>, context: &RequestContext) -> Result<(), Error>;
+    /// Revocations that have not expired.
+    async fn list_session_revocations(&self) -> Result<Vec<SessionRevocation>, Error>;
 }
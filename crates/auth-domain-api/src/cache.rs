@@ -0,0 +1,11 @@
+use async_trait::async_trait;
+
+use crate::Error;
+
+#[async_trait]
+pub trait CacheApi: Send + Sync {
+    /// Apply the cache invalidations other instances broadcast, until the
+    /// connection they come over fails. Until it is followed again, cached
+    /// entries are kept only for the fallback time to live.
+    async fn follow_invalidations(&self) -> Result<(), Error>;
+}
@@ -1,6 +1,17 @@
 use async_trait::async_trait;
 
+/// Hits and misses of one of the domain's caches.
+#[derive(Debug, Clone)]
+pub struct CacheMetrics {
+    pub name: &'static str,
+    pub hits: u64,
+    pub misses: u64,
+    /// Approximate number of entries.
+    pub entries: u64,
+}
+
 #[async_trait]
 pub trait HealthApi: Send + Sync {
     async fn is_healthy(&self) -> bool;
+    fn cache_metrics(&self) -> Vec<CacheMetrics>;
 }
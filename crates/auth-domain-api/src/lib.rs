@@ -1,5 +1,9 @@
+mod account;
+mod audit;
 mod auth;
+mod cache;
 mod health;
+mod webhook;
 
 mod error;
 
@@ -7,10 +11,18 @@ use auth_utils::arcbox::ArcBox;
 
 pub use error::Error;
 
+pub use account::{AccountApi, ProfileExport, SessionExport, UserExport};
+pub use audit::{AuditApi, AuditEventInfo};
 pub use auth::{AuthApi, UserInfo};
-pub use health::HealthApi;
+pub use cache::CacheApi;
+pub use health::{CacheMetrics, HealthApi};
+pub use webhook::{WebhookApi, WebhookDeliveryInfo, WebhookEndpointInfo};
 
 pub struct AuthDomainApi {
+    pub account_api: ArcBox<dyn AccountApi>,
+    pub audit_api: ArcBox<dyn AuditApi>,
     pub auth_api: ArcBox<dyn AuthApi>,
+    pub cache_api: ArcBox<dyn CacheApi>,
     pub health_api: ArcBox<dyn HealthApi>,
+    pub webhook_api: ArcBox<dyn WebhookApi>,
 }
@@ -0,0 +1,74 @@
+use async_trait::async_trait;
+use auth_domain_models::webhook::{WebhookDelivery, WebhookEndpoint, WebhookEventType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Error;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookEndpointInfo {
+    pub id: i64,
+    pub url: String,
+    pub events: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<WebhookEndpoint> for WebhookEndpointInfo {
+    fn from(endpoint: WebhookEndpoint) -> Self {
+        Self {
+            id: endpoint.id,
+            url: endpoint.url,
+            events: endpoint.events.iter().map(|event| event.to_string()).collect(),
+            created_at: endpoint.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDeliveryInfo {
+    pub id: i64,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub last_response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryInfo {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type.to_string(),
+            status: delivery.status.to_string(),
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at,
+            last_attempt_at: delivery.last_attempt_at,
+            last_response_status: delivery.last_response_status,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at,
+        }
+    }
+}
+
+#[async_trait]
+pub trait WebhookApi: Send + Sync {
+    /// Register an endpoint. A signing secret is generated when none is given.
+    async fn add_endpoint(&self, url: &str, events: &[WebhookEventType], secret: Option<String>) -> Result<WebhookEndpoint, Error>;
+    async fn list_endpoints(&self) -> Result<Vec<WebhookEndpoint>, Error>;
+    async fn delete_endpoint(&self, id: i64) -> Result<(), Error>;
+    async fn list_deliveries(&self, endpoint_id: i64, offset: u64, limit: u64) -> Result<Vec<WebhookDelivery>, Error>;
+
+    /// Attempt every delivery that is due, returning how many were attempted.
+    async fn deliver_due(&self) -> Result<usize, Error>;
+
+    /// Delete the events past the retention period that have no delivery
+    /// left to attempt, with their deliveries, returning how many events
+    /// were deleted.
+    async fn prune_events(&self) -> Result<u64, Error>;
+}
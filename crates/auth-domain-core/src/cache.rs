@@ -0,0 +1,273 @@
+use std::{
+    hash::Hash,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use auth_db::{adapters::NotificationAdapter, DatabaseTransaction};
+use auth_domain_api::CacheMetrics;
+use auth_domain_models::auth::{Session, SessionId, User};
+use auth_utils::arcbox::ArcBox;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The notification channel instances broadcast their invalidations on.
+pub(crate) const CHANNEL: &str = "auth_play_cache";
+
+/// A bounded cache with a time to live, counting hits and misses. Caches
+/// nothing when its capacity is zero.
+pub(crate) struct Cache<K, V> {
+    name: &'static str,
+    entries: Option<moka::future::Cache<K, (V, Instant)>>,
+    /// The time to live while invalidations from other instances may be
+    /// missed.
+    fallback_ttl: Duration,
+    fallback: AtomicBool,
+    /// Bumped on every invalidation, so a value loaded from before one is
+    /// not cached after it.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K, V> Cache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    fn new(name: &'static str, ttl: Duration, fallback_ttl: Duration, capacity: u64) -> Self {
+        let entries = (capacity > 0).then(|| {
+            moka::future::Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .support_invalidation_closures()
+                .build()
+        });
+
+        Self {
+            name,
+            entries,
+            fallback_ttl,
+            fallback: AtomicBool::new(false),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) async fn get(&self, key: &K) -> Option<V> {
+        let value = match self.entries {
+            Some(ref entries) => entries.get(key).await,
+            None => None,
+        };
+        let value = value
+            .filter(|(_, cached_at)| !self.fallback.load(Ordering::Acquire) || cached_at.elapsed() < self.fallback_ttl)
+            .map(|(value, _)| value);
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        value
+    }
+
+    /// To pass to [`Cache::insert`] with a value about to be loaded.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Cache a value loaded at `generation`, unless something was
+    /// invalidated since.
+    pub(crate) async fn insert(&self, generation: u64, key: K, value: V) {
+        let Some(ref entries) = self.entries else {
+            return;
+        };
+        if self.generation() == generation {
+            entries.insert(key, (value, Instant::now())).await;
+        }
+    }
+
+    pub(crate) async fn invalidate(&self, key: &K) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        if let Some(ref entries) = self.entries {
+            entries.invalidate(key).await;
+        }
+    }
+
+    pub(crate) fn invalidate_where(&self, predicate: impl Fn(&K, &V) -> bool + Send + Sync + 'static) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        if let Some(ref entries) = self.entries {
+            if let Err(err) = entries.invalidate_entries_if(move |key, (value, _)| predicate(key, value)) {
+                tracing::warn!("Couldn't invalidate {} cache entries - {}", self.name, err);
+                entries.invalidate_all();
+            }
+        }
+    }
+
+    fn invalidate_all(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        if let Some(ref entries) = self.entries {
+            entries.invalidate_all();
+        }
+    }
+
+    fn set_fallback(&self, fallback: bool) {
+        self.fallback.store(fallback, Ordering::Release);
+    }
+
+    fn metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            name: self.name,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.as_ref().map(|entries| entries.entry_count()).unwrap_or_default(),
+        }
+    }
+}
+
+/// A change that stales cached entries, on this instance or another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Invalidation {
+    User(i64),
+    UserSessions(i64),
+    Session(SessionId),
+}
+
+/// Invalidations as broadcast to other instances.
+#[derive(Debug, Serialize, Deserialize)]
+struct Notice {
+    origin: Uuid,
+    invalidations: Vec<Invalidation>,
+}
+
+/// Caches of what every authenticated request looks up. Kept fresh by
+/// [`CacheSubscriber`](crate::subscribers::cache::CacheSubscriber), and
+/// across instances by the invalidations they broadcast. Changes made behind
+/// the domain's back, such as roles edited in the database, show once the
+/// entries expire.
+pub(crate) struct Caches {
+    pub users: Cache<i64, User>,
+    pub sessions: Cache<SessionId, Session>,
+    /// Tells this instance's broadcasts from others'.
+    origin: Uuid,
+    notification_adapter: ArcBox<dyn NotificationAdapter>,
+}
+
+impl Caches {
+    pub(crate) fn new(ttl: Duration, fallback_ttl: Duration, capacity: u64, notification_adapter: ArcBox<dyn NotificationAdapter>) -> Self {
+        let caches = Self {
+            users: Cache::new("users", ttl, fallback_ttl, capacity),
+            sessions: Cache::new("sessions", ttl, fallback_ttl, capacity),
+            origin: Uuid::new_v4(),
+            notification_adapter,
+        };
+        // Nothing from other instances is received until invalidations are followed.
+        caches.fall_back();
+
+        caches
+    }
+
+    pub(crate) async fn invalidate(&self, invalidation: Invalidation) {
+        match invalidation {
+            Invalidation::User(user_id) => self.users.invalidate(&user_id).await,
+            Invalidation::UserSessions(user_id) => self.sessions.invalidate_where(move |_, session| session.user_id == Some(user_id)),
+            Invalidation::Session(session_id) => self.sessions.invalidate(&session_id).await,
+        }
+    }
+
+    /// Broadcast invalidations to the other instances once the transaction
+    /// commits.
+    pub(crate) async fn broadcast(&self, tx: &mut DatabaseTransaction, invalidations: Vec<Invalidation>) -> Result<(), auth_db::Error> {
+        if invalidations.is_empty() {
+            return Ok(());
+        }
+        let notice = Notice {
+            origin: self.origin,
+            invalidations,
+        };
+        let payload = serde_json::to_string(&notice).map_err(|err| auth_db::Error::Any(err.into()))?;
+
+        self.notification_adapter.notify(tx, CHANNEL, &payload).await
+    }
+
+    /// Apply invalidations another instance broadcast.
+    pub(crate) async fn receive(&self, payload: &str) {
+        let notice: Notice = match serde_json::from_str(payload) {
+            Ok(notice) => notice,
+            Err(err) => {
+                tracing::warn!("Ignoring a malformed cache invalidation - {}", err);
+                return;
+            }
+        };
+        if notice.origin == self.origin {
+            return;
+        }
+        for invalidation in notice.invalidations {
+            self.invalidate(invalidation).await;
+        }
+    }
+
+    /// Invalidations from other instances may be missed from now on: keep
+    /// entries only for the fallback time to live.
+    pub(crate) fn fall_back(&self) {
+        self.users.set_fallback(true);
+        self.sessions.set_fallback(true);
+    }
+
+    /// Invalidations from other instances are received again. Entries cached
+    /// until now may have missed some, so are dropped.
+    pub(crate) fn resume(&self) {
+        self.users.invalidate_all();
+        self.sessions.invalidate_all();
+        self.users.set_fallback(false);
+        self.sessions.set_fallback(false);
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<CacheMetrics> {
+        vec![self.users.metrics(), self.sessions.metrics()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Cache;
+
+    #[tokio::test]
+    async fn skips_values_loaded_before_an_invalidation() {
+        let cache: Cache<i64, &str> = Cache::new("test", Duration::from_secs(60), Duration::from_secs(1), 10);
+        assert_eq!(cache.get(&1).await, None);
+
+        let generation = cache.generation();
+        cache.insert(generation, 1, "loaded").await;
+        assert_eq!(cache.get(&1).await, Some("loaded"));
+
+        let generation = cache.generation();
+        cache.invalidate(&1).await;
+        cache.insert(generation, 1, "stale").await;
+        assert_eq!(cache.get(&1).await, None);
+
+        let metrics = cache.metrics();
+        assert_eq!((metrics.hits, metrics.misses), (1, 2));
+
+        let disabled: Cache<i64, &str> = Cache::new("test", Duration::from_secs(60), Duration::from_secs(1), 0);
+        disabled.insert(disabled.generation(), 1, "loaded").await;
+        assert_eq!(disabled.get(&1).await, None);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_shorter_ttl() {
+        let cache: Cache<i64, &str> = Cache::new("test", Duration::from_secs(60), Duration::from_millis(50), 10);
+        cache.insert(cache.generation(), 1, "loaded").await;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(cache.get(&1).await, Some("loaded"));
+        cache.set_fallback(true);
+        assert_eq!(cache.get(&1).await, None);
+
+        cache.insert(cache.generation(), 1, "reloaded").await;
+        assert_eq!(cache.get(&1).await, Some("reloaded"));
+    }
+}
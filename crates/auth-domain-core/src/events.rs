@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use auth_db::DatabaseTransaction;
+use auth_domain_models::{
+    audit::RequestContext,
+    auth::{SessionId, User},
+};
+use auth_utils::arcbox::ArcBox;
+
+/// Something that happened in the domain, published by the services once it
+/// has happened.
+#[derive(Debug, Clone)]
+pub enum DomainEvent {
+    UserRegistered {
+        user: User,
+        context: RequestContext,
+    },
+    RegistrationFailed {
+        email: String,
+        reason: String,
+        context: RequestContext,
+    },
+    UserLoggedIn {
+        user: User,
+        context: RequestContext,
+    },
+    LoginFailed {
+        email: String,
+        reason: String,
+        context: RequestContext,
+    },
+    UserLoggedOut {
+        user_id: i64,
+        context: RequestContext,
+    },
+    SessionCreated {
+        session_id: SessionId,
+        user_id: Option<i64>,
+        context: RequestContext,
+    },
+    SessionRevoked {
+        session_id: SessionId,
+        user_id: Option<i64>,
+        context: RequestContext,
+    },
+    SessionRotated {
+        old_session_id: SessionId,
+        session_id: SessionId,
+        user_id: Option<i64>,
+        context: RequestContext,
+    },
+    /// All of a user's sessions were revoked.
+    UserSessionsRevoked {
+        user_id: i64,
+        context: RequestContext,
+    },
+    PasswordChanged {
+        user: User,
+        context: RequestContext,
+    },
+    PasswordChangeFailed {
+        user_id: i64,
+        reason: String,
+        context: RequestContext,
+    },
+    AccountDeletionScheduled {
+        user: User,
+        context: RequestContext,
+    },
+    AccountDeletionCancelled {
+        user: User,
+        context: RequestContext,
+    },
+    AccountPurged {
+        user_id: i64,
+    },
+}
+
+#[async_trait]
+pub trait EventSubscriber: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Called inside the transaction that made the change, for reactions that
+    /// must commit or roll back with it. Only successful changes are published
+    /// here, and an error rolls the whole change back.
+    async fn handle_in_transaction(&self, _tx: &mut DatabaseTransaction, _event: &DomainEvent) -> Result<(), auth_db::Error> {
+        Ok(())
+    }
+
+    /// Called once the change is committed, or has failed.
+    async fn handle(&self, _event: &DomainEvent) {}
+}
+
+/// In-process publish/subscribe for [`DomainEvent`]s. Subscribers are called
+/// in the order they were registered.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Vec<ArcBox<dyn EventSubscriber>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self, subscriber: ArcBox<dyn EventSubscriber>) {
+        tracing::debug!("Subscribing {} to domain events", subscriber.name());
+        self.subscribers.push(subscriber);
+    }
+
+    pub(crate) async fn publish_in_transaction(&self, tx: &mut DatabaseTransaction, event: &DomainEvent) -> Result<(), auth_db::Error> {
+        for subscriber in self.subscribers.iter() {
+            subscriber.handle_in_transaction(tx, event).await?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn publish(&self, event: &DomainEvent) {
+        for subscriber in self.subscribers.iter() {
+            subscriber.handle(event).await;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use auth_domain_api::{AccountApi, CacheApi, WebhookApi};
+use auth_utils::arcbox::ArcBox;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+use crate::Error;
+
+/// How often delivered webhook events past their retention are deleted.
+const WEBHOOK_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub async fn start_purge_job(account_api: ArcBox<dyn AccountApi>, interval: Duration, subsys: SubsystemHandle) -> Result<(), Error> {
+    tracing::trace!("Starting account purge job");
+
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            }
+
+            _ = ticker.tick() => {
+                match account_api.purge_deleted_users().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("Purged {} deleted accounts", count),
+                    Err(err) => tracing::warn!("Failed to purge deleted accounts: {}", err),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn start_webhook_worker(webhook_api: ArcBox<dyn WebhookApi>, interval: Duration, subsys: SubsystemHandle) -> Result<(), Error> {
+    tracing::trace!("Starting webhook worker");
+
+    let mut ticker = tokio::time::interval(interval);
+    let mut prune_ticker = tokio::time::interval(WEBHOOK_PRUNE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            }
+
+            _ = ticker.tick() => {
+                match webhook_api.deliver_due().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::debug!("Attempted {} webhook deliveries", count),
+                    Err(err) => tracing::warn!("Failed to deliver webhooks: {}", err),
+                }
+            }
+
+            _ = prune_ticker.tick() => {
+                match webhook_api.prune_events().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::debug!("Pruned {} webhook events", count),
+                    Err(err) => tracing::warn!("Failed to prune webhook events: {}", err),
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn start_cache_listener(cache_api: ArcBox<dyn CacheApi>, retry_interval: Duration, subsys: SubsystemHandle) -> Result<(), Error> {
+    tracing::trace!("Starting cache invalidation listener");
+
+    loop {
+        tokio::select! {
+            _ = subsys.on_shutdown_requested() => {
+                break;
+            }
+
+            result = cache_api.follow_invalidations() => {
+                if let Err(err) = result {
+                    tracing::warn!("Not receiving cache invalidations, retrying in {:?}: {}", retry_interval, err);
+                }
+                tokio::select! {
+                    _ = subsys.on_shutdown_requested() => {
+                        break;
+                    }
+
+                    _ = tokio::time::sleep(retry_interval) => {}
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,24 +1,105 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use account::AccountService;
+use audit::AuditService;
 use auth::AuthService;
+use auth_audit::AuditSender;
 use auth_db::RepositoryAdapters;
-use auth_domain_api::{AuthApi, AuthDomainApi, HealthApi};
+use auth_domain_api::{AccountApi, AuditApi, AuthApi, AuthDomainApi, CacheApi, HealthApi, WebhookApi};
 use auth_utils::{arcbox, arcbox::ArcBox};
+use cache::Caches;
+use events::EventBus;
 use health::HealthService;
+use services::cache::CacheService;
+use subscribers::{audit::AuditSubscriber, cache::CacheSubscriber, webhook::WebhookSubscriber};
+use webhook::{WebhookService, WebhookSettings};
 
+mod cache;
 mod error;
+pub mod events;
+pub mod jobs;
 mod services;
+mod subscribers;
 
 pub use error::*;
 pub(crate) use services::*;
 
-#[tracing::instrument(level = "trace", skip(repository_adapters))]
-pub async fn create_auth(repository_adapters: Arc<RepositoryAdapters>) -> Result<AuthDomainApi, Error> {
-    let auth_service = AuthService::new(repository_adapters.clone());
-    let health_service = HealthService::new();
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    /// How long a soft-deleted account is kept before it is purged.
+    pub deletion_grace_period: Duration,
+    /// Delivery attempts per webhook before it is marked failed.
+    pub webhook_max_attempts: u32,
+    /// Delay before the first webhook retry; doubled on each further attempt.
+    pub webhook_retry_base: Duration,
+    /// Upper bound on the delay between webhook retries.
+    pub webhook_retry_max: Duration,
+    /// Timeout for a single webhook request.
+    pub webhook_timeout: Duration,
+    /// How long webhook events, and their deliveries, are kept once nothing
+    /// is left to deliver.
+    pub webhook_retention: Duration,
+    /// How long users and sessions are cached. Bounds how stale a change
+    /// made outside the domain, such as roles edited in the database, can be.
+    pub cache_ttl: Duration,
+    /// Users, and separately sessions, cached at most. Nothing is cached when zero.
+    pub cache_capacity: u64,
+    /// How long users and sessions are cached while invalidations from other
+    /// instances may be missed.
+    pub cache_fallback_ttl: Duration,
+}
+
+#[tracing::instrument(level = "trace", skip(repository_adapters, audit_sender))]
+pub async fn create_auth(config: Configuration, repository_adapters: Arc<RepositoryAdapters>, audit_sender: AuditSender) -> Result<AuthDomainApi, Error> {
+    let grace_period = chrono::Duration::from_std(config.deletion_grace_period).unwrap_or(chrono::Duration::MAX);
+
+    let audit_service = AuditService::new(repository_adapters.clone(), audit_sender);
+    let audit_api: ArcBox<dyn AuditApi> = arcbox!(audit_service);
+
+    let caches = Arc::new(Caches::new(
+        config.cache_ttl,
+        config.cache_fallback_ttl,
+        config.cache_capacity,
+        repository_adapters.notification_adapter.clone(),
+    ));
+
+    let audit_subscriber = AuditSubscriber::new(audit_api.clone());
+    let cache_subscriber = CacheSubscriber::new(caches.clone());
+    let webhook_subscriber = WebhookSubscriber::new(repository_adapters.clone());
+
+    let mut event_bus = EventBus::new();
+    event_bus.subscribe(arcbox!(cache_subscriber));
+    event_bus.subscribe(arcbox!(webhook_subscriber));
+    event_bus.subscribe(arcbox!(audit_subscriber));
+    let event_bus = Arc::new(event_bus);
+
+    let account_service = AccountService::new(repository_adapters.clone(), event_bus.clone(), grace_period);
+    let auth_service = AuthService::new(repository_adapters.clone(), event_bus.clone(), caches.clone());
+    let cache_service = CacheService::new(repository_adapters.clone(), caches.clone());
+    let health_service = HealthService::new(caches);
+    let webhook_service = WebhookService::new(
+        repository_adapters.clone(),
+        WebhookSettings {
+            max_attempts: config.webhook_max_attempts,
+            retry_base: config.webhook_retry_base,
+            retry_max: config.webhook_retry_max,
+            timeout: config.webhook_timeout,
+            retention: config.webhook_retention,
+        },
+    );
 
+    let account_api: ArcBox<dyn AccountApi> = arcbox!(account_service);
     let auth_api: ArcBox<dyn AuthApi> = arcbox!(auth_service);
+    let cache_api: ArcBox<dyn CacheApi> = arcbox!(cache_service);
     let health_api: ArcBox<dyn HealthApi> = arcbox!(health_service);
+    let webhook_api: ArcBox<dyn WebhookApi> = arcbox!(webhook_service);
 
-    Ok(AuthDomainApi { auth_api, health_api })
+    Ok(AuthDomainApi {
+        account_api,
+        audit_api,
+        auth_api,
+        cache_api,
+        health_api,
+        webhook_api,
+    })
 }
@@ -1,2 +1,6 @@
+pub(crate) mod account;
+pub(crate) mod audit;
 pub(crate) mod auth;
+pub(crate) mod cache;
 pub(crate) mod health;
+pub(crate) mod webhook;
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use auth_db::{
+    adapters::{AuditAdapter, SessionAdapter, UserAdapter},
+    Repository, RepositoryAdapters,
+};
+use auth_domain_api::{AccountApi, AuditEventInfo, Error, ProfileExport, SessionExport, UserExport};
+use auth_domain_models::{
+    audit::{AuditEvent, AuditFilter, RequestContext},
+    auth::{Session, User},
+};
+use auth_utils::arcbox::ArcBox;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::events::{DomainEvent, EventBus};
+
+#[derive(Clone)]
+pub(crate) struct AccountService {
+    repository: Arc<Repository>,
+    user_adapter: ArcBox<dyn UserAdapter>,
+    session_adapter: ArcBox<dyn SessionAdapter>,
+    audit_adapter: ArcBox<dyn AuditAdapter>,
+    event_bus: Arc<EventBus>,
+    grace_period: Duration,
+}
+
+impl AccountService {
+    pub(crate) fn new(repository_adapters: Arc<RepositoryAdapters>, event_bus: Arc<EventBus>, grace_period: Duration) -> Self {
+        Self {
+            repository: repository_adapters.repository.clone(),
+            user_adapter: repository_adapters.user_adapter.clone(),
+            session_adapter: repository_adapters.session_adapter.clone(),
+            audit_adapter: repository_adapters.audit_adapter.clone(),
+            event_bus,
+            grace_period,
+        }
+    }
+}
+
+#[async_trait]
+impl AccountApi for AccountService {
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn export_user(&self, id: i64) -> Result<UserExport, Error> {
+        let filter = AuditFilter {
+            actor_id: Some(id),
+            limit: i64::MAX as u64,
+            ..Default::default()
+        };
+
+        let result: Result<(User, Vec<Session>, Vec<AuditEvent>), auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let user_adapter = self.user_adapter.clone();
+                let session_adapter = self.session_adapter.clone();
+                let audit_adapter = self.audit_adapter.clone();
+                let filter = filter.clone();
+                Box::pin(async move {
+                    let user = user_adapter.get_user_by_id(tx, id).await?;
+                    let sessions = session_adapter.list_user_sessions(tx, id).await?;
+                    let audit_events = audit_adapter.list_events(tx, &filter).await?.events;
+
+                    Ok((user, sessions, audit_events))
+                })
+            })
+            .await;
+
+        match result {
+            Ok((user, sessions, audit_events)) => Ok(UserExport {
+                exported_at: Utc::now(),
+                profile: ProfileExport {
+                    id: user.id,
+                    name: user.name,
+                    email: user.email,
+                    roles: user.roles,
+                    deleted_at: user.deleted_at,
+                    purge_at: user.purge_at,
+                },
+                sessions: sessions
+                    .into_iter()
+                    .map(|session| SessionExport {
+                        id: session.id,
+                        expiry: session.expiry,
+                    })
+                    .collect(),
+                audit_events: audit_events.into_iter().map(AuditEventInfo::from).collect(),
+            }),
+            Err(auth_db::Error::NotFound) => Err(Error::NotFound),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn schedule_deletion(&self, id: i64, context: &RequestContext) -> Result<User, Error> {
+        let purge_at = Utc::now().checked_add_signed(self.grace_period).unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+        let result: Result<(User, DomainEvent), auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let user_adapter = self.user_adapter.clone();
+                let session_adapter = self.session_adapter.clone();
+                let context = context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let user = user_adapter.schedule_deletion(tx, id, purge_at).await?;
+                    let revoked = session_adapter.delete_user_sessions(tx, id).await?;
+                    tracing::info!("User {} scheduled for deletion, {} sessions revoked", id, revoked);
+                    let event = DomainEvent::AccountDeletionScheduled { user: user.clone(), context };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok((user, event))
+                })
+            })
+            .await;
+
+        match result {
+            Ok((user, event)) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(user)
+            }
+            Err(auth_db::Error::NotFound) => Err(Error::NotFound),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn cancel_deletion(&self, id: i64, context: &RequestContext) -> Result<User, Error> {
+        let result: Result<(User, DomainEvent), auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.user_adapter.clone();
+                let context = context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let user = adapter.cancel_deletion(tx, id).await?;
+                    let event = DomainEvent::AccountDeletionCancelled { user: user.clone(), context };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok((user, event))
+                })
+            })
+            .await;
+
+        match result {
+            Ok((user, event)) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(user)
+            }
+            Err(auth_db::Error::NotFound) => Err(Error::NotFound),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn purge_deleted_users(&self) -> Result<usize, Error> {
+        let result: Result<Vec<DomainEvent>, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let user_adapter = self.user_adapter.clone();
+                let session_adapter = self.session_adapter.clone();
+                let audit_adapter = self.audit_adapter.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let users = user_adapter.list_purgeable_users(tx, Utc::now()).await?;
+                    let mut events = Vec::with_capacity(users.len());
+                    for user in users.iter() {
+                        session_adapter.delete_user_sessions(tx, user.id).await?;
+                        audit_adapter.anonymize_actor(tx, user.id).await?;
+                        user_adapter.purge_user(tx, user.id).await?;
+                        let event = DomainEvent::AccountPurged { user_id: user.id };
+                        event_bus.publish_in_transaction(tx, &event).await?;
+                        events.push(event);
+                        tracing::info!("User {} purged", user.id);
+                    }
+
+                    Ok(events)
+                })
+            })
+            .await;
+
+        match result {
+            Ok(events) => {
+                for event in events.iter() {
+                    self.event_bus.publish(event).await;
+                }
+
+                Ok(events.len())
+            }
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use auth_audit::AuditSender;
+use auth_db::{adapters::AuditAdapter, Repository, RepositoryAdapters};
+use auth_domain_api::{AuditApi, Error};
+use auth_domain_models::audit::{AuditEvent, AuditFilter, AuditPage, NewAuditEvent};
+use auth_utils::arcbox::ArcBox;
+use chrono::Utc;
+
+#[derive(Clone)]
+pub(crate) struct AuditService {
+    repository: Arc<Repository>,
+    audit_adapter: ArcBox<dyn AuditAdapter>,
+    audit_sender: AuditSender,
+}
+
+impl AuditService {
+    pub(crate) fn new(repository_adapters: Arc<RepositoryAdapters>, audit_sender: AuditSender) -> Self {
+        Self {
+            repository: repository_adapters.repository.clone(),
+            audit_adapter: repository_adapters.audit_adapter.clone(),
+            audit_sender,
+        }
+    }
+}
+
+#[async_trait]
+impl AuditApi for AuditService {
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn record(&self, event: NewAuditEvent) {
+        let new_event = event.clone();
+
+        let result = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.audit_adapter.clone();
+                let new_event = new_event.clone();
+                Box::pin(async move { adapter.add_event(tx, &new_event).await })
+            })
+            .await;
+
+        // External sinks still receive events the database failed to store.
+        let event = match result {
+            Ok(event) => event,
+            Err(err) => {
+                tracing::warn!("Failed to record {} audit event: {}", event.kind, err);
+                AuditEvent {
+                    id: 0,
+                    occurred_at: Utc::now(),
+                    kind: event.kind,
+                    outcome: event.outcome,
+                    actor_id: event.actor_id,
+                    actor_email: event.actor_email,
+                    ip: event.ip,
+                    user_agent: event.user_agent,
+                    detail: event.detail,
+                }
+            }
+        };
+        self.audit_sender.send(event);
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn list_events(&self, filter: &AuditFilter) -> Result<AuditPage, Error> {
+        let result: Result<AuditPage, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.audit_adapter.clone();
+                let filter = filter.clone();
+                Box::pin(async move { adapter.list_events(tx, &filter).await })
+            })
+            .await;
+
+        match result {
+            Ok(page) => Ok(page),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+}
@@ -6,113 +6,268 @@ use auth_db::{
     Repository, RepositoryAdapters,
 };
 use auth_domain_api::{AuthApi, Error, UserInfo};
-use auth_domain_models::auth::{NewSession, NewUser, Session, SessionId, User};
+use auth_domain_models::{
+    audit::RequestContext,
+    auth::{NewSession, NewUser, Session, SessionId, SessionRevocation, User},
+};
 use auth_utils::arcbox::ArcBox;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    cache::{Caches, Invalidation},
+    events::{DomainEvent, EventBus},
+};
 
 #[derive(Clone)]
 pub(crate) struct AuthService {
     repository: Arc<Repository>,
     user_adapter: ArcBox<dyn UserAdapter>,
     session_adapter: ArcBox<dyn SessionAdapter>,
+    event_bus: Arc<EventBus>,
+    caches: Arc<Caches>,
 }
 
 impl AuthService {
-    pub(crate) fn new(repository_adapters: Arc<RepositoryAdapters>) -> Self {
+    pub(crate) fn new(repository_adapters: Arc<RepositoryAdapters>, event_bus: Arc<EventBus>, caches: Arc<Caches>) -> Self {
         Self {
             repository: repository_adapters.repository.clone(),
             user_adapter: repository_adapters.user_adapter.clone(),
             session_adapter: repository_adapters.session_adapter.clone(),
+            event_bus,
+            caches,
         }
     }
 }
 
 #[async_trait]
 impl AuthApi for AuthService {
-    #[tracing::instrument(level = "trace", skip(self))]
-    async fn register(&self, new_user: &NewUser) -> Result<User, Error> {
-        let adapter = self.user_adapter.clone();
+    #[tracing::instrument(level = "trace", skip(self, new_user))]
+    async fn register(&self, new_user: &NewUser, context: &RequestContext) -> Result<User, Error> {
+        let email = new_user.email.clone();
         let new_user = NewUser {
             name: new_user.name.clone(),
             email: new_user.email.clone(),
             password: new_user.password.clone(),
         };
+        let event_context = context.clone();
 
-        let result: Result<User, auth_db::Error> = self
+        let result: Result<(User, DomainEvent), auth_db::Error> = self
             .repository
-            .transaction(|tx| Box::pin(async move { adapter.add_user(tx, &new_user).await }))
+            .transaction(|tx| {
+                let adapter = self.user_adapter.clone();
+                let new_user = new_user.clone();
+                let event_context = event_context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let user = adapter.add_user(tx, &new_user).await?;
+                    let event = DomainEvent::UserRegistered {
+                        user: user.clone(),
+                        context: event_context,
+                    };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok((user, event))
+                })
+            })
             .await;
 
         match result {
-            Ok(user) => Ok(user),
-            Err(err) => Err(Error::DatabaseError(err)),
+            Ok((user, event)) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(user)
+            }
+            Err(err) => {
+                let event = DomainEvent::RegistrationFailed {
+                    email,
+                    reason: err.to_string(),
+                    context: context.clone(),
+                };
+                self.event_bus.publish(&event).await;
+
+                Err(Error::DatabaseError(err))
+            }
         }
     }
 
     #[tracing::instrument(level = "trace", skip(self, password))]
-    async fn authenticate(&self, email: &str, password: &str) -> Result<UserInfo, Error> {
-        let adapter = self.user_adapter.clone();
+    async fn authenticate(&self, email: &str, password: &str, context: &RequestContext) -> Result<UserInfo, Error> {
         let email = email.to_string();
         let password = password.to_string();
+        let attempted_email = email.clone();
 
         let result: Result<User, auth_db::Error> = self
             .repository
-            .transaction(|tx| Box::pin(async move { adapter.authenticate_user(tx, &email, &password).await }))
+            .transaction(|tx| {
+                let adapter = self.user_adapter.clone();
+                let email = email.clone();
+                let password = password.clone();
+                Box::pin(async move { adapter.authenticate_user(tx, &email, &password).await })
+            })
             .await;
         match result {
-            Ok(user) => Ok(UserInfo {
-                id: user.id,
-                name: user.name.clone(),
-                email: user.email.clone(),
-                password_sha: user.password_sha.clone(),
-            }),
-            Err(_) => Err(Error::NotFound),
+            Ok(user) if user.deleted_at.is_none() => {
+                let event = DomainEvent::UserLoggedIn {
+                    user: user.clone(),
+                    context: context.clone(),
+                };
+                self.event_bus.publish(&event).await;
+
+                Ok(UserInfo::from(user))
+            }
+            result => {
+                let reason = match result {
+                    Err(err) => err.to_string(),
+                    Ok(_) => "Account deleted".to_string(),
+                };
+                let event = DomainEvent::LoginFailed {
+                    email: attempted_email,
+                    reason,
+                    context: context.clone(),
+                };
+                self.event_bus.publish(&event).await;
+
+                Err(Error::NotFound)
+            }
         }
     }
 
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn logout(&self, id: i64, context: &RequestContext) -> Result<(), Error> {
+        let event = DomainEvent::UserLoggedOut {
+            user_id: id,
+            context: context.clone(),
+        };
+        self.event_bus.publish(&event).await;
+
+        Ok(())
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     async fn get_user(&self, id: i64) -> Result<UserInfo, Error> {
-        let adapter = self.user_adapter.clone();
+        if let Some(user) = self.caches.users.get(&id).await {
+            return Ok(UserInfo::from(user));
+        }
+        let generation = self.caches.users.generation();
 
         let result: Result<User, auth_db::Error> = self
             .repository
-            .transaction(|tx| Box::pin(async move { adapter.get_user_by_id(tx, id).await }))
+            .transaction(|tx| {
+                let adapter = self.user_adapter.clone();
+                Box::pin(async move { adapter.get_user_by_id(tx, id).await })
+            })
             .await;
         match result {
-            Ok(user) => Ok(UserInfo {
-                id: user.id,
-                name: user.name.clone(),
-                email: user.email.clone(),
-                password_sha: user.password_sha.clone(),
-            }),
-            Err(_) => Err(Error::NotFound),
+            Ok(user) if user.deleted_at.is_none() => {
+                self.caches.users.insert(generation, id, user.clone()).await;
+                Ok(UserInfo::from(user))
+            }
+            _ => Err(Error::NotFound),
         }
     }
 
-    #[tracing::instrument(level = "trace", skip(self))]
-    async fn create_session(&self, new_session: &NewSession) -> Result<Session, Error> {
-        let adapter = self.session_adapter.clone();
-        let new_session = new_session.clone();
+    #[tracing::instrument(level = "trace", skip(self, current_password, new_password))]
+    async fn change_password(&self, id: i64, current_password: &str, new_password: &str, context: &RequestContext) -> Result<UserInfo, Error> {
+        let current_password = current_password.to_string();
+        let new_password = new_password.to_string();
+        let event_context = context.clone();
 
-        let result: Result<Session, auth_db::Error> = self
+        let result: Result<(User, DomainEvent), auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.user_adapter.clone();
+                let current_password = current_password.clone();
+                let new_password = new_password.clone();
+                let event_context = event_context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let user = adapter.update_password(tx, id, &current_password, &new_password).await?;
+                    let event = DomainEvent::PasswordChanged {
+                        user: user.clone(),
+                        context: event_context,
+                    };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok((user, event))
+                })
+            })
+            .await;
+
+        match result {
+            Ok((user, event)) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(UserInfo::from(user))
+            }
+            Err(err) => {
+                let event = DomainEvent::PasswordChangeFailed {
+                    user_id: id,
+                    reason: err.to_string(),
+                    context: context.clone(),
+                };
+                self.event_bus.publish(&event).await;
+
+                match err {
+                    auth_db::Error::InvalidPassword => Err(Error::InvalidPassword),
+                    auth_db::Error::NotFound => Err(Error::NotFound),
+                    err => Err(Error::DatabaseError(err)),
+                }
+            }
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn create_session(&self, new_session: &NewSession, context: &RequestContext) -> Result<Session, Error> {
+        let result: Result<(Session, DomainEvent), auth_db::Error> = self
             .repository
-            .transaction(|tx| Box::pin(async move { adapter.create_session(tx, &new_session).await }))
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                let new_session = new_session.clone();
+                let context = context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let session = adapter.create_session(tx, &new_session).await?;
+                    let event = DomainEvent::SessionCreated {
+                        session_id: session.id,
+                        user_id: session.user_id,
+                        context,
+                    };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok((session, event))
+                })
+            })
             .await;
 
         match result {
-            Ok(session) => Ok(session),
+            Ok((session, event)) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(session)
+            }
             Err(err) => Err(Error::DatabaseError(err)),
         }
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
     async fn save_session(&self, session: &Session) -> Result<(), Error> {
-        let adapter = self.session_adapter.clone();
-        let session = session.clone();
+        let id = session.id;
 
         let result: Result<Session, auth_db::Error> = self
             .repository
-            .transaction(|tx| Box::pin(async move { adapter.save_session(tx, &session).await }))
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                let session = session.clone();
+                let caches = self.caches.clone();
+                Box::pin(async move {
+                    let session = adapter.save_session(tx, &session).await?;
+                    caches.broadcast(tx, vec![Invalidation::Session(id)]).await?;
+
+                    Ok(session)
+                })
+            })
             .await;
+        self.caches.invalidate(Invalidation::Session(id)).await;
 
         match result {
             Ok(_) => Ok(()),
@@ -120,34 +275,210 @@ impl AuthApi for AuthService {
         }
     }
 
+    #[tracing::instrument(level = "trace", skip(self, extensions))]
+    async fn extend_sessions(&self, extensions: &[(SessionId, DateTime<Utc>)]) -> Result<u64, Error> {
+        let extensions = extensions.to_vec();
+
+        let result: Result<u64, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                let extensions = extensions.clone();
+                Box::pin(async move { adapter.extend_sessions(tx, &extensions).await })
+            })
+            .await;
+
+        result.map_err(Error::DatabaseError)
+    }
+
     #[tracing::instrument(level = "trace", skip(self))]
     async fn load_session(&self, id: &SessionId) -> Result<Option<Session>, Error> {
-        let adapter = self.session_adapter.clone();
-        let id = id.clone();
+        if let Some(session) = self.caches.sessions.get(id).await {
+            if session.expiry > Utc::now() {
+                return Ok(Some(session));
+            }
+        }
+        let id = *id;
+        let generation = self.caches.sessions.generation();
 
         let result: Result<Option<Session>, auth_db::Error> = self
             .repository
-            .transaction(|tx| Box::pin(async move { adapter.load_session(tx, &id).await }))
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                Box::pin(async move { adapter.load_session(tx, &id).await })
+            })
             .await;
 
         match result {
-            Ok(session) => Ok(session),
+            Ok(Some(session)) => {
+                self.caches.sessions.insert(generation, id, session.clone()).await;
+                Ok(Some(session))
+            }
+            Ok(None) => Ok(None),
             Err(err) => Err(Error::DatabaseError(err)),
         }
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
-    async fn delete_session(&self, id: &SessionId) -> Result<(), Error> {
-        let adapter = self.session_adapter.clone();
-        let id = id.clone();
+    async fn delete_session(&self, id: &SessionId, context: &RequestContext) -> Result<(), Error> {
+        let id = *id;
 
-        let result: Result<(), auth_db::Error> = self
+        let result: Result<Option<DomainEvent>, auth_db::Error> = self
             .repository
-            .transaction(|tx| Box::pin(async move { adapter.delete_session(tx, &id).await }))
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                let context = context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let Some(session) = adapter.delete_session(tx, &id).await? else {
+                        return Ok(None);
+                    };
+                    let event = DomainEvent::SessionRevoked {
+                        session_id: session.id,
+                        user_id: session.user_id,
+                        context,
+                    };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok(Some(event))
+                })
+            })
             .await;
+
         match result {
-            Ok(_) => Ok(()),
+            Ok(Some(event)) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(())
+            }
+            Ok(None) => Ok(()),
             Err(err) => Err(Error::DatabaseError(err)),
         }
     }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn cycle_session(&self, id: &SessionId, context: &RequestContext) -> Result<Option<Session>, Error> {
+        let id = *id;
+
+        let result: Result<Option<(Session, DomainEvent)>, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                let context = context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let Some(session) = adapter.cycle_session(tx, &id).await? else {
+                        return Ok(None);
+                    };
+                    let event = DomainEvent::SessionRotated {
+                        old_session_id: id,
+                        session_id: session.id,
+                        user_id: session.user_id,
+                        context,
+                    };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok(Some((session, event)))
+                })
+            })
+            .await;
+
+        match result {
+            Ok(Some((session, event))) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(Some(session))
+            }
+            Ok(None) => Ok(None),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn revoke_session(&self, id: &SessionId, user_id: Option<i64>, expiry: DateTime<Utc>, context: &RequestContext) -> Result<(), Error> {
+        let session_id = *id;
+        let revocation = SessionRevocation {
+            session_id: Some(session_id),
+            user_id,
+            revoked_at: Utc::now(),
+            expiry,
+        };
+
+        let result: Result<DomainEvent, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                let revocation = revocation.clone();
+                let context = context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    adapter.revoke_sessions(tx, &revocation).await?;
+                    let event = DomainEvent::SessionRevoked { session_id, user_id, context };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok(event)
+                })
+            })
+            .await;
+
+        match result {
+            Ok(event) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(())
+            }
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn revoke_user_sessions(&self, user_id: i64, expiry: DateTime<Utc>, context: &RequestContext) -> Result<(), Error> {
+        let revocation = SessionRevocation {
+            session_id: None,
+            user_id: Some(user_id),
+            revoked_at: Utc::now(),
+            expiry,
+        };
+
+        let result: Result<DomainEvent, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                let revocation = revocation.clone();
+                let context = context.clone();
+                let event_bus = self.event_bus.clone();
+                Box::pin(async move {
+                    let deleted = adapter.delete_user_sessions(tx, user_id).await?;
+                    adapter.revoke_sessions(tx, &revocation).await?;
+                    tracing::info!("Sessions of user {} revoked, {} stored sessions deleted", user_id, deleted);
+                    let event = DomainEvent::UserSessionsRevoked { user_id, context };
+                    event_bus.publish_in_transaction(tx, &event).await?;
+
+                    Ok(event)
+                })
+            })
+            .await;
+
+        match result {
+            Ok(event) => {
+                self.event_bus.publish(&event).await;
+
+                Ok(())
+            }
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn list_session_revocations(&self) -> Result<Vec<SessionRevocation>, Error> {
+        let result: Result<Vec<SessionRevocation>, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.session_adapter.clone();
+                Box::pin(async move { adapter.list_revocations(tx).await })
+            })
+            .await;
+
+        result.map_err(Error::DatabaseError)
+    }
 }
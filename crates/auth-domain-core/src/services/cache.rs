@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use auth_db::{Repository, RepositoryAdapters};
+use auth_domain_api::{CacheApi, Error};
+
+use crate::cache::{Caches, CHANNEL};
+
+#[derive(Clone)]
+pub(crate) struct CacheService {
+    repository: Arc<Repository>,
+    caches: Arc<Caches>,
+}
+
+impl CacheService {
+    pub(crate) fn new(repository_adapters: Arc<RepositoryAdapters>, caches: Arc<Caches>) -> Self {
+        Self {
+            repository: repository_adapters.repository.clone(),
+            caches,
+        }
+    }
+}
+
+#[async_trait]
+impl CacheApi for CacheService {
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn follow_invalidations(&self) -> Result<(), Error> {
+        let mut listener = self.repository.listen(CHANNEL).await?;
+        let _following = Following::start(&self.caches);
+        tracing::debug!("Following cache invalidations");
+
+        loop {
+            let payload = listener.recv().await?;
+            self.caches.receive(&payload).await;
+        }
+    }
+}
+
+/// Falls the caches back to the shorter time to live when invalidations stop
+/// being followed, for whatever reason.
+struct Following<'a> {
+    caches: &'a Caches,
+}
+
+impl<'a> Following<'a> {
+    fn start(caches: &'a Caches) -> Self {
+        caches.resume();
+
+        Self { caches }
+    }
+}
+
+impl Drop for Following<'_> {
+    fn drop(&mut self) {
+        self.caches.fall_back();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use auth_audit::{create_audit_sinks, Configuration as AuditConfiguration};
+    use auth_db::{connect_database, testing::database_url, Configuration as DatabaseConfiguration};
+    use auth_domain_api::AuthDomainApi;
+    use auth_domain_models::{audit::RequestContext, auth::NewSession};
+    use chrono::Utc;
+
+    use crate::{create_auth, Configuration};
+
+    async fn instance() -> AuthDomainApi {
+        let repository_adapters = connect_database(&DatabaseConfiguration::new(database_url())).await.unwrap();
+        let (audit_sender, _) = create_audit_sinks(&AuditConfiguration {
+            buffer_size: 16,
+            file: None,
+            syslog: None,
+        })
+        .unwrap();
+        let config = Configuration {
+            deletion_grace_period: Duration::from_secs(60),
+            webhook_max_attempts: 1,
+            webhook_retry_base: Duration::from_secs(1),
+            webhook_retry_max: Duration::from_secs(1),
+            webhook_timeout: Duration::from_secs(1),
+            webhook_retention: Duration::from_secs(60),
+            cache_ttl: Duration::from_secs(60),
+            cache_capacity: 100,
+            cache_fallback_ttl: Duration::from_millis(200),
+        };
+
+        create_auth(config, repository_adapters, audit_sender).await.unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a local Postgres"]
+    async fn follows_invalidations_from_other_instances() {
+        let (here, there) = (instance().await, instance().await);
+        let cache_api = here.cache_api.clone();
+        let following = tokio::spawn(async move { cache_api.follow_invalidations().await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut session = there
+            .auth_api
+            .create_session(
+                &NewSession {
+                    user_id: None,
+                    data: b"before".to_vec(),
+                    expiry: Utc::now() + chrono::Duration::minutes(1),
+                },
+                &RequestContext::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(here.auth_api.load_session(&session.id).await.unwrap().unwrap().data, b"before");
+
+        session.data = b"after".to_vec();
+        there.auth_api.save_session(&session).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(here.auth_api.load_session(&session.id).await.unwrap().unwrap().data, b"after");
+
+        // Without invalidations, entries are kept only for the fallback time to live.
+        following.abort();
+        let _ = following.await;
+        session.data = b"missed".to_vec();
+        there.auth_api.save_session(&session).await.unwrap();
+        assert_eq!(here.auth_api.load_session(&session.id).await.unwrap().unwrap().data, b"after");
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(here.auth_api.load_session(&session.id).await.unwrap().unwrap().data, b"missed");
+
+        there.auth_api.delete_session(&session.id, &RequestContext::default()).await.unwrap();
+    }
+}
@@ -1,12 +1,18 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use auth_domain_api::HealthApi;
+use auth_domain_api::{CacheMetrics, HealthApi};
+
+use crate::cache::Caches;
 
 #[derive(Clone)]
-pub(crate) struct HealthService {}
+pub(crate) struct HealthService {
+    caches: Arc<Caches>,
+}
 
 impl HealthService {
-    pub(crate) fn new() -> Self {
-        Self {}
+    pub(crate) fn new(caches: Arc<Caches>) -> Self {
+        Self { caches }
     }
 }
 
@@ -16,4 +22,8 @@ impl HealthApi for HealthService {
     async fn is_healthy(&self) -> bool {
         true
     }
+
+    fn cache_metrics(&self) -> Vec<CacheMetrics> {
+        self.caches.metrics()
+    }
 }
@@ -0,0 +1,380 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use auth_db::{adapters::WebhookAdapter, Repository, RepositoryAdapters};
+use auth_domain_api::{Error, WebhookApi};
+use auth_domain_models::webhook::{
+    NewWebhookEndpoint, NewWebhookEvent, PendingWebhookDelivery, WebhookAttempt, WebhookDelivery, WebhookEndpoint, WebhookEventType,
+};
+use auth_utils::{arcbox::ArcBox, hmac};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use uuid::Uuid;
+
+pub(crate) const SIGNATURE_HEADER: &str = "x-auth-play-signature";
+pub(crate) const EVENT_HEADER: &str = "x-auth-play-event";
+pub(crate) const DELIVERY_HEADER: &str = "x-auth-play-delivery";
+
+const CLAIM_BATCH_SIZE: u64 = 50;
+
+#[derive(Debug, Clone)]
+pub(crate) struct WebhookSettings {
+    pub max_attempts: u32,
+    pub retry_base: Duration,
+    pub retry_max: Duration,
+    pub timeout: Duration,
+    pub retention: Duration,
+}
+
+/// Build an outbox event. The payload is what receivers see and sign-check.
+pub(crate) fn webhook_event(event_type: WebhookEventType, data: serde_json::Value) -> NewWebhookEvent {
+    let id = Uuid::new_v4();
+    let payload = json!({
+        "id": id,
+        "type": event_type.as_str(),
+        "occurred_at": Utc::now(),
+        "data": data,
+    });
+
+    NewWebhookEvent {
+        id,
+        event_type,
+        payload: payload.to_string(),
+    }
+}
+
+/// Delay before the retry following `attempts` failed attempts.
+pub(crate) fn backoff(settings: &WebhookSettings, attempts: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+
+    settings.retry_base.saturating_mul(factor).min(settings.retry_max)
+}
+
+/// Value of the signature header: the timestamp and an HMAC-SHA256 over
+/// `"{timestamp}.{payload}"`.
+pub(crate) fn signature(secret: &str, timestamp: i64, payload: &str) -> String {
+    let signature = hmac::sign(secret.as_bytes(), format!("{}.{}", timestamp, payload).as_bytes());
+
+    format!("t={},v1={}", timestamp, signature)
+}
+
+/// POST a delivery, returning the response status or a description of why
+/// it could not be sent.
+pub(crate) async fn send(client: &reqwest::Client, pending: &PendingWebhookDelivery) -> Result<u16, (Option<u16>, String)> {
+    let response = client
+        .post(&pending.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, pending.delivery.event_type.as_str())
+        .header(DELIVERY_HEADER, pending.delivery.id.to_string())
+        .header(SIGNATURE_HEADER, signature(&pending.secret, Utc::now().timestamp(), &pending.payload))
+        .body(pending.payload.clone())
+        .send()
+        .await
+        .map_err(|err| (None, err.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err((Some(status.as_u16()), format!("Receiver responded {}", status)))
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct WebhookService {
+    repository: Arc<Repository>,
+    webhook_adapter: ArcBox<dyn WebhookAdapter>,
+    client: reqwest::Client,
+    settings: WebhookSettings,
+}
+
+impl WebhookService {
+    pub(crate) fn new(repository_adapters: Arc<RepositoryAdapters>, settings: WebhookSettings) -> Self {
+        let client = reqwest::Client::builder().timeout(settings.timeout).build().unwrap_or_default();
+
+        Self {
+            repository: repository_adapters.repository.clone(),
+            webhook_adapter: repository_adapters.webhook_adapter.clone(),
+            client,
+            settings,
+        }
+    }
+
+    fn attempt(&self, delivery: &WebhookDelivery, result: Result<u16, (Option<u16>, String)>) -> WebhookAttempt {
+        match result {
+            Ok(status) => WebhookAttempt {
+                response_status: Some(status as i32),
+                error: None,
+                next_attempt_at: None,
+                succeeded: true,
+            },
+            Err((status, error)) => {
+                let attempts = delivery.attempts as u32 + 1;
+                let next_attempt_at: Option<DateTime<Utc>> = if attempts < self.settings.max_attempts {
+                    chrono::Duration::from_std(backoff(&self.settings, attempts))
+                        .ok()
+                        .and_then(|delay| Utc::now().checked_add_signed(delay))
+                } else {
+                    None
+                };
+
+                WebhookAttempt {
+                    response_status: status.map(|status| status as i32),
+                    error: Some(error),
+                    next_attempt_at,
+                    succeeded: false,
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl WebhookApi for WebhookService {
+    #[tracing::instrument(level = "trace", skip(self, secret))]
+    async fn add_endpoint(&self, url: &str, events: &[WebhookEventType], secret: Option<String>) -> Result<WebhookEndpoint, Error> {
+        match reqwest::Url::parse(url) {
+            Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {}
+            _ => return Err(Error::Message(format!("Invalid webhook URL - {}", url))),
+        }
+
+        let endpoint = NewWebhookEndpoint {
+            url: url.to_string(),
+            secret: secret.unwrap_or_else(|| hmac::random_token(32)),
+            events: events.to_vec(),
+        };
+
+        let result: Result<WebhookEndpoint, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.webhook_adapter.clone();
+                let endpoint = endpoint.clone();
+                Box::pin(async move { adapter.add_endpoint(tx, &endpoint).await })
+            })
+            .await;
+
+        match result {
+            Ok(endpoint) => Ok(endpoint),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn list_endpoints(&self) -> Result<Vec<WebhookEndpoint>, Error> {
+        let result: Result<Vec<WebhookEndpoint>, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.webhook_adapter.clone();
+                Box::pin(async move { adapter.list_endpoints(tx).await })
+            })
+            .await;
+
+        match result {
+            Ok(endpoints) => Ok(endpoints),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn delete_endpoint(&self, id: i64) -> Result<(), Error> {
+        let result: Result<(), auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.webhook_adapter.clone();
+                Box::pin(async move { adapter.delete_endpoint(tx, id).await })
+            })
+            .await;
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(auth_db::Error::NotFound) => Err(Error::NotFound),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn list_deliveries(&self, endpoint_id: i64, offset: u64, limit: u64) -> Result<Vec<WebhookDelivery>, Error> {
+        let result: Result<Vec<WebhookDelivery>, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.webhook_adapter.clone();
+                Box::pin(async move { adapter.list_deliveries(tx, endpoint_id, offset, limit).await })
+            })
+            .await;
+
+        match result {
+            Ok(deliveries) => Ok(deliveries),
+            Err(err) => Err(Error::DatabaseError(err)),
+        }
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn deliver_due(&self) -> Result<usize, Error> {
+        let now = Utc::now();
+        // Hold claimed deliveries for long enough to send them all.
+        let lease = chrono::Duration::from_std(self.settings.timeout.saturating_mul(CLAIM_BATCH_SIZE as u32)).unwrap_or(chrono::Duration::hours(1));
+        let lease_until = now + lease;
+
+        let result: Result<Vec<PendingWebhookDelivery>, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.webhook_adapter.clone();
+                Box::pin(async move { adapter.claim_due_deliveries(tx, now, lease_until, CLAIM_BATCH_SIZE).await })
+            })
+            .await;
+        let pending = result.map_err(Error::DatabaseError)?;
+
+        for delivery in pending.iter() {
+            let result = send(&self.client, delivery).await;
+            if let Err((_, ref error)) = result {
+                tracing::debug!("Webhook delivery {} to {} failed: {}", delivery.delivery.id, delivery.url, error);
+            }
+
+            let attempt = self.attempt(&delivery.delivery, result);
+            let delivery_id = delivery.delivery.id;
+
+            let result: Result<WebhookDelivery, auth_db::Error> = self
+                .repository
+                .transaction(|tx| {
+                    let adapter = self.webhook_adapter.clone();
+                    let attempt = attempt.clone();
+                    Box::pin(async move { adapter.record_attempt(tx, delivery_id, &attempt).await })
+                })
+                .await;
+            if let Err(err) = result {
+                tracing::warn!("Failed to record webhook delivery {}: {}", delivery_id, err);
+            }
+        }
+
+        Ok(pending.len())
+    }
+
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn prune_events(&self) -> Result<u64, Error> {
+        let before = Utc::now() - chrono::Duration::from_std(self.settings.retention).unwrap_or(chrono::Duration::MAX);
+
+        let result: Result<u64, auth_db::Error> = self
+            .repository
+            .transaction(|tx| {
+                let adapter = self.webhook_adapter.clone();
+                Box::pin(async move { adapter.prune_events(tx, before).await })
+            })
+            .await;
+
+        result.map_err(Error::DatabaseError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use auth_domain_models::webhook::{PendingWebhookDelivery, WebhookDelivery, WebhookDeliveryStatus, WebhookEventType};
+    use auth_utils::hmac;
+    use chrono::Utc;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::oneshot,
+    };
+    use uuid::Uuid;
+
+    use super::{backoff, send, WebhookSettings, SIGNATURE_HEADER};
+
+    /// Accept a single request, answer it with `status` and hand back the raw
+    /// request headers and body.
+    async fn receiver(status: u16) -> (String, oneshot::Receiver<(String, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.to_lowercase().strip_prefix("content-length: ").map(|v| v.trim().parse::<usize>().unwrap()))
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        let response = format!("HTTP/1.1 {} Status\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                        socket.write_all(response.as_bytes()).await.unwrap();
+                        let _ = tx.send((head.to_lowercase(), body.to_string()));
+                        break;
+                    }
+                }
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn pending(url: String) -> PendingWebhookDelivery {
+        PendingWebhookDelivery {
+            delivery: WebhookDelivery {
+                id: 1,
+                endpoint_id: 1,
+                event_id: Uuid::new_v4(),
+                event_type: WebhookEventType::UserRegistered,
+                status: WebhookDeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt_at: Utc::now(),
+                last_attempt_at: None,
+                last_response_status: None,
+                last_error: None,
+                created_at: Utc::now(),
+            },
+            url,
+            secret: "secret".to_string(),
+            payload: r#"{"type":"user.registered"}"#.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_payload() {
+        let (url, rx) = receiver(204).await;
+        let client = reqwest::Client::new();
+
+        assert_eq!(send(&client, &pending(url)).await, Ok(204));
+
+        let (head, body) = rx.await.unwrap();
+        assert_eq!(body, r#"{"type":"user.registered"}"#);
+        assert!(head.contains("x-auth-play-event: user.registered"));
+
+        let header = head.lines().find_map(|line| line.strip_prefix(&format!("{}: ", SIGNATURE_HEADER))).unwrap();
+        let (timestamp, signature) = header.split_once(",v1=").unwrap();
+        let timestamp = timestamp.strip_prefix("t=").unwrap();
+        assert!(hmac::verify(b"secret", format!("{}.{}", timestamp, body).as_bytes(), signature));
+    }
+
+    #[tokio::test]
+    async fn reports_failed_delivery() {
+        let (url, _rx) = receiver(503).await;
+        let client = reqwest::Client::new();
+
+        let (status, _) = send(&client, &pending(url)).await.unwrap_err();
+        assert_eq!(status, Some(503));
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let settings = WebhookSettings {
+            max_attempts: 10,
+            retry_base: Duration::from_secs(30),
+            retry_max: Duration::from_secs(600),
+            timeout: Duration::from_secs(10),
+            retention: Duration::from_secs(60),
+        };
+
+        assert_eq!(backoff(&settings, 1), Duration::from_secs(30));
+        assert_eq!(backoff(&settings, 2), Duration::from_secs(60));
+        assert_eq!(backoff(&settings, 4), Duration::from_secs(240));
+        assert_eq!(backoff(&settings, 6), Duration::from_secs(600));
+        assert_eq!(backoff(&settings, 40), Duration::from_secs(600));
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) mod audit;
+pub(crate) mod cache;
+pub(crate) mod webhook;
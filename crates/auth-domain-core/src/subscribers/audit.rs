@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use auth_domain_api::AuditApi;
+use auth_domain_models::audit::{AuditEventKind, AuditOutcome, NewAuditEvent, RequestContext};
+use auth_utils::arcbox::ArcBox;
+
+use crate::events::{DomainEvent, EventSubscriber};
+
+/// Writes every domain event to the audit log.
+pub(crate) struct AuditSubscriber {
+    audit_api: ArcBox<dyn AuditApi>,
+}
+
+impl AuditSubscriber {
+    pub(crate) fn new(audit_api: ArcBox<dyn AuditApi>) -> Self {
+        Self { audit_api }
+    }
+}
+
+fn audit_event(event: &DomainEvent) -> NewAuditEvent {
+    let none = RequestContext::default();
+
+    match event {
+        DomainEvent::UserRegistered { user, context } => {
+            NewAuditEvent::new(AuditEventKind::Register, AuditOutcome::Success, context).with_actor(Some(user.id), Some(&user.email))
+        }
+        DomainEvent::RegistrationFailed { email, reason, context } => NewAuditEvent::new(AuditEventKind::Register, AuditOutcome::Failure, context)
+            .with_actor(None, Some(email))
+            .with_detail(reason.clone()),
+        DomainEvent::UserLoggedIn { user, context } => {
+            NewAuditEvent::new(AuditEventKind::Login, AuditOutcome::Success, context).with_actor(Some(user.id), Some(&user.email))
+        }
+        DomainEvent::LoginFailed { email, reason, context } => NewAuditEvent::new(AuditEventKind::Login, AuditOutcome::Failure, context)
+            .with_actor(None, Some(email))
+            .with_detail(reason.clone()),
+        DomainEvent::UserLoggedOut { user_id, context } => {
+            NewAuditEvent::new(AuditEventKind::Logout, AuditOutcome::Success, context).with_actor(Some(*user_id), None)
+        }
+        DomainEvent::SessionCreated { session_id, user_id, context } => NewAuditEvent::new(AuditEventKind::SessionCreated, AuditOutcome::Success, context)
+            .with_actor(*user_id, None)
+            .with_detail(session_id.to_string()),
+        DomainEvent::SessionRevoked { session_id, user_id, context } => NewAuditEvent::new(AuditEventKind::SessionDeleted, AuditOutcome::Success, context)
+            .with_actor(*user_id, None)
+            .with_detail(session_id.to_string()),
+        DomainEvent::SessionRotated {
+            old_session_id,
+            session_id,
+            user_id,
+            context,
+        } => NewAuditEvent::new(AuditEventKind::SessionRotated, AuditOutcome::Success, context)
+            .with_actor(*user_id, None)
+            .with_detail(format!("{} -> {}", old_session_id, session_id)),
+        DomainEvent::UserSessionsRevoked { user_id, context } => {
+            NewAuditEvent::new(AuditEventKind::SessionsRevoked, AuditOutcome::Success, context).with_actor(Some(*user_id), None)
+        }
+        DomainEvent::PasswordChanged { user, context } => {
+            NewAuditEvent::new(AuditEventKind::PasswordChanged, AuditOutcome::Success, context).with_actor(Some(user.id), Some(&user.email))
+        }
+        DomainEvent::PasswordChangeFailed { user_id, reason, context } => NewAuditEvent::new(AuditEventKind::PasswordChanged, AuditOutcome::Failure, context)
+            .with_actor(Some(*user_id), None)
+            .with_detail(reason.clone()),
+        DomainEvent::AccountDeletionScheduled { user, context } => {
+            NewAuditEvent::new(AuditEventKind::AccountDeletionScheduled, AuditOutcome::Success, context).with_actor(Some(user.id), Some(&user.email))
+        }
+        DomainEvent::AccountDeletionCancelled { user, context } => {
+            NewAuditEvent::new(AuditEventKind::AccountDeletionCancelled, AuditOutcome::Success, context).with_actor(Some(user.id), Some(&user.email))
+        }
+        DomainEvent::AccountPurged { user_id } => {
+            NewAuditEvent::new(AuditEventKind::AccountPurged, AuditOutcome::Success, &none).with_actor(Some(*user_id), None)
+        }
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for AuditSubscriber {
+    fn name(&self) -> &'static str {
+        "audit"
+    }
+
+    async fn handle(&self, event: &DomainEvent) {
+        self.audit_api.record(audit_event(event)).await;
+    }
+}
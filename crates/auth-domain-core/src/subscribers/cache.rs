@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use auth_db::DatabaseTransaction;
+
+use crate::{
+    cache::{Caches, Invalidation},
+    events::{DomainEvent, EventSubscriber},
+};
+
+/// Drops cached users and sessions once a change to them is committed, here
+/// and, through the broadcast made with the change, on other instances.
+pub(crate) struct CacheSubscriber {
+    caches: Arc<Caches>,
+}
+
+impl CacheSubscriber {
+    pub(crate) fn new(caches: Arc<Caches>) -> Self {
+        Self { caches }
+    }
+}
+
+fn invalidations(event: &DomainEvent) -> Vec<Invalidation> {
+    match event {
+        DomainEvent::PasswordChanged { user, .. } | DomainEvent::AccountDeletionCancelled { user, .. } => vec![Invalidation::User(user.id)],
+        DomainEvent::UserLoggedOut { user_id, .. } => vec![Invalidation::User(*user_id)],
+        // Deleting an account deletes its sessions.
+        DomainEvent::AccountDeletionScheduled { user, .. } => vec![Invalidation::User(user.id), Invalidation::UserSessions(user.id)],
+        DomainEvent::AccountPurged { user_id } => vec![Invalidation::User(*user_id), Invalidation::UserSessions(*user_id)],
+        DomainEvent::UserSessionsRevoked { user_id, .. } => vec![Invalidation::UserSessions(*user_id)],
+        DomainEvent::SessionRevoked { session_id, .. } => vec![Invalidation::Session(*session_id)],
+        DomainEvent::SessionRotated { old_session_id, .. } => vec![Invalidation::Session(*old_session_id)],
+        _ => Vec::new(),
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for CacheSubscriber {
+    fn name(&self) -> &'static str {
+        "cache"
+    }
+
+    async fn handle_in_transaction(&self, tx: &mut DatabaseTransaction, event: &DomainEvent) -> Result<(), auth_db::Error> {
+        self.caches.broadcast(tx, invalidations(event)).await
+    }
+
+    async fn handle(&self, event: &DomainEvent) {
+        for invalidation in invalidations(event) {
+            self.caches.invalidate(invalidation).await;
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use auth_db::{adapters::WebhookAdapter, DatabaseTransaction, RepositoryAdapters};
+use auth_domain_models::webhook::WebhookEventType;
+use auth_utils::arcbox::ArcBox;
+use serde_json::json;
+
+use crate::{
+    events::{DomainEvent, EventSubscriber},
+    webhook::webhook_event,
+};
+
+/// Writes webhook events to the outbox, in the same transaction as the change
+/// they describe.
+pub(crate) struct WebhookSubscriber {
+    webhook_adapter: ArcBox<dyn WebhookAdapter>,
+}
+
+impl WebhookSubscriber {
+    pub(crate) fn new(repository_adapters: Arc<RepositoryAdapters>) -> Self {
+        Self {
+            webhook_adapter: repository_adapters.webhook_adapter.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl EventSubscriber for WebhookSubscriber {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn handle_in_transaction(&self, tx: &mut DatabaseTransaction, event: &DomainEvent) -> Result<(), auth_db::Error> {
+        let event = match event {
+            DomainEvent::UserRegistered { user, .. } => webhook_event(
+                WebhookEventType::UserRegistered,
+                json!({ "user_id": user.id, "name": user.name, "email": user.email }),
+            ),
+            DomainEvent::AccountDeletionScheduled { user, .. } => webhook_event(
+                WebhookEventType::UserDeletionScheduled,
+                json!({ "user_id": user.id, "purge_at": user.purge_at }),
+            ),
+            DomainEvent::AccountPurged { user_id } => webhook_event(WebhookEventType::UserDeleted, json!({ "user_id": user_id })),
+            _ => return Ok(()),
+        };
+
+        self.webhook_adapter.enqueue_event(tx, &event).await?;
+
+        Ok(())
+    }
+}
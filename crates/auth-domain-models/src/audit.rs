@@ -0,0 +1,204 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEventKind {
+    Register,
+    Login,
+    Logout,
+    SessionCreated,
+    SessionDeleted,
+    SessionRotated,
+    SessionsRevoked,
+    SessionDrift,
+    PasswordChanged,
+    AccountDeletionScheduled,
+    AccountDeletionCancelled,
+    AccountPurged,
+}
+
+impl AuditEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventKind::Register => "register",
+            AuditEventKind::Login => "login",
+            AuditEventKind::Logout => "logout",
+            AuditEventKind::SessionCreated => "session_created",
+            AuditEventKind::SessionDeleted => "session_deleted",
+            AuditEventKind::SessionRotated => "session_rotated",
+            AuditEventKind::SessionsRevoked => "sessions_revoked",
+            AuditEventKind::SessionDrift => "session_drift",
+            AuditEventKind::PasswordChanged => "password_changed",
+            AuditEventKind::AccountDeletionScheduled => "account_deletion_scheduled",
+            AuditEventKind::AccountDeletionCancelled => "account_deletion_cancelled",
+            AuditEventKind::AccountPurged => "account_purged",
+        }
+    }
+}
+
+impl fmt::Display for AuditEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "register" => Ok(AuditEventKind::Register),
+            "login" => Ok(AuditEventKind::Login),
+            "logout" => Ok(AuditEventKind::Logout),
+            "session_created" => Ok(AuditEventKind::SessionCreated),
+            "session_deleted" => Ok(AuditEventKind::SessionDeleted),
+            "session_rotated" => Ok(AuditEventKind::SessionRotated),
+            "sessions_revoked" => Ok(AuditEventKind::SessionsRevoked),
+            "session_drift" => Ok(AuditEventKind::SessionDrift),
+            "password_changed" => Ok(AuditEventKind::PasswordChanged),
+            "account_deletion_scheduled" => Ok(AuditEventKind::AccountDeletionScheduled),
+            "account_deletion_cancelled" => Ok(AuditEventKind::AccountDeletionCancelled),
+            "account_purged" => Ok(AuditEventKind::AccountPurged),
+            _ => Err(format!("Unknown audit event kind - {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+        }
+    }
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "success" => Ok(AuditOutcome::Success),
+            "failure" => Ok(AuditOutcome::Failure),
+            _ => Err(format!("Unknown audit outcome - {}", s)),
+        }
+    }
+}
+
+/// Where a request came from, as seen by the API layer.
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    pub kind: AuditEventKind,
+    pub outcome: AuditOutcome,
+    pub actor_id: Option<i64>,
+    pub actor_email: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+impl NewAuditEvent {
+    pub fn new(kind: AuditEventKind, outcome: AuditOutcome, context: &RequestContext) -> Self {
+        Self {
+            kind,
+            outcome,
+            actor_id: None,
+            actor_email: None,
+            ip: context.ip.clone(),
+            user_agent: context.user_agent.clone(),
+            detail: None,
+        }
+    }
+
+    pub fn with_actor(mut self, actor_id: Option<i64>, actor_email: Option<&str>) -> Self {
+        self.actor_id = actor_id;
+        self.actor_email = actor_email.map(str::to_string);
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub kind: AuditEventKind,
+    pub outcome: AuditOutcome,
+    pub actor_id: Option<i64>,
+    pub actor_email: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub kind: Option<AuditEventKind>,
+    pub outcome: Option<AuditOutcome>,
+    pub actor_id: Option<i64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub offset: u64,
+    pub limit: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditPage {
+    pub events: Vec<AuditEvent>,
+    pub total: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AuditEventKind, AuditOutcome};
+
+    #[test]
+    fn kind_round_trip() {
+        let kinds = [
+            AuditEventKind::Register,
+            AuditEventKind::Login,
+            AuditEventKind::Logout,
+            AuditEventKind::SessionCreated,
+            AuditEventKind::SessionDeleted,
+            AuditEventKind::SessionRotated,
+            AuditEventKind::SessionsRevoked,
+            AuditEventKind::SessionDrift,
+            AuditEventKind::PasswordChanged,
+            AuditEventKind::AccountDeletionScheduled,
+            AuditEventKind::AccountDeletionCancelled,
+            AuditEventKind::AccountPurged,
+        ];
+        for kind in kinds {
+            assert_eq!(kind, kind.as_str().parse().unwrap());
+        }
+
+        for outcome in [AuditOutcome::Success, AuditOutcome::Failure] {
+            assert_eq!(outcome, outcome.as_str().parse().unwrap());
+        }
+
+        assert!("bogus".parse::<AuditEventKind>().is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
+pub const ADMIN_ROLE: &str = "admin";
+
 #[derive(Debug, Clone)]
 pub struct NewUser {
     pub name: String,
@@ -14,12 +16,16 @@ pub struct User {
     pub name: String,
     pub email: String,
     pub password_sha: String,
+    pub roles: Vec<String>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub purge_at: Option<DateTime<Utc>>,
 }
 
 pub type SessionId = Uuid;
 
 #[derive(Debug, Clone)]
 pub struct NewSession {
+    pub user_id: Option<i64>,
     pub data: Vec<u8>,
     pub expiry: DateTime<Utc>,
 }
@@ -27,6 +33,17 @@ pub struct NewSession {
 #[derive(Debug, Clone)]
 pub struct Session {
     pub id: SessionId,
+    pub user_id: Option<i64>,
     pub data: Vec<u8>,
     pub expiry: DateTime<Utc>,
 }
+
+/// A revoked session, or with no session id, all sessions of a user started
+/// before `revoked_at`. Kept until the sessions it revokes have expired.
+#[derive(Debug, Clone)]
+pub struct SessionRevocation {
+    pub session_id: Option<SessionId>,
+    pub user_id: Option<i64>,
+    pub revoked_at: DateTime<Utc>,
+    pub expiry: DateTime<Utc>,
+}
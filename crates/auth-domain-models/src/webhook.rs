@@ -0,0 +1,133 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEventType {
+    UserRegistered,
+    UserDeletionScheduled,
+    UserDeleted,
+}
+
+impl WebhookEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventType::UserRegistered => "user.registered",
+            WebhookEventType::UserDeletionScheduled => "user.deletion_scheduled",
+            WebhookEventType::UserDeleted => "user.deleted",
+        }
+    }
+}
+
+impl fmt::Display for WebhookEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WebhookEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user.registered" => Ok(WebhookEventType::UserRegistered),
+            "user.deletion_scheduled" => Ok(WebhookEventType::UserDeletionScheduled),
+            "user.deleted" => Ok(WebhookEventType::UserDeleted),
+            _ => Err(format!("Unknown webhook event type - {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+impl WebhookDeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookDeliveryStatus::Pending => "pending",
+            WebhookDeliveryStatus::Succeeded => "succeeded",
+            WebhookDeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for WebhookDeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WebhookDeliveryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(WebhookDeliveryStatus::Pending),
+            "succeeded" => Ok(WebhookDeliveryStatus::Succeeded),
+            "failed" => Ok(WebhookDeliveryStatus::Failed),
+            _ => Err(format!("Unknown webhook delivery status - {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewWebhookEndpoint {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEventType>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookEndpoint {
+    pub id: i64,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEventType>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An event written to the outbox alongside the change that caused it.
+#[derive(Debug, Clone)]
+pub struct NewWebhookEvent {
+    pub id: Uuid,
+    pub event_type: WebhookEventType,
+    pub payload: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub endpoint_id: i64,
+    pub event_id: Uuid,
+    pub event_type: WebhookEventType,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub last_response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A delivery that is due, together with what is needed to send it.
+#[derive(Debug, Clone)]
+pub struct PendingWebhookDelivery {
+    pub delivery: WebhookDelivery,
+    pub url: String,
+    pub secret: String,
+    pub payload: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookAttempt {
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+    /// When to try again, or `None` if the delivery is finished.
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub succeeded: bool,
+}
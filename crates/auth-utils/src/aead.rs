@@ -0,0 +1,55 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand_core::OsRng;
+
+/// Length of the keys taken by [`seal`] and [`open`].
+pub const KEY_LEN: usize = 32;
+
+const NONCE_LEN: usize = 24;
+
+/// XChaCha20-Poly1305 encryption of `plaintext` under a random nonce, which
+/// is prepended to the ciphertext. `aad` is authenticated but not encrypted.
+pub fn seal(key: &[u8; KEY_LEN], plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .expect("XChaCha20-Poly1305 encrypts messages of any practical length");
+
+    [nonce.as_slice(), &ciphertext].concat()
+}
+
+/// Decrypt what [`seal`] produced. `None` when it was not sealed with this
+/// key and `aad`, or has been tampered with.
+pub fn open(key: &[u8; KEY_LEN], sealed: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(key.into());
+
+    cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad }).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{open, seal};
+
+    #[test]
+    fn seal_open() {
+        let key = [7u8; 32];
+        let sealed = seal(&key, b"session", b"k1");
+        assert_ne!(sealed, seal(&key, b"session", b"k1"));
+
+        assert_eq!(open(&key, &sealed, b"k1").as_deref(), Some(&b"session"[..]));
+        assert_eq!(open(&key, &sealed, b"k2"), None);
+        assert_eq!(open(&[8u8; 32], &sealed, b"k1"), None);
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(open(&key, &tampered, b"k1"), None);
+        assert_eq!(open(&key, b"short", b"k1"), None);
+    }
+}
@@ -0,0 +1,62 @@
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Hex-encoded HMAC-SHA256 of `message` keyed with `secret`.
+pub fn sign(secret: &[u8], message: &[u8]) -> String {
+    base16::encode_lower(&sign_bytes(secret, message))
+}
+
+/// Raw HMAC-SHA256 of `message` keyed with `secret`.
+pub fn sign_bytes(secret: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(message);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Constant-time check of a hex-encoded HMAC-SHA256 signature.
+pub fn verify(secret: &[u8], message: &[u8], signature: &str) -> bool {
+    let Ok(signature) = base16::decode(signature.as_bytes()) else {
+        return false;
+    };
+
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(message);
+
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Compare two tokens in time independent of where they differ.
+pub fn tokens_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len() && expected.bytes().zip(provided.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Hex-encoded random token of `len` bytes from the OS CSPRNG.
+pub fn random_token(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    OsRng.fill_bytes(&mut bytes);
+
+    base16::encode_lower(&bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{random_token, sign, verify};
+
+    #[test]
+    fn sign_verify() {
+        // RFC 4231 test case 2.
+        let signature = sign(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(signature, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+
+        assert!(verify(b"Jefe", b"what do ya want for nothing?", &signature));
+        assert!(!verify(b"Jefe", b"what do ya want for something?", &signature));
+        assert!(!verify(b"Jefe", b"what do ya want for nothing?", "zz"));
+
+        assert_eq!(random_token(16).len(), 32);
+        assert_ne!(random_token(16), random_token(16));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod aead;
 pub mod arcbox;
 pub mod argon2;
+pub mod hmac;
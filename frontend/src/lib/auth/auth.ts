@@ -7,11 +7,19 @@ type UserInfo = {
 
 export const userInfo = writable<UserInfo>({ email: undefined, name: undefined });
 
+// Sent back in X-CSRF-Token with every state-changing request.
+let csrfToken: string | undefined = undefined;
+
+function csrfHeaders(): Record<string, string> {
+    return csrfToken === undefined ? {} : { 'X-CSRF-Token': csrfToken };
+}
+
 export async function getSession() {
     console.log('Fetching session');
     const res = await fetch('/auth/session', { credentials: 'same-origin' });
     let sessionResponse = await res.json();
     console.log('Response: ', sessionResponse);
+    csrfToken = sessionResponse.csrf_token;
     if (sessionResponse.email !== null) {
         userInfo.set({ email: sessionResponse.email, name: sessionResponse.name });
     } else {
@@ -19,25 +27,27 @@ export async function getSession() {
     }
 }
 
-export async function postLogin(email: string, password: string) {
+export async function postLogin(email: string, password: string, remember: boolean = false) {
     const res = await fetch('/auth/login', {
         method: 'POST',
         headers: {
             Accept: 'application/json',
-            'Content-Type': 'application/json'
+            'Content-Type': 'application/json',
+            ...csrfHeaders()
         },
-        body: JSON.stringify({ email: email, password: password })
+        body: JSON.stringify({ email: email, password: password, remember: remember })
     });
     return await res.json();
 }
 
-export async function getLogout() {
-    const res = await fetch('/auth/logout', {
-        method: 'GET',
+export async function postLogout() {
+    await fetch('/auth/logout', {
+        method: 'POST',
         headers: {
             Accept: 'application/json',
-            'Content-Type': 'application/json'
-        },
+            ...csrfHeaders()
+        }
     });
-    return await res.json();
+    // Logging out ends the session, and with it the token.
+    await getSession();
 }